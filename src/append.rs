@@ -0,0 +1,159 @@
+use std::fs;
+use crate::library::*;
+use crate::args::*;
+use crate::backend::*;
+use crate::error::*;
+use crate::create::{get_pi,tar_compress_cmd};
+use crate::extract::decompress_in_tmp;
+use crate::message::text;
+
+pub fn append(mut d:AppendData) -> Result<()> {
+
+	// アーカイブの種類を判定
+	let arc_type = match d.arc_type {
+		Some(t) => t,
+		None => guess_existing_type(&d.output)?
+	};
+
+	// アーカイブが存在することを確認
+	if !Path::new(&d.output).is_file() {
+		return Err(ArchiverError::NotFound(msg!("archive-not-found",d.output)));
+	}
+
+	// 入力ファイルの String を Path に変換
+	let ip = d.input.iter().map(Path::new).collect::<Vec<_>>();
+
+	// ファイルが全て存在することを確認
+	for (p,i) in izip!(ip.iter(),d.input.iter()) {
+		if !p.exists() {
+			return Err(ArchiverError::NotFound(msg!("file-not-found",i)));
+		}
+	}
+
+	// パラメータの条件を確認
+	d.level = rewrite_rate(&d.output,d.rate,&arc_type)?;
+
+	// 実装を選択して実行
+	let backend = select_backend(&arc_type,&d.backend,Operation::Append)?;
+	return backend.append(&d,&arc_type).map_err(|e| e.context(text("append-failed")) );
+
+}
+
+/// 外部コマンドによる追加
+pub fn append_external(d:&AppendData,arc_type:&ArcType) -> Result<()> {
+
+	let ip = d.input.iter().map(Path::new).collect::<Vec<_>>();
+	let tmp = work_dir()?;
+
+	// 元のアーカイブを壊さないよう、作業ディレクトリに複製したものに追加する
+	let mut archive = match arc_type {
+		ArcType::Zip    => tmp.join_str("archive.zip"),
+		ArcType::SevenZ => tmp.join_str("archive.7z"),
+		ArcType::Rar    => tmp.join_str("archive.rar"),
+		ArcType::Lha    => tmp.join_str("archive.lzh"),
+		ArcType::Zpaq   => tmp.join_str("archive.zpaq"),
+		ArcType::Tar    => tmp.join_str("archive.tar"),
+		at if is_compress(at) => tmp.join_str(format!("file.{}",compress_ext(at)?)),
+		_ => {
+			return Err(ArchiverError::Unsupported(msg!("unsupported-append")));
+		}
+	};
+	if let Err(e) = fs::copy(&d.output,&archive) {
+		return Err(ArchiverError::Failed(msg!("copy-failed",e)));
+	}
+
+	// コマンドを用意
+	let cl:Vec<Cmd> = match arc_type {
+		ArcType::Zip => {
+			get_pi(&ip,&d.keep_path,&None)?.into_iter().map(|(p,i)| {
+				// 作成時の既定の除外の条件 (default_excludes) と同じく、どの階層の隠しファイルも除外する
+				let mut c = Cmd::new_cwd("zip",vs(["-r","-x",".*","-x","*/.*","-x","__MACOSX","-x","__MACOSX/*","-x","*/__MACOSX","-x","*/__MACOSX/*"]),p);
+				if !d.verbose { c.args.push(OsString::from("-q")); }
+				c.args.extend([
+					OsString::from(format!("-{}",d.level)),
+					OsString::from(&archive),i
+				]);
+				c
+			}).collect()
+		},
+		ArcType::SevenZ => {
+			get_pi(&ip,&d.keep_path,&None)?.into_iter().map(|(p,i)| {
+				let mut c = Cmd::new_cwd("7z",vs(["a","-ba","-xr!.*","-xr!__MACOSX"]),p);
+				c.args.extend([
+					OsString::from(format!("-mx={}",d.level)),
+					OsString::from(&archive),i
+				]);
+				c.inherit_output = d.verbose;
+				c
+			}).collect()
+		},
+		ArcType::Rar => {
+			get_pi(&ip,&d.keep_path,&None)?.into_iter().map(|(p,i)| {
+				let mut c = Cmd::new_cwd("rar",vs(["a","-r"]),p);
+				if !d.verbose { c.args.push(OsString::from("-inul")); }
+				c.args.extend([
+					OsString::from(format!("-m{}",d.level)),
+					OsString::from(&archive),i
+				]);
+				c
+			}).collect()
+		},
+		ArcType::Lha => {
+			get_pi(&ip,&d.keep_path,&None)?.into_iter().map(|(p,i)| {
+				let mut c = Cmd::new_cwd("lha",vs(["-a"]),p);
+				if !d.verbose { c.args.push(OsString::from("-q")); }
+				c.args.extend([OsString::from(&archive),i]);
+				c
+			}).collect()
+		},
+		ArcType::Zpaq => {
+			get_pi(&ip,&d.keep_path,&None)?.into_iter().map(|(p,i)| {
+				let mut c = Cmd::new_cwd("zpaq",vs(["a"]),p);
+				c.args.extend([OsString::from(&archive),i]);
+				c
+			}).collect()
+		},
+		ArcType::Tar => tar_append(&ip,&d.keep_path,&archive)?,
+		at => {
+			// 解凍して tar アーカイブにする
+			let tar = decompress_in_tmp(at,&tmp)?;
+			let mut l = tar_append(&ip,&d.keep_path,&tar)?;
+
+			// 再圧縮する
+			let mut c = tar_compress_cmd(at,&d.level,"file")?;
+			c.cwd = tmp.path().to_path_buf();
+			l.push(c);
+			archive = tmp.join_str(format!("file.{}",compress_ext(at)?));
+			l
+		}
+	};
+
+	// コマンドを実行
+	several_cmd(cl)?;
+	if !Path::new(&archive).is_file() {
+		return Err(ArchiverError::Failed(msg!("append-nothing")));
+	}
+	if fs::rename(&archive,&d.output).is_err() {
+		if let Err(e) = fs::copy(&archive,&d.output) {
+			return Err(ArchiverError::Failed(msg!("save-failed",e)));
+		}
+	}
+
+	close_dir(tmp)?;
+	return Ok(());
+
+}
+
+/// tar アーカイブへの追加
+fn tar_append(ip:&Vec<&Path>,keep_path:&bool,archive:&OsString) -> Result<Vec<Cmd>> {
+	Ok(get_pi(ip,keep_path,&None)?.into_iter().map(|(p,i)| {
+		let mut c = Cmd::new_cwd_env(
+			"bsdtar",
+			vs(["-r","-f"]),
+			p,
+			[("COPYFILE_DISABLE","1")]
+		);
+		c.args.extend([OsString::from(archive),i]);
+		c
+	}).collect())
+}
@@ -1,82 +1,301 @@
-pub use clap::{Parser,Args,Subcommand,ArgEnum};
-
-#[derive(Parser)]
-/// アーカイブコマンドのラッパー
-pub struct Arguments {
-	#[clap(subcommand)]
-	pub command: ArcCmd
-}
-
-#[derive(Subcommand)]
-pub enum ArcCmd {
-	/// 新しいアーカイブを作成します。単一ファイルの圧縮やディスクイメージの作成にも対応しています。
-	#[clap(name="create",aliases=&["c","archive"])]
-	Create(CreateData),
-	/// アーカイブにファイルを追加します。
-	Append,
-	/// アーカイブからファイルを削除します。
-	Remove,
-	/// アーカイブ内のファイル名を変更します。
-	Rename,
-	/// アーカイブを展開します。
-	Extract(ExtractData),
-	/// アーカイブの内容を表示します。
-	List(ListData),
-	/// このコマンドで使用するパッケージを全てインストールします
-	Setup,
-	/// ヘルプを表示します。
-	Help
-}
+pub use clap::{Parser,Args,ArgEnum};
+use crate::message::text;
+
+// ヘルプの文章は言語ごとに切り替えるため、 message.rs の一覧に ID で記述する
 
 #[derive(Args)]
 pub struct CreateData {
-	/// アーカイブに追加するファイルを指定します。指定しない場合は、空のアーカイブが作成されることがあります。
+	#[clap(help=text("help.create.input"))]
 	pub input: Vec<String>,
-	#[clap(short,long)]
-	/// 出力先となるアーカイブを指定します。
+	#[clap(short,long,help=text("help.create.output"))]
 	pub output: String,
-	#[clap(short='t',long="type",arg_enum)]
-	/// アーカイブの種類を変更します。標準では出力ファイルの拡張子から判定します。圧縮系のフォーマットは複数の入力ファイルに対して自動的に tar アーカイブにした上で圧縮します。
+	#[clap(short='t',long="type",arg_enum,help=text("help.create.arc-type"))]
 	pub arc_type: Option<ArcType>,
-	#[clap(short,long,default_value_t=6)]
-	/// 圧縮を伴うアーカイブにおいて圧縮率を指定します。
+	#[clap(long,arg_enum,help=text("help.backend"))]
+	pub backend: Option<BackendType>,
+	#[clap(short,long,default_value_t=6,help=text("help.create.rate"))]
 	pub rate: u8,
-	/// 進行状況などを出力します
-	#[clap(short,long)]
+	#[clap(short,long,help=text("help.verbose-files"))]
 	pub verbose: bool,
-	#[clap(long="image-name",default_value_t=String::from("Untitled"))]
-	/// DMG,ISO の場合にディスクの名前を指定します。フォルダから作成する場合は無視されます。
+	#[clap(long,arg_enum,default_value="auto",help=text("help.progress"))]
+	pub progress: ProgressMode,
+	#[clap(long,arg_enum,default_value="ask",help=text("help.create.overwrite"))]
+	pub overwrite: Overwrite,
+	#[clap(long="image-name",default_value_t=String::from("Untitled"),help=text("help.create.image-name"))]
 	pub image_name: String,
-	#[clap(long="keep-path")]
-	/// 追加するファイルのパスをアーカイブの階層構造に使用します。指定しない場合は追加するファイルをルート階層に配置します。
+	#[clap(long="keep-path",help=text("help.keep-path"))]
+	pub keep_path: bool,
+	#[clap(long,help=text("help.create.threads"))]
+	pub threads: Option<usize>,
+	#[clap(long,arg_enum,use_value_delimiter=true,help=text("help.create.preserve"))]
+	pub preserve: Vec<Preserve>,
+	#[clap(long="split-size",help=text("help.create.split-size"))]
+	pub split_size: Option<String>,
+	#[clap(long,conflicts_with="preserve",help=text("help.create.reproducible"))]
+	pub reproducible: bool,
+	#[clap(flatten)]
+	pub filter: FilterData,
+	#[clap(flatten)]
+	pub pw: PasswordData,
+	/// --keep-path のアーカイブ内のパスの基準 (この中の入力ファイルは相対パスにする、 None はカレントディレクトリ)
+	#[clap(skip)]
+	pub base: Option<std::path::PathBuf>
+}
+
+#[derive(Args)]
+pub struct AppendData {
+	#[clap(required=true,help=text("help.append.input"))]
+	pub input: Vec<String>,
+	#[clap(short,long,help=text("help.append.output"))]
+	pub output: String,
+	#[clap(short='t',long="type",arg_enum,help=text("help.arc-type-existing"))]
+	pub arc_type: Option<ArcType>,
+	#[clap(long,arg_enum,help=text("help.backend"))]
+	pub backend: Option<BackendType>,
+	#[clap(short,long,help=text("help.append.rate"))]
+	pub rate: Option<u8>,
+	/// 書き直す際に使う圧縮率 (rate または元の圧縮率を変換したもの)
+	#[clap(skip)]
+	pub level: u8,
+	#[clap(short,long,help=text("help.verbose"))]
+	pub verbose: bool,
+	#[clap(long="keep-path",help=text("help.keep-path"))]
 	pub keep_path: bool
 }
 
+#[derive(Args)]
+pub struct RemoveData {
+	#[clap(help=text("help.remove.input"))]
+	pub input: String,
+	#[clap(required=true,help=text("help.remove.target"))]
+	pub target: Vec<String>,
+	#[clap(short='t',long="type",arg_enum,help=text("help.arc-type-existing"))]
+	pub arc_type: Option<ArcType>,
+	#[clap(long,arg_enum,help=text("help.backend"))]
+	pub backend: Option<BackendType>,
+	#[clap(short,long,help=text("help.rate"))]
+	pub rate: Option<u8>,
+	/// 書き直す際に使う圧縮率 (rate または元の圧縮率を変換したもの)
+	#[clap(skip)]
+	pub level: u8,
+	#[clap(short,long,help=text("help.verbose"))]
+	pub verbose: bool
+}
+
+#[derive(Args)]
+pub struct RenameData {
+	#[clap(help=text("help.rename.input"))]
+	pub input: String,
+	#[clap(required_unless_present="map",requires="new",help=text("help.rename.old"))]
+	pub old: Option<String>,
+	#[clap(help=text("help.rename.new"))]
+	pub new: Option<String>,
+	#[clap(short,long,help=text("help.rename.map"))]
+	pub map: Option<String>,
+	#[clap(short='t',long="type",arg_enum,help=text("help.arc-type-existing"))]
+	pub arc_type: Option<ArcType>,
+	#[clap(short,long,help=text("help.rate"))]
+	pub rate: Option<u8>,
+	/// 書き直す際に使う圧縮率 (rate または元の圧縮率を変換したもの)
+	#[clap(skip)]
+	pub level: u8,
+	#[clap(short,long,help=text("help.verbose"))]
+	pub verbose: bool
+}
+
+#[derive(Args)]
+pub struct ConvertData {
+	#[clap(help=text("help.convert.input"))]
+	pub input: String,
+	#[clap(help=text("help.convert.output"))]
+	pub output: String,
+	#[clap(long,arg_enum,help=text("help.convert.from"))]
+	pub from: Option<ArcType>,
+	#[clap(short='t',long="type",arg_enum,help=text("help.convert.arc-type"))]
+	pub arc_type: Option<ArcType>,
+	#[clap(long,arg_enum,help=text("help.backend"))]
+	pub backend: Option<BackendType>,
+	#[clap(short,long,default_value_t=6,help=text("help.convert.rate"))]
+	pub rate: u8,
+	#[clap(long,arg_enum,default_value="ask",help=text("help.convert.overwrite"))]
+	pub overwrite: Overwrite,
+	#[clap(short,long,help=text("help.verbose"))]
+	pub verbose: bool
+}
+
 #[derive(Args)]
 pub struct ListData {
-	/// リスト表示するアーカイブを指定します。
+	#[clap(help=text("help.list.input"))]
 	pub input: String,
-	#[clap(short='t',long="type",arg_enum)]
-	/// アーカイブの種類を変更します。標準ではアーカイブの拡張子から判定します。
+	#[clap(short='t',long="type",arg_enum,help=text("help.arc-type"))]
 	pub arc_type: Option<ArcType>,
+	#[clap(long,arg_enum,help=text("help.backend"))]
+	pub backend: Option<BackendType>,
+	#[clap(short,long,arg_enum,default_value="table",help=text("help.list.format"))]
+	pub format: ListFormat,
+	#[clap(long,help=text("help.list.checksum"))]
+	pub checksum: bool,
+	#[clap(flatten)]
+	pub pw: PasswordData
+}
+
+#[derive(Args)]
+pub struct TestData {
+	#[clap(help=text("help.test.input"))]
+	pub input: String,
+	#[clap(short='t',long="type",arg_enum,help=text("help.arc-type"))]
+	pub arc_type: Option<ArcType>,
+	#[clap(long,arg_enum,help=text("help.backend"))]
+	pub backend: Option<BackendType>,
+	#[clap(short,long,help=text("help.test.verbose"))]
+	pub verbose: bool,
+	#[clap(flatten)]
+	pub pw: PasswordData
 }
 
 #[derive(Args)]
 pub struct ExtractData {
-	/// 展開するアーカイブファイルを指定します。
+	#[clap(help=text("help.extract.input"))]
 	pub input: String,
-	#[clap(short,long)]
-	/// アーカイブの展開先となるディレクトリを指定します。或いは、解凍した圧縮ファイルの保存先を指定します。指定しない場合は現在のディレクトリに展開/解凍されます。
+	#[clap(short,long,help=text("help.extract.output"))]
 	pub output: Option<String>,
-	/// 展開対象のファイルを指定します。
-	#[clap(short,long)]
+	#[clap(long,help=text("help.extract.target"))]
 	pub target: Vec<String>,
-	#[clap(short='t',long="type",arg_enum)]
-	/// アーカイブの種類を変更します。標準ではアーカイブファイルの拡張子から判定します。
+	#[clap(short='t',long="type",arg_enum,help=text("help.arc-type"))]
 	pub arc_type: Option<ArcType>,
+	#[clap(long,arg_enum,help=text("help.backend"))]
+	pub backend: Option<BackendType>,
+	#[clap(long="no-untar",help=text("help.extract.no-untar"))]
+	pub no_untar: bool,
+	#[clap(long,help=text("help.extract.smart"))]
+	pub smart: bool,
+	#[clap(long,arg_enum,use_value_delimiter=true,help=text("help.extract.preserve"))]
+	pub preserve: Vec<Preserve>,
+	#[clap(short,long,help=text("help.verbose-files"))]
+	pub verbose: bool,
+	#[clap(long,arg_enum,default_value="auto",help=text("help.progress"))]
+	pub progress: ProgressMode,
+	#[clap(long,arg_enum,default_value="ask",help=text("help.extract.overwrite"))]
+	pub overwrite: Overwrite,
+	#[clap(flatten)]
+	pub filter: FilterData,
+	#[clap(flatten)]
+	pub safety: SafetyData,
+	#[clap(flatten)]
+	pub pw: PasswordData
+}
+
+#[derive(Args,Clone,Default)]
+pub struct SafetyData {
+	#[clap(long="unsafe",help=text("help.safety.allow-unsafe"))]
+	pub allow_unsafe: bool,
+	#[clap(long="max-size",help=text("help.safety.max-size"))]
+	pub max_size: Option<String>,
+	#[clap(long="max-files",help=text("help.safety.max-files"))]
+	pub max_files: Option<u64>,
+	#[clap(long="max-ratio",help=text("help.safety.max-ratio"))]
+	pub max_ratio: Option<f64>
+}
+
+#[derive(Args,Clone,Default)]
+pub struct FilterData {
+	#[clap(long,help=text("help.filter.include"))]
+	pub include: Vec<String>,
+	#[clap(long,help=text("help.filter.exclude"))]
+	pub exclude: Vec<String>,
+	#[clap(long="exclude-from",help=text("help.filter.exclude-from"))]
+	pub exclude_from: Vec<String>,
+	#[clap(long="exclude-vcs",help=text("help.filter.exclude-vcs"))]
+	pub exclude_vcs: bool
+}
+
+#[derive(Args,Clone,Default)]
+pub struct PasswordData {
+	#[clap(long,help=text("help.password.password"))]
+	pub password: Option<String>,
+	#[clap(long="password-file",conflicts_with="password",help=text("help.password.password-file"))]
+	pub password_file: Option<String>,
+	#[clap(long="password-env",conflicts_with_all=&["password","password-file"],help=text("help.password.password-env"))]
+	pub password_env: Option<String>
+}
+
+#[derive(Args)]
+pub struct SetupData {
+	#[clap(short,long,help=text("help.setup.yes"))]
+	pub yes: bool
+}
+
+#[derive(ArgEnum,Clone,Copy,PartialEq)]
+pub enum ListFormat {
+	#[clap(name="table",help=text("value.format.table"))]
+	Table,
+	#[clap(name="json",help=text("value.format.json"))]
+	Json,
+	#[clap(name="ndjson",aliases=["jsonl"],help=text("value.format.ndjson"))]
+	Ndjson,
+	#[clap(name="csv",help=text("value.format.csv"))]
+	Csv
+}
+
+#[derive(ArgEnum,Clone,Copy,PartialEq)]
+pub enum Preserve {
+	#[clap(name="xattrs",help=text("value.preserve.xattrs"))]
+	Xattrs,
+	#[clap(name="acls",help=text("value.preserve.acls"))]
+	Acls,
+	#[clap(name="selinux",help=text("value.preserve.selinux"))]
+	Selinux,
+	#[clap(name="owner",help=text("value.preserve.owner"))]
+	Owner,
+	#[clap(name="sparse",help=text("value.preserve.sparse"))]
+	Sparse,
+	#[clap(name="hardlinks",help=text("value.preserve.hardlinks"))]
+	Hardlinks
+}
+
+#[derive(ArgEnum,Clone,Copy,PartialEq)]
+pub enum Overwrite {
+	#[clap(name="never",help=text("value.overwrite.never"))]
+	Never,
+	#[clap(name="always",help=text("value.overwrite.always"))]
+	Always,
+	#[clap(name="ask",help=text("value.overwrite.ask"))]
+	Ask,
+	#[clap(name="newer",help=text("value.overwrite.newer"))]
+	Newer,
+	#[clap(name="rename",help=text("value.overwrite.rename"))]
+	Rename
+}
+
+#[derive(ArgEnum,Clone,Copy,PartialEq)]
+pub enum ProgressMode {
+	#[clap(name="auto",help=text("value.progress.auto"))]
+	Auto,
+	#[clap(name="bar",help=text("value.progress.bar"))]
+	Bar,
+	#[clap(name="json",aliases=["ndjson"],help=text("value.progress.json"))]
+	Json,
+	#[clap(name="none",help=text("value.progress.none"))]
+	None
+}
+
+#[derive(ArgEnum,Clone,Copy,PartialEq)]
+pub enum Lang {
+	#[clap(name="ja",help=text("value.lang.ja"))]
+	Ja,
+	#[clap(name="en",help=text("value.lang.en"))]
+	En
+}
+
+#[derive(ArgEnum,Clone,Copy,PartialEq)]
+pub enum BackendType {
+	#[clap(name="native",help=text("value.backend.native"))]
+	Native,
+	#[clap(name="external",help=text("value.backend.external"))]
+	External
 }
 
-#[derive(ArgEnum,Clone)]
+#[derive(ArgEnum,Clone,Copy,PartialEq,Debug)]
 pub enum ArcType {
 	#[clap(name="zip")]
 	Zip,
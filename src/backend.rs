@@ -0,0 +1,147 @@
+use which::which;
+use crate::args::*;
+use crate::error::*;
+use crate::entry::Entry;
+use crate::create::create_external;
+use crate::list::list_external;
+use crate::test::test_external;
+use crate::extract::extract_external;
+use crate::append::append_external;
+use crate::remove::remove_external;
+use crate::native::{Native,native_supports};
+
+/// アーカイブの操作の種類 (setup で表示する順)
+#[derive(Clone,Copy,PartialEq)]
+pub enum Operation {
+	Create,
+	List,
+	Extract,
+	Append,
+	Remove,
+	Rename,
+	Test
+}
+
+/// アーカイブの操作を実際に行う実装
+pub trait Backend {
+	/// アーカイブを作成する
+	fn create(&self,d:&CreateData,arc_type:&ArcType) -> Result<()>;
+	/// アーカイブの内容を読み込む
+	fn list(&self,d:&ListData,arc_type:&ArcType) -> Result<Vec<Entry>>;
+	/// アーカイブが破損していないか検査する
+	fn test(&self,d:&TestData,arc_type:&ArcType) -> Result<()>;
+	/// アーカイブを output に展開する (untar は圧縮された tar アーカイブを展開するかどうか)
+	fn extract(&self,d:&ExtractData,arc_type:&ArcType,untar:bool,output:&str) -> Result<()>;
+	/// アーカイブにファイルを追加する
+	fn append(&self,d:&AppendData,arc_type:&ArcType) -> Result<()>;
+	/// アーカイブからファイルを削除する
+	fn remove(&self,d:&RemoveData,arc_type:&ArcType) -> Result<()>;
+	/// ネイティブ実装か
+	fn native(&self) -> bool;
+}
+
+/// 外部コマンドを実行する実装
+pub struct External;
+impl Backend for External {
+	fn create(&self,d:&CreateData,arc_type:&ArcType) -> Result<()> {
+		return create_external(d,arc_type);
+	}
+	fn list(&self,d:&ListData,arc_type:&ArcType) -> Result<Vec<Entry>> {
+		return list_external(d,arc_type);
+	}
+	fn test(&self,d:&TestData,arc_type:&ArcType) -> Result<()> {
+		return test_external(d,arc_type);
+	}
+	fn extract(&self,d:&ExtractData,arc_type:&ArcType,untar:bool,output:&str) -> Result<()> {
+		return extract_external(d,arc_type,untar,output);
+	}
+	fn append(&self,d:&AppendData,arc_type:&ArcType) -> Result<()> {
+		return append_external(d,arc_type);
+	}
+	fn remove(&self,d:&RemoveData,arc_type:&ArcType) -> Result<()> {
+		return remove_external(d,arc_type);
+	}
+	fn native(&self) -> bool {
+		return false;
+	}
+}
+
+/// 実装を選択する。指定がない場合は外部コマンドが揃っていればそれを使い、なければネイティブ実装を使う
+pub fn select_backend(arc_type:&ArcType,backend:&Option<BackendType>,op:Operation) -> Result<Box<dyn Backend>> {
+	return Ok(match backend {
+		Some(BackendType::External) => Box::new(External),
+		Some(BackendType::Native) => {
+			if !native_supports(arc_type,op) {
+				return Err(ArchiverError::Unsupported(msg!("native-unsupported")));
+			}
+			Box::new(Native)
+		},
+		None => {
+			if !tools_available(arc_type,op) && native_supports(arc_type,op) { Box::new(Native) }
+			else { Box::new(External) }
+		}
+	});
+}
+
+/// 外部コマンドでの操作に必要なコマンドが全て存在するか
+pub fn tools_available(arc_type:&ArcType,op:Operation) -> bool {
+	return match &required_tools(arc_type)[op as usize] {
+		Some(l) => l.iter().all(|p| which(p).is_ok() ),
+		None => false
+	};
+}
+
+/// 外部コマンドでの各操作 (Operation の順) に必要なコマンド (None は非対応)
+pub fn required_tools(at:&ArcType) -> [Option<Vec<&'static str>>;7] {
+	if let Some((c,d)) = compressor(at) {
+		// 作成は tar アーカイブの圧縮、一覧は解凍しながら表示、追加などは解凍して tar アーカイブを書き直してから再圧縮する
+		// 検査は圧縮コマンドで行い、中身が tar アーカイブであれば解凍しながら構造も確認する
+		let test = match at {
+			ArcType::Rzip|ArcType::Lzfse => None,
+			_ => Some(vec![d,"bsdtar"])
+		};
+		return [
+			Some(vec!["bsdtar",c]),
+			Some(vec![d,"bsdtar"]),
+			Some(vec![d]),
+			Some(vec![d,"bsdtar",c]),
+			Some(vec![d,"bsdtar",c]),
+			Some(vec![d,"bsdtar",c]),
+			test
+		];
+	}
+	return match at {
+		ArcType::Zip    => [Some(vec!["zip"])       ,Some(vec!["zipinfo"]),Some(vec!["unzip"])   ,Some(vec!["zip"])   ,Some(vec!["zip"])   ,Some(vec!["zipnote"]),Some(vec!["unzip"])],
+		ArcType::SevenZ => [Some(vec!["7z"])        ,Some(vec!["7z"])     ,Some(vec!["7z"])      ,Some(vec!["7z"])    ,Some(vec!["7z"])    ,Some(vec!["7z"])     ,Some(vec!["7z"])],
+		ArcType::Tar    => [Some(vec!["bsdtar"])    ,Some(vec!["bsdtar"]) ,Some(vec!["bsdtar"])  ,Some(vec!["bsdtar"]),Some(vec!["bsdtar"]),Some(vec!["bsdtar"]) ,Some(vec!["bsdtar"])],
+		ArcType::Cpio   => [Some(vec!["cpio"])      ,Some(vec!["bsdtar"]) ,Some(vec!["bsdtar"])  ,None                ,Some(vec!["bsdtar"]),Some(vec!["bsdtar"]) ,Some(vec!["bsdtar"])],
+		ArcType::Rar    => [Some(vec!["rar"])       ,Some(vec!["rar"])    ,Some(vec!["rar"])     ,Some(vec!["rar"])   ,Some(vec!["rar"])   ,Some(vec!["rar"])    ,Some(vec!["rar"])],
+		ArcType::Aar    => [Some(vec!["aa"])        ,Some(vec!["aa"])     ,Some(vec!["aa"])      ,None                ,None                ,None                 ,Some(vec!["aa"])],
+		ArcType::Wim    => [Some(vec!["wimcapture"]),Some(vec!["wimdir"]) ,Some(vec!["wimapply"]),None                ,None                ,None                 ,Some(vec!["wiminfo"])],
+		ArcType::Dmg    => [Some(vec!["hdiutil"])   ,None                 ,None                  ,None                ,None                ,None                 ,Some(vec!["hdiutil"])],
+		ArcType::Iso    => [Some(vec!["hdiutil"])   ,Some(vec!["bsdtar"]) ,None                  ,None                ,None                ,None                 ,Some(vec!["bsdtar"])],
+		ArcType::Zpaq   => [Some(vec!["zpaq"])      ,Some(vec!["zpaq"])   ,Some(vec!["zpaq"])    ,Some(vec!["zpaq"])  ,None                ,None                 ,Some(vec!["zpaq"])],
+		ArcType::Lha    => [Some(vec!["lha"])       ,Some(vec!["bsdtar"]) ,Some(vec!["lha"])     ,Some(vec!["lha"])   ,Some(vec!["lha"])   ,None                 ,Some(vec!["bsdtar"])],
+		_               => [None                    ,None                 ,None                  ,None                ,None                ,None                 ,None]
+	};
+}
+
+/// 圧縮系のフォーマットの (圧縮,解凍) コマンド (圧縮系でなければ None)
+fn compressor(at:&ArcType) -> Option<(&'static str,&'static str)> {
+	return Some(match at {
+		ArcType::Compress => ("compress","uncompress"),
+		ArcType::Gzip     => ("gzip"    ,"gzip"      ),
+		ArcType::Bzip2    => ("bzip2"   ,"bzip2"     ),
+		ArcType::Xz       => ("xz"      ,"xz"        ),
+		ArcType::Lzip     => ("lzip"    ,"lzip"      ),
+		ArcType::Lzma     => ("lzma"    ,"lzma"      ),
+		ArcType::Lz4      => ("lz4"     ,"lz4"       ),
+		ArcType::Lzop     => ("lzop"    ,"lzop"      ),
+		ArcType::Lrzip    => ("lrzip"   ,"lrzip"     ),
+		ArcType::Rzip     => ("rzip"    ,"rzip"      ),
+		ArcType::Zstd     => ("zstd"    ,"zstd"      ),
+		ArcType::Brotli   => ("brotli"  ,"brotli"    ),
+		ArcType::Lzfse    => ("aa"      ,"aa"        ),
+		_ => { return None; }
+	});
+}
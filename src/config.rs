@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::mem::take;
+use std::path::PathBuf;
+use serde::{Deserialize,Deserializer};
+use serde::de::Error as _;
+use crate::library::*;
+use crate::args::*;
+use crate::error::*;
+use crate::options::*;
+use crate::message::text;
+
+// 設定ファイル (~/.config/archiver/config.toml と、現在のディレクトリから親をたどって最初に見つかった .archiver.toml)
+//
+//   type = "zip"                    # 拡張子や内容から種類が判定できない場合の種類
+//   rate = 6
+//   exclude = ["target","*.o"]
+//
+//   [format.xz]                     # 種類ごとの設定
+//   rate = 9
+//
+//   [profile.release]               # --profile release で使う設定
+//   keep-path = true
+//
+//   [profile.release.format.zstd]   # プロファイルの種類ごとの設定
+//   rate = 9
+//
+// 設定の名前はコマンドラインの引数と同じ
+// 書式は toml クレートで読み込むので、 . で区切ったキー (format.xz.rate = 9 など) やインラインテーブルも使える
+
+/// 1つのセクションの設定 (指定されていないものは None)
+#[derive(Default,Clone,Deserialize)]
+#[serde(rename_all="kebab-case",deny_unknown_fields)]
+struct Settings {
+	#[serde(rename="type",default,deserialize_with="arg_enum")]
+	arc_type: Option<ArcType>,
+	#[serde(default,deserialize_with="arg_enum")]
+	backend: Option<BackendType>,
+	rate: Option<u8>,
+	#[serde(default,deserialize_with="positive")]
+	threads: Option<usize>,
+	keep_path: Option<bool>,
+	image_name: Option<String>,
+	#[serde(default,deserialize_with="one_or_many")]
+	exclude: Vec<String>,
+	exclude_vcs: Option<bool>,
+	#[serde(default,deserialize_with="arg_enum")]
+	overwrite: Option<Overwrite>,
+	#[serde(default,deserialize_with="arg_enum")]
+	progress: Option<ProgressMode>,
+	/// 種類ごとのセクション ([format.種類])
+	#[serde(default)]
+	format: HashMap<String,Settings>,
+	/// プロファイルのセクション ([profile.名前])
+	#[serde(default)]
+	profile: HashMap<String,Settings>
+}
+impl Settings {
+	/// o で指定されているもので置き換える (除外のパターンは追加する)
+	fn merge(&mut self,o:&Settings) {
+		if o.arc_type.is_some() { self.arc_type = o.arc_type; }
+		if o.backend.is_some() { self.backend = o.backend; }
+		if o.rate.is_some() { self.rate = o.rate; }
+		if o.threads.is_some() { self.threads = o.threads; }
+		if o.keep_path.is_some() { self.keep_path = o.keep_path; }
+		if o.image_name.is_some() { self.image_name = o.image_name.clone(); }
+		self.exclude.extend(o.exclude.iter().cloned());
+		if o.exclude_vcs.is_some() { self.exclude_vcs = o.exclude_vcs; }
+		if o.overwrite.is_some() { self.overwrite = o.overwrite; }
+		if o.progress.is_some() { self.progress = o.progress; }
+	}
+}
+
+/// コマンドラインと同じ名前で指定する値
+fn arg_enum<'de,D:Deserializer<'de>,T:ArgEnum>(d:D) -> std::result::Result<Option<T>,D::Error> {
+	let s = String::deserialize(d)?;
+	return T::from_str(&s,false).map(Some).map_err(|_| D::Error::custom(msg!("config-invalid-choice",s)) );
+}
+fn positive<'de,D:Deserializer<'de>>(d:D) -> std::result::Result<Option<usize>,D::Error> {
+	return match usize::deserialize(d)? {
+		0 => Err(D::Error::custom(text("config-not-positive"))),
+		n => Ok(Some(n))
+	};
+}
+/// 1つだけの場合は配列にしなくてもよい
+fn one_or_many<'de,D:Deserializer<'de>>(d:D) -> std::result::Result<Vec<String>,D::Error> {
+	#[derive(Deserialize)]
+	#[serde(untagged)]
+	enum OneOrMany {
+		One(String),
+		Many(Vec<String>)
+	}
+	return Ok(match OneOrMany::deserialize(d)? {
+		OneOrMany::One(s) => vec![s],
+		OneOrMany::Many(l) => l
+	});
+}
+
+/// 種類の正式な名前 (セクションの名前に使う)
+fn format_name(at:&ArcType) -> Option<String> {
+	return at.to_possible_value().map(|v| v.get_name().to_string() );
+}
+
+/// 設定ファイルのセクションごとの設定と使用するプロファイル
+#[derive(Default)]
+pub struct Config {
+	/// "" (全体), "format.種類", "profile.名前", "profile.名前.format.種類" ごとの設定
+	sections: HashMap<String,Settings>,
+	profile: Option<String>
+}
+
+impl Config {
+	/// 設定ファイルを読み込む (同じ設定はユーザーの設定より .archiver.toml の方を優先する)
+	pub fn load(profile:Option<&str>) -> Result<Config> {
+		let mut c = Config::default();
+		for path in config_files() {
+			let text = match fs::read_to_string(&path) {
+				Ok(t) => t,
+				Err(e) if e.kind()==ErrorKind::NotFound => { continue; },
+				Err(e) => { return Err(ArchiverError::Usage(msg!("config-unreadable",path.display(),e))); }
+			};
+			for (name,s) in parse(&text,&path.to_string_lossy())? {
+				c.sections.entry(name).or_default().merge(&s);
+			}
+		}
+		if let Some(p) = profile {
+			let section = format!("profile.{}",p);
+			if !c.sections.keys().any(|n| n==&section || n.starts_with(&format!("{}.",section)) ) {
+				return Err(ArchiverError::Usage(msg!("profile-not-found",p)));
+			}
+			c.profile = Some(p.to_string());
+		}
+		return Ok(c);
+	}
+
+	/// 種類 (分からない場合は None) に使う設定 (全体、種類ごと、プロファイル、プロファイルの種類ごとの順に優先する)
+	fn settings(&self,at:Option<ArcType>) -> Settings {
+		let format = at.as_ref().and_then(format_name);
+		let mut names = vec![String::new()];
+		if let Some(f) = &format { names.push(format!("format.{}",f)); }
+		if let Some(p) = &self.profile {
+			names.push(format!("profile.{}",p));
+			if let Some(f) = &format { names.push(format!("profile.{}.format.{}",p,f)); }
+		}
+		let mut s = Settings::default();
+		for n in names.iter() {
+			if let Some(t) = self.sections.get(n) { s.merge(t); }
+		}
+		return s;
+	}
+
+	/// 既存のアーカイブの種類が内容と拡張子から判定できない場合は設定の種類にする (標準入力は読み込む時に判定する)
+	fn existing_type(&self,at:Option<ArcType>,input:&String) -> Option<ArcType> {
+		if at.is_some() || is_stdio(input) || detect_type(input,true).is_some() { return at; }
+		return self.settings(None).arc_type;
+	}
+
+	// 以下はコマンドラインで指定されていない (given が false の) 引数に設定の値を使う
+	// given には引数の ID (フィールド名を - でつないだもの) を渡す
+
+	pub fn create(&self,mut o:CreateOptions,given:&dyn Fn(&str)->bool) -> CreateOptions {
+		let d = &mut o.d;
+		if d.arc_type.is_none() && guess_type(&d.output,true).is_none() { d.arc_type = self.settings(None).arc_type; }
+		let s = self.settings(d.arc_type.or_else(|| guess_type(&d.output,true) ));
+		filter(&mut d.filter,&s,given);
+		d.backend = d.backend.or(s.backend);
+		d.threads = d.threads.or(s.threads);
+		set(&mut d.rate,given("rate"),s.rate);
+		set(&mut d.keep_path,given("keep-path"),s.keep_path);
+		set(&mut d.image_name,given("image-name"),s.image_name);
+		set(&mut d.progress,given("progress"),s.progress);
+		set(&mut d.overwrite,given("overwrite"),s.overwrite);
+		return o;
+	}
+
+	pub fn extract(&self,mut o:ExtractOptions,given:&dyn Fn(&str)->bool) -> ExtractOptions {
+		let d = &mut o.d;
+		d.arc_type = self.existing_type(d.arc_type,&d.input);
+		let s = self.settings(d.arc_type.or_else(|| detect_type(&d.input,true) ));
+		filter(&mut d.filter,&s,given);
+		d.backend = d.backend.or(s.backend);
+		set(&mut d.progress,given("progress"),s.progress);
+		set(&mut d.overwrite,given("overwrite"),s.overwrite);
+		return o;
+	}
+
+	pub fn list(&self,mut o:ListOptions) -> ListOptions {
+		let d = &mut o.d;
+		d.arc_type = self.existing_type(d.arc_type,&d.input);
+		let s = self.settings(d.arc_type.or_else(|| detect_type(&d.input,true) ));
+		d.backend = d.backend.or(s.backend);
+		return o;
+	}
+
+	pub fn test(&self,mut o:TestOptions) -> TestOptions {
+		let d = &mut o.d;
+		d.arc_type = self.existing_type(d.arc_type,&d.input);
+		let s = self.settings(d.arc_type.or_else(|| detect_type(&d.input,true) ));
+		d.backend = d.backend.or(s.backend);
+		return o;
+	}
+
+	pub fn append(&self,mut o:AppendOptions,given:&dyn Fn(&str)->bool) -> AppendOptions {
+		let d = &mut o.d;
+		d.arc_type = self.existing_type(d.arc_type,&d.output);
+		let s = self.settings(d.arc_type.or_else(|| guess_existing_type(&d.output).ok() ));
+		d.backend = d.backend.or(s.backend);
+		d.rate = d.rate.or(s.rate);
+		set(&mut d.keep_path,given("keep-path"),s.keep_path);
+		return o;
+	}
+
+	pub fn remove(&self,mut o:RemoveOptions) -> RemoveOptions {
+		let d = &mut o.d;
+		d.arc_type = self.existing_type(d.arc_type,&d.input);
+		let s = self.settings(d.arc_type.or_else(|| guess_existing_type(&d.input).ok() ));
+		d.backend = d.backend.or(s.backend);
+		d.rate = d.rate.or(s.rate);
+		return o;
+	}
+
+	pub fn rename(&self,mut o:RenameOptions) -> RenameOptions {
+		let d = &mut o.d;
+		d.arc_type = self.existing_type(d.arc_type,&d.input);
+		let s = self.settings(d.arc_type.or_else(|| guess_existing_type(&d.input).ok() ));
+		d.rate = d.rate.or(s.rate);
+		return o;
+	}
+
+	/// 圧縮率などは変換先の種類の設定を使う
+	pub fn convert(&self,mut o:ConvertOptions,given:&dyn Fn(&str)->bool) -> ConvertOptions {
+		let d = &mut o.d;
+		d.from = self.existing_type(d.from,&d.input);
+		if d.arc_type.is_none() && guess_type(&d.output,true).is_none() { d.arc_type = self.settings(None).arc_type; }
+		let s = self.settings(d.arc_type.or_else(|| guess_type(&d.output,true) ));
+		d.backend = d.backend.or(s.backend);
+		set(&mut d.rate,given("rate"),s.rate);
+		set(&mut d.overwrite,given("overwrite"),s.overwrite);
+		return o;
+	}
+}
+
+/// コマンドラインで指定されていなければ設定の値にする
+fn set<T>(v:&mut T,given:bool,c:Option<T>) {
+	if given { return; }
+	if let Some(c) = c { *v = c; }
+}
+
+/// 設定の除外のパターンはコマンドラインのものより前に置く (コマンドラインの ! で始まるパターンで対象に戻せる)
+fn filter(f:&mut FilterData,s:&Settings,given:&dyn Fn(&str)->bool) {
+	f.exclude.splice(0..0,s.exclude.iter().cloned());
+	set(&mut f.exclude_vcs,given("exclude-vcs"),s.exclude_vcs);
+}
+
+/// 読み込む設定ファイル (後のものほど優先する)
+fn config_files() -> Vec<PathBuf> {
+	let mut l = vec![];
+	let dir = match env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty() ) {
+		Some(d) => Some(PathBuf::from(d)),
+		None => env::var_os("HOME").map(|h| PathBuf::from(h).join(".config") )
+	};
+	if let Some(d) = dir { l.push(d.join("archiver").join("config.toml")); }
+	if let Ok(cd) = env::current_dir() {
+		if let Some(p) = cd.ancestors().map(|d| d.join(".archiver.toml") ).find(|p| p.is_file() ) { l.push(p); }
+	}
+	return l;
+}
+
+/// 設定ファイルをセクションごとの設定にする (file はエラーの表示用)
+fn parse(text:&str,file:&str) -> Result<Vec<(String,Settings)>> {
+	let mut root = match toml::from_str::<Settings>(text) {
+		Ok(s) => s,
+		Err(e) => { return Err(ArchiverError::Usage(msg!("config-syntax",file,e.to_string().trim_end()))); }
+	};
+	let mut l = vec![];
+	for (t,s) in take(&mut root.format) { l.push(format_section("format",&t,s,file)?); }
+	for (n,mut p) in take(&mut root.profile) {
+		let name = format!("profile.{}",n);
+		if !p.profile.is_empty() { return Err(ArchiverError::Usage(msg!("config-section",file,format!("{}.profile",name)))); }
+		for (t,s) in take(&mut p.format) { l.push(format_section(&format!("{}.format",name),&t,s,file)?); }
+		l.push((name,p));
+	}
+	l.push((String::new(),root));
+	return Ok(l);
+}
+
+/// 種類ごとのセクションの名前を確認し、種類は正式な名前にする (prefix は format か profile.名前.format)
+fn format_section(prefix:&str,t:&str,s:Settings,file:&str) -> Result<(String,Settings)> {
+	let name = format!("{}.{}",prefix,t);
+	let format = match ArcType::from_str(t,false).ok().as_ref().and_then(format_name) {
+		Some(f) if s.format.is_empty() && s.profile.is_empty() => f,
+		_ => { return Err(ArchiverError::Usage(msg!("config-section",file,name))); }
+	};
+	// 種類ごとのセクションで種類は指定できない
+	if s.arc_type.is_some() { return Err(ArchiverError::Usage(msg!("config-unknown-key",file,format!("{}.type",name)))); }
+	return Ok((format!("{}.{}",prefix,format),s));
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn config(text:&str,profile:Option<&str>) -> Config {
+		let mut c = Config { sections: HashMap::new(), profile: profile.map(|p| p.to_string() ) };
+		for (name,s) in parse(text,"test.toml").unwrap() { c.sections.entry(name).or_default().merge(&s); }
+		return c;
+	}
+
+	#[test]
+	fn documented_example() {
+		let c = config(r#"
+type = "zip"                    # 拡張子や内容から種類が判定できない場合の種類
+rate = 6
+exclude = ["target","*.o"]
+
+[format.xz]                     # 種類ごとの設定
+rate = 9
+
+[profile.release]               # --profile release で使う設定
+keep-path = true
+
+[profile.release.format.zstd]   # プロファイルの種類ごとの設定
+rate = 9
+"#,Some("release"));
+		let s = c.settings(None);
+		assert!(matches!(s.arc_type,Some(ArcType::Zip)));
+		assert_eq!(s.rate,Some(6));
+		assert_eq!(s.exclude,vec!["target","*.o"]);
+		assert_eq!(s.keep_path,Some(true));
+		assert_eq!(c.settings(Some(ArcType::Xz)).rate,Some(9));
+		assert_eq!(c.settings(Some(ArcType::Zstd)).rate,Some(9));
+		assert_eq!(c.settings(Some(ArcType::Gzip)).rate,Some(6));
+	}
+
+	#[test]
+	fn rejected() {
+		for t in ["[format.unknown]","[format.xz]\ntype = \"zip\"","[profile.a.profile.b]","overwrite = \"bogus\"","threads = 0","unknown = 1"] {
+			assert!(matches!(parse(t,"test.toml"),Err(ArchiverError::Usage(_))),"{}",t);
+		}
+	}
+}
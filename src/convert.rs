@@ -0,0 +1,171 @@
+use std::fs;
+use which::which;
+use crate::library::*;
+use crate::args::*;
+use crate::backend::*;
+use crate::error::*;
+use crate::safety::{Limits,check_archive};
+use crate::create::compress_stdout_cmd;
+use crate::extract::{contains_tar,decompress_stdout_cmd,compress_remove_ext};
+use crate::overwrite::{prepare_output,staged_output,commit_output};
+use crate::message::text;
+
+pub fn convert(mut d:ConvertData) -> Result<()> {
+
+	// アーカイブが存在することを確認
+	if !Path::new(&d.input).is_file() {
+		return Err(ArchiverError::NotFound(msg!("file-not-found",d.input)));
+	}
+
+	// 変換元と変換先のアーカイブの種類を判定
+	let in_type = match d.from {
+		Some(t) => t,
+		None => {
+			match detect_type(&d.input,true) {
+				Some(t) => t,
+				None => {
+					return Err(ArchiverError::Unsupported(msg!("convert-unknown-from")));
+				}
+			}
+		}
+	};
+	let out_type = match d.arc_type {
+		Some(t) => t,
+		None => {
+			match guess_type(&d.output,true) {
+				Some(t) => t,
+				None => ArcType::Zip
+			}
+		}
+	};
+
+	// 保存先があることを確認
+	let op = Path::new(&d.output).absolute_path();
+	if Path::new(&d.input).absolute_path()==op {
+		return Err(ArchiverError::Usage(msg!("convert-same-file")));
+	}
+	match op.parent() {
+		Some(p) => {
+			if !p.is_dir() {
+				return Err(ArchiverError::NotFound(msg!("output-dir-missing")));
+			}
+		},
+		None => {
+			return Err(ArchiverError::Usage(msg!("output-invalid")));
+		}
+	}
+
+	// パラメータの条件を確認
+	rate_conversion(&mut d.rate,&out_type)?;
+
+	// 保存先のアーカイブが既に存在する場合は --overwrite に従い、同じフォルダに作成してから成功した場合だけ置き換える
+	let output = match prepare_output(d.overwrite,&d.output,std::slice::from_ref(&d.input))? {
+		Some(o) => o,
+		None => { return Ok(()); }
+	};
+	let (stage,staged) = staged_output(&output)?;
+	d.output = staged;
+
+	let untar = is_compress(&in_type) && contains_tar(&in_type,&d.input);
+
+	// tar アーカイブの圧縮形式だけを変える場合は、ディスクに書き出さずにパイプで繋いで変換する
+	let stream = match out_type {
+		ArcType::Tar => untar,
+		at => (untar || in_type==ArcType::Tar) && is_compress(&at)
+	};
+	if stream && d.backend!=Some(BackendType::Native) {
+		if let Some(cl) = stream_cmds(&d,&in_type,&out_type) {
+			piped_cmd(cl).map_err(|e| e.context(text("convert-failed")) )?;
+			return commit_output(stage,&d.output,&output);
+		}
+	}
+
+	// 作業ディレクトリに展開する
+	let tmp = work_dir()?;
+	let dir = tmp.path().join("extract");
+	if let Err(e) = fs::create_dir(&dir) {
+		return Err(ArchiverError::Failed(msg!("tmp-dir-failed",e)));
+	}
+	let dest = match is_compress(&in_type) && !untar {
+		// 単一ファイルの圧縮は元のファイル名で解凍する
+		true => {
+			let name = compress_remove_ext(&d.input,&in_type);
+			dir.join(Path::new(&name).file_name().unwrap_or_default())
+		},
+		false => dir.clone()
+	}.to_string_lossy().to_string();
+	let ed = ExtractData {
+		input: d.input.clone(),
+		output: Some(dest.clone()),
+		target: vec![],
+		arc_type: Some(in_type),
+		backend: d.backend,
+		no_untar: false,
+		smart: false,
+		preserve: vec![],
+		verbose: d.verbose,
+		progress: ProgressMode::None,
+		overwrite: Overwrite::Always,
+		filter: FilterData::default(),
+		safety: SafetyData::default(),
+		pw: PasswordData::default()
+	};
+	// 作業ディレクトリの外に展開されないように確認する
+	if untar || !is_compress(&in_type) {
+		let limits = Limits::new(&ed.safety,0)?;
+		check_archive(&ed.input,&in_type,untar,&None,&ed.safety,&limits,&tmp)?;
+	}
+	let backend = select_backend(&in_type,&d.backend,Operation::Extract)?;
+	backend.extract(&ed,&in_type,untar,&dest).map_err(|e| e.context(text("extract-failed")) )?;
+
+	// 展開したものから作成する (ルート階層にあるものをそのまま配置する)
+	let mut input = match fs::read_dir(&dir) {
+		Ok(rd) => rd.filter_map(|e| e.ok() ).map(|e| e.path().to_string_lossy().to_string() ).collect::<Vec<_>>(),
+		Err(e) => {
+			return Err(ArchiverError::Failed(msg!("convert-read-extracted",e)));
+		}
+	};
+	input.sort();
+	let cd = CreateData {
+		input,
+		output: d.output.clone(),
+		arc_type: Some(out_type),
+		backend: d.backend,
+		rate: d.rate,
+		verbose: d.verbose,
+		progress: ProgressMode::None,
+		overwrite: Overwrite::Always,
+		image_name: String::from("Untitled"),
+		keep_path: false,
+		threads: None,
+		preserve: vec![],
+		split_size: None,
+		reproducible: false,
+		filter: FilterData::default(),
+		pw: PasswordData::default(),
+		base: None
+	};
+	let backend = select_backend(&out_type,&d.backend,Operation::Create)?;
+	backend.create(&cd,&out_type).map_err(|e| e.context(text("create-failed")) )?;
+
+	commit_output(stage,&d.output,&output)?;
+	close_dir(tmp)?;
+	return Ok(());
+
+}
+
+/// 解凍するコマンドと圧縮するコマンドを繋いだもの (コマンドが不足している場合などは None)
+fn stream_cmds(d:&ConvertData,in_type:&ArcType,out_type:&ArcType) -> Option<Vec<Cmd>> {
+	let mut l = vec![];
+	if is_compress(in_type) {
+		l.push(decompress_stdout_cmd(in_type,&d.input)?);
+	}
+	if *out_type!=ArcType::Tar {
+		let mut c = compress_stdout_cmd(out_type,&d.rate,1)?;
+		if l.is_empty() { c.args.push(OsString::from(&d.input)); }
+		l.push(c);
+	}
+	l.last_mut()?.stdout = Some(d.output.clone());
+	if !l.iter().all(|c| which(&c.prog).is_ok() ) { return None; }
+	return Some(l);
+}
@@ -1,12 +1,29 @@
-use std::env::current_dir;
 use std::fs;
+use std::process::{Command,Stdio};
+use which::which;
 use crate::library::*;
 use crate::args::*;
-
-pub fn create(mut d:CreateData) {
-
-	// カレントディレクトリ
-	let cd = current_dir().expect("カレントディレクトリが存在しません");
+use crate::error::*;
+use crate::backend::*;
+use crate::password::*;
+use crate::volume::*;
+use crate::filter::*;
+use crate::progress;
+use crate::preserve::*;
+use crate::overwrite::{prepare_output,staged_output,commit_output};
+use crate::reproducible::*;
+use crate::message::text;
+
+pub fn create(mut d:CreateData) -> Result<()> {
+
+	// 標準出力に書き出す場合は種類を判定できないので --type を必須にする
+	let stdout = is_stdio(&d.output);
+	if stdout {
+		if d.arc_type.is_none() { return Err(ArchiverError::Usage(msg!("stdout-needs-type"))); }
+		if d.split_size.is_some() { return Err(ArchiverError::Usage(msg!("stdout-no-split"))); }
+		// 進行状況などがアーカイブに混ざらないようにする
+		d.verbose = false;
+	}
 
 	// アーカイブの種類を判定
 	let arc_type = match d.arc_type {
@@ -19,58 +36,147 @@ pub fn create(mut d:CreateData) {
 		}
 	};
 
+	// パスワードを読み込み、暗号化できる種類であることを確認
+	load_password(&mut d.pw)?;
+	check_encryption(&d.pw,&arc_type)?;
+
+	// 再現可能なアーカイブを作成できる種類であることを確認
+	if d.reproducible { check_reproducible(&d,&arc_type)?; }
+
 	// 入力ファイルの String を Path に変換
-	let ip = d.input.iter().map(|i| Path::new(i) ).collect::<Vec<_>>();
+	let ip = d.input.iter().map(Path::new).collect::<Vec<_>>();
 
 	// ファイルが全て存在することを確認
 	for (p,i) in izip!(ip.iter(),d.input.iter()) {
 		if !p.exists() {
-			error(format!("ファイルが存在しません: {}",i));
+			return Err(ArchiverError::NotFound(msg!("file-not-found",i)));
 		}
 	}
 
 	// 保存先があることを確認
 	let op = Path::new(&d.output).absolute_path();
 	match op.parent() {
+		_ if stdout => {},
 		Some(p) => {
 			if !p.is_dir() {
-				error("アーカイブの保存先が存在していません");
+				return Err(ArchiverError::NotFound(msg!("output-dir-missing")));
 			}
 		},
 		None => {
-			error("アーカイブの保存先が正しくありません");
+			return Err(ArchiverError::Usage(msg!("output-invalid")));
 		}
 	}
 
 	// パラメータの条件を確認
-	rate_conversion(&mut d.rate,&arc_type);
+	rate_conversion(&mut d.rate,&arc_type)?;
 
-	// 実行モードを選択 (単一ファイル,複数ファイルアーカイブ,空のアーカイブ)
-	let filetypes = match ip.len() {
-		0 => CreateType::Empty,
-		1 => {
-			match ip[0] {
-				p if p.is_file() && !p.is_symlink() => CreateType::SingleFile,
-				p if p.is_dir()  && !p.is_symlink() => CreateType::SingleDir,
-				_ => CreateType::Multiple
+	// 並列に圧縮できるコマンドがない gzip と bzip2 は、ネイティブ実装で並列に圧縮する
+	if thread_count(&d.threads)>1 && d.backend.is_none() && matches!(arc_type,ArcType::Gzip|ArcType::Bzip2) && !has_parallel_compressor(&arc_type) {
+		d.backend = Some(BackendType::Native);
+	}
+
+	// 入力ファイルを絞り込む (zip と 7z は隠しファイルを既定で除外する)
+	let filter = Filter::new(&d.filter,default_excludes(&arc_type),true)?;
+	let split = d.split_size.as_ref().map(split_size).transpose()?;
+
+	// 引数を全て確認してから、保存先のアーカイブが既に存在する場合は --overwrite に従う
+	if !stdout {
+		match prepare_output(d.overwrite,&d.output,&d.input)? {
+			Some(o) => { d.output = o; },
+			None => { return Ok(()); }
+		}
+	}
+	let stage = stage_inputs(&mut d,&filter)?;
+
+	// 分割する場合は作業ディレクトリに作成してから分割する (rar と 7z は作成時に分割する)
+	let tmp = work_dir()?;
+	let output = d.output.clone();
+	if split.is_some() && !split_on_create(&arc_type) {
+		d.output = tmp.path().join(Path::new(&output).file_name().unwrap_or_default()).to_string_lossy().to_string();
+	}
+
+	// 分割しない場合は保存先と同じフォルダに作成し、成功してから既存のアーカイブと置き換える
+	let staged = match stdout || split.is_some() {
+		true => None,
+		false => {
+			let (t,o) = staged_output(&output)?;
+			d.output = o;
+			Some(t)
+		}
+	};
+
+	// 進行状況は入力ファイルを読み込んだ量で表す
+	let total = d.input.iter().map(|i| progress::path_size(Path::new(i)) ).sum::<u64>();
+
+	// 標準出力に書き出す場合は、 tar アーカイブと圧縮はパイプで繋いで書き出し、それ以外は作業ディレクトリに作成してから書き出す
+	if stdout {
+		if d.backend!=Some(BackendType::Native) && !d.reproducible {
+			if let Some(mut cl) = stdout_cmds(&d,&arc_type) {
+				warn_unsupported(&d.preserve,&preserve_type(&d,&arc_type),false,false);
+				let progress = progress::start(&d.progress,d.verbose,"create",total);
+				for c in cl.iter_mut() { progress::file_events(c); }
+				let r = piped_cmd(cl);
+				if let Some(p) = progress { p.finish(r.is_ok()); }
+				return r.map_err(|e| e.context(text("create-failed")) );
 			}
-		},
-		_ => CreateType::Multiple
+		}
+		d.output = tmp.join_str("archive").to_string_lossy().to_string();
+	}
+
+	// 実装を選択して実行 (再現可能なアーカイブは bsdtar で作成する)
+	let backend = match d.reproducible {
+		true => None,
+		false => Some(select_backend(&arc_type,&password_backend(&d.pw,&arc_type,&d.backend),Operation::Create)?)
+	};
+	if let Some(b) = &backend { warn_unsupported(&d.preserve,&preserve_type(&d,&arc_type),b.native(),false); }
+	let progress = progress::start(&d.progress,d.verbose,"create",total);
+	let r = match &backend {
+		Some(b) => b.create(&d,&arc_type),
+		None => create_reproducible(&d,&arc_type)
 	};
+	if let Some(p) = progress { p.finish(r.is_ok()); }
+	r.map_err(|e| e.context(text("create-failed")) )?;
+
+	if let Some(size) = split {
+		if !split_on_create(&arc_type) { split_archive(&arc_type,&d.output,&output,size)?; }
+	}
+	if stdout && !copy_to_stdout(&d.output) { return Err(ArchiverError::Failed(msg!("stdout-write-failed"))); }
+	if let Some(t) = staged { commit_output(t,&d.output,&output)?; }
+	close_dir(tmp)?;
+	if let Some(s) = stage { close_dir(s)?; }
+	return Ok(());
 
-	let tmp = tempdir().expect("作業ディレクトリが作成できませんでした");
+}
+
+/// 外部コマンドによるアーカイブの作成
+pub fn create_external(d:&CreateData,arc_type:&ArcType) -> Result<()> {
+
+	// カレントディレクトリ
+	let cd = cwd()?;
+
+	// 入力ファイルの String を Path に変換
+	let ip = d.input.iter().map(Path::new).collect::<Vec<_>>();
+
+	// 実行モードを選択 (単一ファイル,複数ファイルアーカイブ,空のアーカイブ)
+	let filetypes = create_type(&ip);
+	// 空のアーカイブには暗号化するものがない
+	let empty = matches!(filetypes,CreateType::Empty);
+
+	let tmp = work_dir()?;
 
 	// アーカイブファイルの一時保存先
 	let mut archive:OsString = OsString::new();
 	// アーカイブを一時保存先に保存して利用するかどうか
 	let mut use_temp_position:bool = true;
+	// コマンドをパイプで繋いで実行するかどうか
+	let mut pipe:bool = false;
 
 	// コマンドを用意
-	let cl:Vec<Cmd> = match (filetypes,arc_type) {
+	let mut cl:Vec<Cmd> = match (filetypes,*arc_type) {
 		(CreateType::Empty,ArcType::Zip) => {
 			archive = tmp.join_str("archive.zip");
 			let empty_dir = tmp.join_str("_");
-			if let Err(_) = fs::create_dir(&empty_dir) { error("アーカイブ作成ができませんでした"); }
+			if let Err(e) = fs::create_dir(&empty_dir) { return Err(ArchiverError::Failed(msg!("create-dir-failed",e))); }
 
 			["-r","-d"].into_iter().map(|flag| {
 				let mut args = vec![flag];
@@ -79,11 +185,26 @@ pub fn create(mut d:CreateData) {
 				Cmd::new_cwd("zip",vs(args),tmp.path().to_path_buf())
 			}).collect()
 		},
+		// zip -P はパスワードが引数に残り暗号化も弱い (ZipCrypto) ので、 7z で AES-256 にする
+		(_,ArcType::Zip) if d.pw.password.is_some() => {
+			archive = tmp.join_str("archive.zip");
+
+			get_pi(&ip,&d.keep_path,&d.base)?.into_iter().map(|(p,i)| {
+				let mut c = Cmd::new_cwd("7z",vs(["a","-ba","-tzip","-mem=AES256"]),p);
+				c.args.extend([
+					OsString::from(format!("-mx={}",d.rate)),
+					OsString::from(&archive),i
+				]);
+				if d.verbose { c.args.push(OsString::from("-bb1")); }
+				c.inherit_output = d.verbose;
+				c
+			}).collect()
+		},
 		(_,ArcType::Zip) => {
 			archive = tmp.join_str("archive.zip");
 
-			get_pi(&ip,&d.keep_path).into_iter().map(|(p,i)| {
-				let mut c = Cmd::new_cwd("zip",vs(["-r","-x",".*","-x","__MACOSX"]),p);
+			get_pi(&ip,&d.keep_path,&d.base)?.into_iter().map(|(p,i)| {
+				let mut c = Cmd::new_cwd("zip",vs(["-r"]),p);
 				if !d.verbose { c.args.push(OsString::from("-q")); }
 				c.args.extend([
 					OsString::from(format!("-{}",d.rate)),
@@ -95,7 +216,7 @@ pub fn create(mut d:CreateData) {
 		(CreateType::Empty,ArcType::SevenZ) => {
 			archive = tmp.join_str("archive.7z");
 			let empty_dir = tmp.join_str("_");
-			if let Err(_) = fs::create_dir(&empty_dir) { error("アーカイブ作成ができませんでした"); }
+			if let Err(e) = fs::create_dir(&empty_dir) { return Err(ArchiverError::Failed(msg!("create-dir-failed",e))); }
 
 			[
 				vs(["a","-ba","-t7z","archive.7z","_"]),
@@ -109,32 +230,50 @@ pub fn create(mut d:CreateData) {
 		(_,ArcType::SevenZ) => {
 			archive = tmp.join_str("archive.7z");
 
-			get_pi(&ip,&d.keep_path).into_iter().map(|(p,i)| {
-				let mut c = Cmd::new_cwd("7z",vs(["a","-ba","-t7z","-xr!.*"]),p);
+			let volume = d.split_size.as_ref().map(split_size).transpose()?;
+			let l = group_pi(get_pi(&ip,&d.keep_path,&d.base)?).into_iter().map(|(p,i)| {
+				let mut c = Cmd::new_cwd("7z",vs(["a","-ba","-t7z"]),p);
+				if let Some(s) = volume { c.args.push(OsString::from(format!("-v{}b",s))); }
 				c.args.extend([
 					OsString::from(format!("-mx={}",d.rate)),
-					OsString::from(&archive),i
+					OsString::from(&archive)
 				]);
+				c.args.extend(i);
+				if d.verbose { c.args.push(OsString::from("-bb1")); }
 				c.inherit_output = d.verbose;
 				c
-			}).collect()
+			}).collect::<Vec<Cmd>>();
+			// 分割した 7z アーカイブには後から追加できない
+			if d.split_size.is_some() {
+				if l.len()>1 { return Err(ArchiverError::Unsupported(msg!("split-single"))); }
+				use_temp_position = false;
+			}
+			l
 		},
 		(CreateType::Empty,ArcType::Rar) => {
-			error("空の RAR アーカイブは作成できません");
-			panic!();
+			return Err(ArchiverError::Unsupported(msg!("empty-rar")));
 		},
 		(_,ArcType::Rar) => {
 			archive = tmp.join_str("archive.rar");
 
-			get_pi(&ip,&d.keep_path).into_iter().map(|(p,i)| {
+			let volume = d.split_size.as_ref().map(split_size).transpose()?;
+			let l = group_pi(get_pi(&ip,&d.keep_path,&d.base)?).into_iter().map(|(p,i)| {
 				let mut c = Cmd::new_cwd("rar",vs(["a","-r"]),p);
 				if !d.verbose { c.args.push(OsString::from("-inul")); }
+				if let Some(s) = volume { c.args.push(OsString::from(format!("-v{}b",s))); }
 				c.args.extend([
 					OsString::from(format!("-m{}",d.rate)),
-					OsString::from(&archive),i
+					OsString::from(&archive)
 				]);
+				c.args.extend(i);
 				c
-			}).collect()
+			}).collect::<Vec<Cmd>>();
+			// 分割した RAR アーカイブには後から追加できない
+			if d.split_size.is_some() {
+				if l.len()>1 { return Err(ArchiverError::Unsupported(msg!("split-single"))); }
+				use_temp_position = false;
+			}
+			l
 		},
 		(CreateType::Empty,ArcType::Aar) => {
 			archive = tmp.join_str("archive.aar");
@@ -171,7 +310,7 @@ pub fn create(mut d:CreateData) {
 		(CreateType::Empty,ArcType::Wim) => {
 			archive = tmp.join_str("image.wim");
 			let empty_dir = tmp.join_str("_");
-			if let Err(_) = fs::create_dir(&empty_dir) { error("アーカイブ作成ができませんでした"); }
+			if let Err(e) = fs::create_dir(&empty_dir) { return Err(ArchiverError::Failed(msg!("create-dir-failed",e))); }
 
 			vec![
 				Cmd::new_cwd(
@@ -190,8 +329,8 @@ pub fn create(mut d:CreateData) {
 		},
 		(CreateType::Empty,ArcType::Dmg) => {
 			use_temp_position = false;
-			let empty_dir = tmp.join_str(d.image_name);
-			if let Err(_) = fs::create_dir(&empty_dir) { error("アーカイブ作成ができませんでした"); }
+			let empty_dir = tmp.join_str(&d.image_name);
+			if let Err(e) = fs::create_dir(&empty_dir) { return Err(ArchiverError::Failed(msg!("create-dir-failed",e))); }
 
 			let mut c = Cmd::new(
 				"hdiutil",
@@ -227,8 +366,8 @@ pub fn create(mut d:CreateData) {
 		},
 		(CreateType::Empty,ArcType::Iso) => {
 			use_temp_position = false;
-			let empty_dir = tmp.join_str(d.image_name);
-			if let Err(_) = fs::create_dir(&empty_dir) { error("アーカイブ作成ができませんでした"); }
+			let empty_dir = tmp.join_str(&d.image_name);
+			if let Err(e) = fs::create_dir(&empty_dir) { return Err(ArchiverError::Failed(msg!("create-dir-failed",e))); }
 
 			let mut c = Cmd::new(
 				"hdiutil",
@@ -252,7 +391,7 @@ pub fn create(mut d:CreateData) {
 		(CreateType::Empty,ArcType::Lha) => {
 			archive = tmp.join_str("archive.lhz");
 			let empty_dir = tmp.join_str("_");
-			if let Err(_) = fs::create_dir(&empty_dir) { error("アーカイブ作成ができませんでした"); }
+			if let Err(e) = fs::create_dir(&empty_dir) { return Err(ArchiverError::Failed(msg!("create-dir-failed",e))); }
 
 			let mut c = Cmd::new_cwd(
 				"lha",
@@ -266,7 +405,7 @@ pub fn create(mut d:CreateData) {
 		(_,ArcType::Lha) => {
 			archive = tmp.join_str("archive.lhz");
 
-			get_pi(&ip,&d.keep_path).into_iter().map(|(p,i)| {
+			get_pi(&ip,&d.keep_path,&d.base)?.into_iter().map(|(p,i)| {
 				let mut c = Cmd::new_cwd("lha",vs(["-a"]),p);
 				if !d.verbose { c.args.push(OsString::from("-q")); }
 				c.args.extend([OsString::from(&archive),i]);
@@ -274,21 +413,19 @@ pub fn create(mut d:CreateData) {
 			}).collect()
 		},
 		(CreateType::Empty,ArcType::Zpaq) => {
-			error("空の ZPAQ アーカイブは作成できません");
-			panic!();
+			return Err(ArchiverError::Unsupported(msg!("empty-zpaq")));
 		},
 		(_,ArcType::Zpaq) => {
 			archive = tmp.join_str("archive.zpaq");
 
-			get_pi(&ip,&d.keep_path).into_iter().map(|(p,i)| {
+			get_pi(&ip,&d.keep_path,&d.base)?.into_iter().map(|(p,i)| {
 				let mut c = Cmd::new_cwd("zpaq",vs(["a"]),p);
 				c.args.extend([OsString::from(&archive),i]);
 				c
 			}).collect()
 		},
 		(_,ArcType::Wim)|(_,ArcType::Dmg)|(_,ArcType::Iso) => {
-			error("WIM/DMG/ISO は単一のフォルダから作成することができます。");
-			panic!();
+			return Err(ArchiverError::Unsupported(msg!("image-single-folder")));
 		},
 		(CreateType::Empty,ArcType::Cpio) => {
 			archive = tmp.join_str("archive.cpio");
@@ -304,12 +441,17 @@ pub fn create(mut d:CreateData) {
 		(CreateType::SingleFile,ArcType::Cpio)|(CreateType::SingleDir,ArcType::Cpio) => {
 			archive = tmp.join_str("archive.cpio");
 
-			let v = get_pi(&ip,&d.keep_path);
+			let v = get_pi(&ip,&d.keep_path,&d.base)?;
+			// cpio にはファイル名を標準入力から渡す
+			let name = match v[0].1.to_str() {
+				Some(n) => n.to_string(),
+				None => { return Err(ArchiverError::Usage(msg!("path-not-utf8",v[0].1.to_string_lossy()))); }
+			};
 			let mut c = Cmd::new_cwd_stdin(
 				"cpio",
 				vs(["--create","--null","-O","archive.cpio"]),
 				v[0].0.as_path().to_path_buf(),
-				v[0].1.to_str().unwrap().to_string()
+				name
 			);
 			if !d.verbose { c.args.push(OsString::from("--quiet")); }
 			vec![c]
@@ -317,49 +459,120 @@ pub fn create(mut d:CreateData) {
 		(CreateType::Multiple,ArcType::Cpio) => {
 			archive = tmp.join_str("archive.cpio");
 
+			let v = get_pi(&ip,&true,&d.base)?;
 			let mut c = Cmd::new_cwd_stdin(
 				"cpio",
 				vs(["--create","--null","-O"]),
-				cd,
-				d.input.join("\0")
+				v.first().map(|(p,_)| p.clone() ).unwrap_or(cd),
+				v.iter().map(|(_,i)| i.to_string_lossy() ).collect::<Vec<_>>().join("\0")
 			);
 			if !d.verbose { c.args.push(OsString::from("--quiet")); }
 			c.args.push(OsString::from(&archive));
 			vec![c]
 		},
 		// tar と圧縮系をここに集約
-		(ct,at) => tar_or_compress(ct,at,&ip,&d.rate,&d.keep_path,&mut archive,&tmp)
+		(ct,at) => {
+			let mut l = tar_or_compress(ct,at,&ip,d,&mut archive,&tmp,&mut pipe)?;
+			if d.verbose {
+				for c in l.iter_mut().filter(|c| c.prog=="bsdtar" ) { c.args.insert(0,OsString::from("-v")); }
+			}
+			l
+		}
 	};
 
+	// 暗号化する場合はパスワードを渡す
+	if let Some(pw) = d.pw.password.as_ref().filter(|_| !empty ) {
+		for c in cl.iter_mut() { add_password_args(c,pw,&tmp,true)?; }
+	}
+
+	// 保存する属性を指定し、進行状況を表示する場合は bsdtar から処理中のファイルを受け取る
+	for c in cl.iter_mut() {
+		add_preserve_args(c,&d.preserve,false);
+		progress::file_events(c);
+	}
+
 	// コマンドを実行
-	if several_cmd(cl) {
-		if use_temp_position {
-			if !Path::new(&archive).is_file() {
-				error("アーカイブは作成されていません。");
-			}
-			if let Err(_) = fs::hard_link(&archive,&d.output) {
-				if let Err(_) = fs::copy(&archive,&d.output) {
-					error("アーカイブの保存に失敗しました。");
-				}
+	match pipe {
+		true => piped_cmd(cl)?,
+		false => several_cmd(cl)?
+	};
+	if split_on_create(arc_type) && d.split_size.is_some() { move_volumes(&tmp,arc_type,&d.output)?; }
+	if use_temp_position {
+		if !Path::new(&archive).is_file() {
+			return Err(ArchiverError::Failed(msg!("not-created")));
+		}
+		if fs::hard_link(&archive,&d.output).is_err() {
+			if let Err(e) = fs::copy(&archive,&d.output) {
+				return Err(ArchiverError::Failed(msg!("save-failed",e)));
 			}
 		}
 	}
-	else { error("アーカイブの作成に失敗しました"); }
 
-	tmp.close().expect("作業ディレクトリが完全には削除されませんでした");
+	close_dir(tmp)?;
+	return Ok(());
 
 }
 
+/// tar アーカイブを作成して圧縮し、標準出力に書き出すコマンド (パイプで作成できない種類は None)
+fn stdout_cmds(d:&CreateData,arc_type:&ArcType) -> Option<Vec<Cmd>> {
+	let ip = d.input.iter().map(Path::new).collect::<Vec<_>>();
+	let ct = create_type(&ip);
+	let threads = thread_count(&d.threads);
+	let mut l = vec![];
+	match (ct,arc_type) {
+		// 単一ファイルの圧縮
+		(CreateType::SingleFile,at) if is_compress(at) => {
+			let mut c = compress_stdout_cmd(at,&d.rate,threads)?;
+			c.args.push(OsString::from(&d.input[0]));
+			l.push(c);
+		},
+		(ct,at) if *at==ArcType::Tar || is_compress(at) => {
+			l.push(tar_cmd(&ct,&ip,&d.keep_path,&d.base,OsStr::new("-")).ok()?);
+			if *at!=ArcType::Tar { l.push(compress_stdout_cmd(at,&d.rate,threads)?); }
+		},
+		_ => { return None; }
+	}
+	if !l.iter().all(|c| which(&c.prog).is_ok() ) { return None; }
+	for c in l.iter_mut() {
+		c.inherit_output = true;
+		add_preserve_args(c,&d.preserve,false);
+	}
+	return Some(l);
+}
+
+/// 保存できる属性を判定する種類 (圧縮系のフォーマットで tar アーカイブを作成する場合は tar)
+fn preserve_type(d:&CreateData,arc_type:&ArcType) -> ArcType {
+	let ip = d.input.iter().map(Path::new).collect::<Vec<_>>();
+	return match create_type(&ip) {
+		CreateType::SingleFile => *arc_type,
+		_ if is_compress(arc_type) => ArcType::Tar,
+		_ => *arc_type
+	};
+}
+
+/// 同じディレクトリで実行する入力ファイルを1つのコマンドにまとめる
+fn group_pi(pi:Vec<(PathBuf,OsString)>) -> Vec<(PathBuf,Vec<OsString>)> {
+	let mut l:Vec<(PathBuf,Vec<OsString>)> = vec![];
+	for (p,i) in pi {
+		match l.last_mut() {
+			Some((q,v)) if *q==p => v.push(i),
+			_ => l.push((p,vec![i]))
+		}
+	}
+	return l;
+}
+
 /// (カレントディレクトリ,入力ファイル) のペアに変換
-fn get_pi(i:&Vec<&Path>,keep_path:&bool) -> Vec<(PathBuf,OsString)> {
+/// --keep-path の場合、 base の中の入力ファイルは base からの相対パスにする
+pub fn get_pi(i:&Vec<&Path>,keep_path:&bool,base:&Option<PathBuf>) -> Result<Vec<(PathBuf,OsString)>> {
 
 	match *keep_path {
 		true => {
 			i.iter().map( |p| {
-				(
-					Path::new(".").absolute_path(),
-					p.to_path_buf().into_os_string()
-				)
+				Ok(match base.as_ref().and_then(|b| p.strip_prefix(b).ok().map(|r| (b,r)) ) {
+					Some((b,r)) => (b.clone(),r.as_os_str().to_os_string()),
+					None => (Path::new(".").absolute_path(),p.to_path_buf().into_os_string())
+				})
 			}).collect()
 		},
 		false => {
@@ -367,22 +580,22 @@ fn get_pi(i:&Vec<&Path>,keep_path:&bool) -> Vec<(PathBuf,OsString)> {
 				let dir = p.parent();
 				let base = p.file_name();
 				match (dir,base) {
-					(Some(d),Some(b)) => (d.absolute_path(),b.to_os_string()),
-					_ => {
-						error("パスが存在しません");
-						panic!();
-					}
+					(Some(d),Some(b)) => Ok((d.absolute_path(),b.to_os_string())),
+					_ => Err(ArchiverError::Usage(msg!("path-no-name",p.to_string_lossy())))
 				}
 			}).collect()
 		}
 	}
 }
 
-/// tar アーカイブ / 圧縮
+/// tar アーカイブ / 圧縮 (pipe が true になった場合はコマンドをパイプで繋いで実行する)
 fn tar_or_compress(
 	ct:CreateType,at:ArcType,
-	i:&Vec<&Path>,rate:&u8,keep_path:&bool,archive:&mut OsString,tmp:&TempDir
-) -> Vec<Cmd> {
+	i:&Vec<&Path>,d:&CreateData,archive:&mut OsString,tmp:&TempDir,pipe:&mut bool
+) -> Result<Vec<Cmd>> {
+
+	let rate = &d.rate;
+	let threads = thread_count(&d.threads);
 
 	// 単一ファイルの圧縮の場合とそうでない場合に分離
 	let compress = match (&ct,&at) {
@@ -392,19 +605,28 @@ fn tar_or_compress(
 	};
 
 	if compress {
-		let v = get_pi(i,keep_path);
-		let src_name = v[0].1.as_os_str().to_str().unwrap();
-		let dst_name = format!("{}.{}",&src_name,compress_ext(&at));
+		let v = get_pi(i,&d.keep_path,&d.base)?;
+		let src_name = &v[0].1;
+		let mut dst_name = src_name.clone();
+		dst_name.push(format!(".{}",compress_ext(&at)?));
 		let src = i[0].to_path_buf();
-		let src_tmp = tmp.join_str(&src_name);
+		let src_tmp = tmp.join_str(src_name);
 		let dst_tmp = tmp.join_str(&dst_name);
 		*archive = dst_tmp;
-		if let Err(_) = fs::hard_link(&src,&src_tmp) {
-			if let Err(_) = fs::copy(&src,&src_tmp) {
-				error("圧縮が開始できませんでした。");
+
+		// 並列に圧縮する場合は元のファイルを直接読み込む
+		if threads>1 {
+			if let Some(mut c) = compress_stdout_cmd(&at,rate,threads) {
+				c.args.push(src.absolute_path().into_os_string());
+				c.stdout = Some(archive.to_string_lossy().to_string());
+				return Ok(vec![c]);
 			}
 		}
 
+		if fs::hard_link(&src,&src_tmp).is_err() && fs::copy(&src,&src_tmp).is_err() {
+			return Err(ArchiverError::Failed(msg!("compress-start-failed")));
+		}
+
 		// 圧縮
 		let mut c = match &at {
 			ArcType::Compress => Cmd::new("compress",vs(["-f",                              ])),
@@ -419,81 +641,182 @@ fn tar_or_compress(
 			ArcType::Rzip     => Cmd::new("rzip"    ,vs(["-k",          &format!("-{}",rate)])),
 			ArcType::Zstd     => Cmd::new("zstd"    ,vs(["-z","-q",     &format!("-{}",rate)])),
 			ArcType::Brotli   => Cmd::new("brotli"  ,vs([          "-q",&format!( "{}",rate)])),
-			ArcType::Lzfse    => Cmd::new("aa"      ,vs(["archive","-o",&dst_name,"-i"      ])),
-			_ => { panic!(); }
+			ArcType::Lzfse    => {
+				let mut c = Cmd::new("aa",vs(["archive","-o"]));
+				c.args.extend([dst_name,OsString::from("-i")]);
+				c
+			},
+			_ => { return Err(ArchiverError::Unsupported(msg!("unsupported-compress"))); }
 		};
 		c.args.push(OsString::from(src_name));
 		c.cwd = tmp.path().to_path_buf();
-		vec![c]
+		return Ok(vec![c]);
 	}
 	else {
-		*archive = tmp.join_str("archive.tar");
+		// 圧縮できるコマンドがあれば、 tar アーカイブを作業ディレクトリに書き出さずにパイプで渡す
+		if at!=ArcType::Tar {
+			if let Some(mut c) = compress_stdout_cmd(&at,rate,threads) {
+				*archive = tmp.join_str(format!("archive.tar.{}",compress_ext(&at)?));
+				c.stdout = Some(archive.to_string_lossy().to_string());
+				*pipe = true;
+				return Ok(vec![tar_cmd(&ct,i,&d.keep_path,&d.base,OsStr::new("-"))?,c]);
+			}
+		}
 
 		// tar アーカイブの部分
-		let mut l = match &ct {
-			CreateType::Empty => {
-				vec![
-					Cmd::new_cwd(
-						"bsdtar",
-						vs(["-c","-f","archive.tar","-T","/dev/null"]),
-						tmp.path().to_path_buf()
-					)
-				]
-			},
-			_ => {
-				get_pi(i,keep_path).into_iter().enumerate().map(|(index,(p,i))| {
-					let mut c = Cmd::new_cwd_env(
-						"bsdtar",
-						match index {
-							0 => vs(["-c","-f"]),
-							_ => vs(["-r","-f"])
-						},
-						p,
-						[("COPYFILE_DISABLE","1")]
-					);
-					c.args.extend([OsString::from(&archive),i]);
-					c
-				}).collect::<Vec<Cmd>>()
-			}
-		};
+		*archive = tmp.join_str("archive.tar");
+		let mut l = vec![tar_cmd(&ct,i,&d.keep_path,&d.base,archive)?];
 
 		// 圧縮の部分
 		match &at {
 			ArcType::Tar => {},
 			_ => {
-				let mut c = match &at {
-					ArcType::Compress => Cmd::new("compress",vs(["-f",                                  ])),
-					ArcType::Gzip     => Cmd::new("gzip"    ,vs([                 &format!("-{}",rate)  ])),
-					ArcType::Bzip2    => Cmd::new("bzip2"   ,vs(["-z",            &format!("-{}",rate)  ])),
-					ArcType::Xz       => Cmd::new("xz"      ,vs(["-z",            &format!("-{}",rate)  ])),
-					ArcType::Lzip     => Cmd::new("lzip"    ,vs([                 &format!("-{}",rate)  ])),
-					ArcType::Lzma     => Cmd::new("lzma"    ,vs(["-z",            &format!("-{}",rate)  ])),
-					ArcType::Lz4      => Cmd::new("lz4"     ,vs(["-z","-q","--rm",&format!("-{}",rate)  ])),
-					ArcType::Lzop     => Cmd::new("lzop"    ,vs(["-U",            &format!("-{}",rate)  ])),
-					ArcType::Lrzip    => Cmd::new("lrzip"   ,vs(["-q","-D",  "-L",&format!( "{}",rate)  ])),
-					ArcType::Rzip     => Cmd::new("rzip"    ,vs(["-U",            &format!("-{}",rate)  ])),
-					ArcType::Zstd     => Cmd::new("zstd"    ,vs(["-z","-q","--rm",&format!("-{}",rate)  ])),
-					ArcType::Brotli   => Cmd::new("brotli"  ,vs(["--rm",     "-q",&format!( "{}",rate)  ])),
-					ArcType::Lzfse    => Cmd::new("aa"      ,vs(["archive","-o","archive.tar.lzfse","-i"])),
-					_ => { panic!(); }
-				};
-				c.args.extend(vs(["archive.tar"]));
+				let mut c = tar_compress_cmd(&at,rate,"archive.tar")?;
 				*archive = tmp.join_str(
-					format!("archive.tar.{}",compress_ext(&at))
+					format!("archive.tar.{}",compress_ext(&at)?)
 				);
 				c.cwd = tmp.path().to_path_buf();
+				// tar アーカイブの作成より圧縮に時間がかかるので、圧縮で読み込んだ量を進行状況とする
+				l[0].track = false;
 				l.push(c);
 			}
 		}
 
-		return l;
+		return Ok(l);
 	}
 
 }
 
-enum CreateType {
+/// 入力ファイルから tar アーカイブを作成して dst (- は標準出力) に書き出すコマンド
+/// 入力ファイルごとのカレントディレクトリは -C で切り替える
+fn tar_cmd(ct:&CreateType,i:&Vec<&Path>,keep_path:&bool,base:&Option<PathBuf>,dst:&OsStr) -> Result<Cmd> {
+	let cd = cwd()?;
+	let mut c = Cmd::new_cwd_env("bsdtar",vs(["-c","-f"]),cd,[("COPYFILE_DISABLE","1")]);
+	c.args.push(dst.to_os_string());
+	match ct {
+		CreateType::Empty => { c.args.extend(vs(["-T","/dev/null"])); },
+		_ => {
+			for (p,i) in get_pi(i,keep_path,base)? {
+				c.args.extend([OsString::from("-C"),p.into_os_string(),i]);
+			}
+		}
+	}
+	return Ok(c);
+}
+
+/// tar アーカイブ file を file.拡張子 に圧縮するコマンド (file のあるディレクトリを cwd にして使用)
+pub fn tar_compress_cmd(at:&ArcType,rate:&u8,file:&str) -> Result<Cmd> {
+	let mut c = match at {
+		ArcType::Compress => Cmd::new("compress",vs(["-f",                                  ])),
+		ArcType::Gzip     => Cmd::new("gzip"    ,vs([                 &format!("-{}",rate)  ])),
+		ArcType::Bzip2    => Cmd::new("bzip2"   ,vs(["-z",            &format!("-{}",rate)  ])),
+		ArcType::Xz       => Cmd::new("xz"      ,vs(["-z",            &format!("-{}",rate)  ])),
+		ArcType::Lzip     => Cmd::new("lzip"    ,vs([                 &format!("-{}",rate)  ])),
+		ArcType::Lzma     => Cmd::new("lzma"    ,vs(["-z",            &format!("-{}",rate)  ])),
+		ArcType::Lz4      => Cmd::new("lz4"     ,vs(["-z","-q","-m","--rm",&format!("-{}",rate)])),
+		ArcType::Lzop     => Cmd::new("lzop"    ,vs(["-U",            &format!("-{}",rate)  ])),
+		ArcType::Lrzip    => Cmd::new("lrzip"   ,vs(["-q","-D",  "-L",&format!( "{}",rate)  ])),
+		ArcType::Rzip     => Cmd::new("rzip"    ,vs(["-U",            &format!("-{}",rate)  ])),
+		ArcType::Zstd     => Cmd::new("zstd"    ,vs(["-z","-q","--rm",&format!("-{}",rate)  ])),
+		ArcType::Brotli   => Cmd::new("brotli"  ,vs(["--rm",     "-q",&format!( "{}",rate)  ])),
+		ArcType::Lzfse    => Cmd::new("aa"      ,vs(["archive","-o",&format!("{}.lzfse",file),"-i"])),
+		_ => { return Err(ArchiverError::Unsupported(msg!("unsupported-compress"))); }
+	};
+	c.args.push(OsString::from(file));
+	return Ok(c);
+}
+
+/// 標準入力 (或いは末尾に追加したファイル) を圧縮して標準出力に書き出すコマンド (None は非対応)
+/// threads が 2 以上の場合は並列に圧縮できるコマンドを優先する
+pub fn compress_stdout_cmd(at:&ArcType,rate:&u8,threads:usize) -> Option<Cmd> {
+	if threads>1 {
+		if let Some(c) = parallel_compress_cmd(at,rate,threads) { return Some(c); }
+	}
+	return Some(match at {
+		ArcType::Compress => Cmd::new("compress",vs(["-c",                 ])),
+		ArcType::Gzip     => Cmd::new("gzip"    ,vs(["-c",     &format!("-{}",rate)])),
+		ArcType::Bzip2    => Cmd::new("bzip2"   ,vs(["-z","-c",&format!("-{}",rate)])),
+		ArcType::Xz       => Cmd::new("xz"      ,vs(["-z","-c",&format!("-{}",rate)])),
+		ArcType::Lzip     => Cmd::new("lzip"    ,vs(["-c",     &format!("-{}",rate)])),
+		ArcType::Lzma     => Cmd::new("lzma"    ,vs(["-z","-c",&format!("-{}",rate)])),
+		ArcType::Lz4      => Cmd::new("lz4"     ,vs(["-z","-q","-c",&format!("-{}",rate)])),
+		ArcType::Lzop     => Cmd::new("lzop"    ,vs(["-c",     &format!("-{}",rate)])),
+		ArcType::Zstd     => Cmd::new("zstd"    ,vs(["-z","-q","-c",&format!("-{}",rate)])),
+		ArcType::Brotli   => Cmd::new("brotli"  ,vs(["-c","-q",&format!( "{}",rate)])),
+		_ => { return None; }
+	});
+}
+
+/// 並列に圧縮して標準出力に書き出すコマンド (使えるコマンドがなければ None)
+fn parallel_compress_cmd(at:&ArcType,rate:&u8,threads:usize) -> Option<Cmd> {
+	let n = threads.to_string();
+	let r = format!("-{}",rate);
+	return Some(match at {
+		ArcType::Gzip  if which("pigz").is_ok()   => Cmd::new("pigz"  ,vs(["-c","-p",&n,&r])),
+		ArcType::Bzip2 if which("pbzip2").is_ok() => Cmd::new("pbzip2",vs(["-c",&format!("-p{}",n),&r])),
+		ArcType::Bzip2 if which("lbzip2").is_ok() => Cmd::new("lbzip2",vs(["-c","-n",&n,&r])),
+		ArcType::Xz    if which("xz").is_ok()     => Cmd::new("xz"    ,vs(["-z","-c","-T",&n,&r])),
+		ArcType::Zstd  if which("zstd").is_ok()   => Cmd::new("zstd"  ,vs(["-z","-q","-c",&format!("-T{}",n),&r])),
+		ArcType::Lzip  if which("plzip").is_ok()  => Cmd::new("plzip" ,vs(["-c","-n",&n,&r])),
+		// lz4 は 1.10 以降でブロックごとに並列に圧縮できる
+		ArcType::Lz4   if lz4_supports_threads()  => Cmd::new("lz4"   ,vs(["-z","-q","-c",&format!("-T{}",n),&r])),
+		_ => { return None; }
+	});
+}
+
+/// 並列に圧縮できるコマンドがあるか
+pub fn has_parallel_compressor(at:&ArcType) -> bool {
+	return parallel_compress_cmd(at,&6,2).is_some();
+}
+
+fn lz4_supports_threads() -> bool {
+	return match Command::new("lz4").arg("-H").stdin(Stdio::null()).output() {
+		Ok(o) => String::from_utf8_lossy(&o.stdout).contains("-T#") || String::from_utf8_lossy(&o.stderr).contains("-T#"),
+		Err(_) => false
+	};
+}
+
+/// 実行モードを選択 (単一ファイル,複数ファイルアーカイブ,空のアーカイブ)
+pub fn create_type(ip:&[&Path]) -> CreateType {
+	return match ip.len() {
+		0 => CreateType::Empty,
+		1 => {
+			match ip[0] {
+				p if p.is_file() && !p.is_symlink() => CreateType::SingleFile,
+				p if p.is_dir()  && !p.is_symlink() => CreateType::SingleDir,
+				_ => CreateType::Multiple
+			}
+		},
+		_ => CreateType::Multiple
+	};
+}
+
+pub enum CreateType {
 	SingleFile,
 	SingleDir,
 	Multiple,
 	Empty
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[derive(Parser)]
+	struct T {
+		#[clap(flatten)]
+		d: CreateData
+	}
+
+	#[test]
+	fn failed_create_keeps_existing_archive() {
+		let dir = tempdir().unwrap();
+		let input = dir.path().join("in.txt").to_string_lossy().to_string();
+		let output = dir.path().join("keep.zip").to_string_lossy().to_string();
+		fs::write(&input,"a").unwrap();
+		fs::write(&output,"old").unwrap();
+		let d = T::parse_from(["create","--overwrite=always","-r","12","-o",&output,&input]).d;
+		assert!(matches!(create(d),Err(ArchiverError::Usage(_))));
+		assert_eq!(fs::read_to_string(&output).unwrap(),"old");
+		assert_eq!(fs::read_dir(dir.path()).unwrap().count(),2);
+	}
+}
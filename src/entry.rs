@@ -0,0 +1,270 @@
+use crate::library::*;
+use crate::args::*;
+use crate::message::text;
+
+/// アーカイブ内のファイルの情報 (取得できないものは None)
+pub struct Entry {
+	pub path: String,
+	pub kind: EntryKind,
+	pub size: Option<u64>,
+	pub compressed_size: Option<u64>,
+	/// パーミッション (種類のビットは含まない)
+	pub mode: Option<u32>,
+	/// 更新日時 (UNIX 時間)
+	pub mtime: Option<i64>,
+	pub uid: Option<u32>,
+	pub gid: Option<u32>,
+	/// シンボリックリンク,ハードリンクのリンク先
+	pub link: Option<String>,
+	/// "crc32:xxxxxxxx" のように種類をつけたチェックサム
+	pub checksum: Option<String>,
+	pub encrypted: bool
+}
+impl Entry {
+	pub fn new(path:String) -> Self {
+		return Entry {
+			path,
+			kind: EntryKind::File,
+			size: None,
+			compressed_size: None,
+			mode: None,
+			mtime: None,
+			uid: None,
+			gid: None,
+			link: None,
+			checksum: None,
+			encrypted: false
+		};
+	}
+}
+
+#[derive(Clone,Copy,PartialEq)]
+pub enum EntryKind {
+	File,
+	Dir,
+	Symlink,
+	HardLink,
+	Other
+}
+impl EntryKind {
+	pub fn name(&self) -> &'static str {
+		return match self {
+			EntryKind::File     => "file",
+			EntryKind::Dir      => "dir",
+			EntryKind::Symlink  => "symlink",
+			EntryKind::HardLink => "hardlink",
+			EntryKind::Other    => "other"
+		};
+	}
+	/// st_mode の種類のビットから判定
+	pub fn from_mode(mode:u32) -> Self {
+		return match mode & 0o170000 {
+			0o040000 => EntryKind::Dir,
+			0o120000 => EntryKind::Symlink,
+			0o100000|0 => EntryKind::File,
+			_ => EntryKind::Other
+		};
+	}
+}
+
+/// 一覧を指定した形式で出力する
+pub fn print_entries(l:&[Entry],format:&ListFormat) {
+	match format {
+		ListFormat::Table => {
+			println!("{}",table_row([text("list.mode"),text("list.owner"),text("list.size"),text("list.compressed"),text("list.mtime"),text("list.path")]));
+			for e in l.iter() {
+				let owner = match (e.uid,e.gid) {
+					(Some(u),Some(g)) => format!("{}/{}",u,g),
+					_ => String::from("-")
+				};
+				let mtime = match e.mtime {
+					Some(t) => {
+						let (y,mo,d,h,mi,_) = utc_datetime(t);
+						format!("{:04}-{:02}-{:02} {:02}:{:02}",y,mo,d,h,mi)
+					},
+					None => String::from("-")
+				};
+				let mut path = e.path.clone();
+				if let Some(l) = &e.link { path = format!("{} -> {}",path,l); }
+				if e.encrypted { path = msg!("list.encrypted",path); }
+				println!("{}",table_row([&mode_string(e),&owner,&opt(&e.size),&opt(&e.compressed_size),&mtime,&path]));
+			}
+		},
+		ListFormat::Json => {
+			println!("[");
+			for (i,e) in l.iter().enumerate() {
+				println!("\t{}{}",json_entry(e),if i+1<l.len() { "," } else { "" });
+			}
+			println!("]");
+		},
+		ListFormat::Ndjson => {
+			for e in l.iter() { println!("{}",json_entry(e)); }
+		},
+		ListFormat::Csv => {
+			println!("path,type,size,compressed_size,mode,mtime,uid,gid,link,checksum,encrypted");
+			for e in l.iter() {
+				println!(
+					"{},{},{},{},{},{},{},{},{},{},{}",
+					csv_quote(&e.path),e.kind.name(),csv_opt(&e.size),csv_opt(&e.compressed_size),
+					e.mode.map(|m| format!("{:04o}",m) ).unwrap_or_default(),
+					e.mtime.map(iso_time).unwrap_or_default(),
+					csv_opt(&e.uid),csv_opt(&e.gid),
+					e.link.as_ref().map(|s| csv_quote(s) ).unwrap_or_default(),
+					e.checksum.clone().unwrap_or_default(),e.encrypted
+				);
+			}
+		}
+	}
+}
+
+/// 表の1行 (全角文字は2文字分の幅として揃える)
+fn table_row(cols:[&str;6]) -> String {
+	let pad = |s:&str,w:usize,right:bool| {
+		let p = " ".repeat(w.saturating_sub(display_width(s)));
+		if right { p+s } else { s.to_string()+&p }
+	};
+	return [
+		pad(cols[0],10,false),pad(cols[1],11,true),pad(cols[2],12,true),
+		pad(cols[3],12,true),pad(cols[4],16,false),cols[5].to_string()
+	].join(" ");
+}
+
+/// ls -l と同様の形式のパーミッション
+fn mode_string(e:&Entry) -> String {
+	let t = match e.kind {
+		EntryKind::File     => '-',
+		EntryKind::Dir      => 'd',
+		EntryKind::Symlink  => 'l',
+		EntryKind::HardLink => 'h',
+		EntryKind::Other    => '?'
+	};
+	let p = match e.mode {
+		Some(m) => {
+			(0..9).map(|i| {
+				if m & (0o400>>i)==0 { '-' } else { ['r','w','x'][i%3] }
+			}).collect::<String>()
+		},
+		None => String::from("?????????")
+	};
+	return format!("{}{}",t,p);
+}
+
+fn opt<T:std::fmt::Display>(v:&Option<T>) -> String {
+	return match v {
+		Some(v) => v.to_string(),
+		None => String::from("-")
+	};
+}
+
+fn csv_opt<T:std::fmt::Display>(v:&Option<T>) -> String {
+	return match v {
+		Some(v) => v.to_string(),
+		None => String::new()
+	};
+}
+
+fn csv_quote(s:&str) -> String {
+	if s.contains([',','"','\n','\r']) { return format!("\"{}\"",s.replace('"',"\"\"")); }
+	return s.to_string();
+}
+
+/// ISO 8601 形式の UTC の日時
+fn iso_time(t:i64) -> String {
+	let (y,mo,d,h,mi,s) = utc_datetime(t);
+	return format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",y,mo,d,h,mi,s);
+}
+
+fn json_entry(e:&Entry) -> String {
+	fn json_opt<T:std::fmt::Display>(v:&Option<T>) -> String {
+		return match v {
+			Some(v) => v.to_string(),
+			None => String::from("null")
+		};
+	}
+	fn json_opt_str(v:Option<String>) -> String {
+		return match v {
+			Some(v) => json_str(&v),
+			None => String::from("null")
+		};
+	}
+	return format!(
+		"{{\"path\":{},\"type\":\"{}\",\"size\":{},\"compressed_size\":{},\"mode\":{},\"mtime\":{},\"uid\":{},\"gid\":{},\"link\":{},\"checksum\":{},\"encrypted\":{}}}",
+		json_str(&e.path),e.kind.name(),json_opt(&e.size),json_opt(&e.compressed_size),
+		json_opt_str(e.mode.map(|m| format!("{:04o}",m) )),json_opt_str(e.mtime.map(iso_time)),
+		json_opt(&e.uid),json_opt(&e.gid),json_opt_str(e.link.clone()),json_opt_str(e.checksum.clone()),e.encrypted
+	);
+}
+
+/// bsdtar が出力する mtree 形式 (use-set なし) を読み込む
+pub fn parse_mtree(s:&str) -> Vec<Entry> {
+	let mut l = vec![];
+	for line in s.lines() {
+		if line.starts_with('#') || line.trim().is_empty() { continue; }
+		let mut words = line.split(' ');
+		let path = mtree_unvis(words.next().unwrap_or(""));
+		let mut e = Entry::new(path.trim_start_matches("./").to_string());
+		for w in words {
+			let (k,v) = match w.split_once('=') {
+				Some(kv) => kv,
+				None => { continue; }
+			};
+			match k {
+				"type" => {
+					e.kind = match v {
+						"file" => EntryKind::File,
+						"dir"  => EntryKind::Dir,
+						"link" => EntryKind::Symlink,
+						_      => EntryKind::Other
+					};
+				},
+				"mode" => { e.mode = u32::from_str_radix(v,8).ok(); },
+				"uid"  => { e.uid = v.parse().ok(); },
+				"gid"  => { e.gid = v.parse().ok(); },
+				"size" => { e.size = v.parse().ok(); },
+				"time" => { e.mtime = v.split('.').next().and_then(|t| t.parse().ok() ); },
+				"link" => { e.link = Some(mtree_unvis(v)); },
+				"sha256digest" => { e.checksum = Some(format!("sha256:{}",v)); },
+				_ => {}
+			}
+		}
+		l.push(e);
+	}
+	return l;
+}
+
+/// mtree のエスケープ (\ と3桁の8進数) を元に戻す
+fn mtree_unvis(s:&str) -> String {
+	let b = s.as_bytes();
+	let mut r:Vec<u8> = vec![];
+	let mut i = 0;
+	while i<b.len() {
+		if b[i]==b'\\' && i+3<b.len() && b[i+1..i+4].iter().all(|c| (b'0'..=b'7').contains(c) ) {
+			r.push(((b[i+1]-b'0')<<6) | ((b[i+2]-b'0')<<3) | (b[i+3]-b'0'));
+			i += 4;
+		}
+		else {
+			r.push(b[i]);
+			i += 1;
+		}
+	}
+	return String::from_utf8_lossy(&r).to_string();
+}
+
+/// 各ツールの出力から取得できなかった情報を other から同じパスのもので補う
+pub fn fill_missing(l:&mut [Entry],other:Vec<Entry>) {
+	for e in l.iter_mut() {
+		let key = e.path.trim_end_matches('/');
+		let o = match other.iter().find(|o| o.path.trim_end_matches('/')==key ) {
+			Some(o) => o,
+			None => { continue; }
+		};
+		if e.mode.is_none()  { e.mode = o.mode; }
+		if e.mtime.is_none() { e.mtime = o.mtime; }
+		if e.uid.is_none()   { e.uid = o.uid; }
+		if e.gid.is_none()   { e.gid = o.gid; }
+		if e.size.is_none()  { e.size = o.size; }
+		if e.link.is_none()  { e.link = o.link.clone(); }
+		if e.checksum.is_none() { e.checksum = o.checksum.clone(); }
+		if e.kind==EntryKind::File && o.kind!=EntryKind::File { e.kind = o.kind; }
+	}
+}
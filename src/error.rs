@@ -0,0 +1,102 @@
+use std::fmt;
+use std::io;
+use crate::progress;
+use crate::library::cleanup_on_error;
+
+/// 処理の失敗の種類 (種類ごとに終了コードが決まっている)
+#[derive(Debug)]
+pub enum ArchiverError {
+	/// 引数や指定が正しくない
+	Usage(String),
+	/// 入力ファイルやアーカイブが存在しない
+	NotFound(String),
+	/// 必要な外部コマンドが見つからない
+	MissingTool(String),
+	/// アーカイブの種類や操作に対応していない
+	Unsupported(String),
+	/// アーカイブが壊れているか読み込めない
+	Corrupt(String),
+	/// 安全でないファイルを含むか展開の上限を超える
+	Unsafe(String),
+	/// 外部コマンドが失敗した (stderr は表示しなかった標準エラー出力)
+	Command { message:String, stderr:String },
+	/// その他の失敗 (ファイルの読み書きなど)
+	Failed(String)
+}
+
+pub type Result<T> = std::result::Result<T,ArchiverError>;
+
+impl ArchiverError {
+	/// 終了コード (ヘルプの exit-codes の一覧と合わせる)
+	pub fn exit_code(&self) -> i32 {
+		return match self {
+			ArchiverError::Failed(_)|ArchiverError::Command{..} => 1,
+			ArchiverError::Usage(_)|ArchiverError::NotFound(_) => 2,
+			ArchiverError::MissingTool(_) => 3,
+			ArchiverError::Unsupported(_) => 4,
+			ArchiverError::Corrupt(_) => 5,
+			ArchiverError::Unsafe(_) => 6
+		};
+	}
+
+	/// 外部コマンドの失敗に何をしていたかを加える (それ以外の失敗はそのまま)
+	pub fn context(self,message:&str) -> Self {
+		return match self {
+			ArchiverError::Command{message:m,stderr} => ArchiverError::Command { message:format!("{} ({})",message,m), stderr },
+			e => e
+		};
+	}
+
+	/// 外部コマンドの失敗をアーカイブの破損として扱う (一覧の表示や検査で読み込めなかった場合)
+	pub fn corrupt(self,message:&str) -> Self {
+		return match self {
+			ArchiverError::Command{message:m,stderr} => ArchiverError::Corrupt(with_stderr(&format!("{} ({})",message,m),&stderr)),
+			ArchiverError::Failed(m) => ArchiverError::Corrupt(format!("{} ({})",message,m)),
+			e => e
+		};
+	}
+}
+
+impl fmt::Display for ArchiverError {
+	fn fmt(&self,f:&mut fmt::Formatter) -> fmt::Result {
+		return match self {
+			ArchiverError::Usage(m)|ArchiverError::NotFound(m)|ArchiverError::MissingTool(m)|
+			ArchiverError::Unsupported(m)|ArchiverError::Corrupt(m)|ArchiverError::Unsafe(m)|ArchiverError::Failed(m) => write!(f,"{}",m),
+			ArchiverError::Command{message,stderr} => write!(f,"{}",with_stderr(message,stderr))
+		};
+	}
+}
+
+impl std::error::Error for ArchiverError {}
+
+/// ネイティブ実装などでの読み書きの失敗 (データが正しくないものは破損として扱う)
+impl From<io::Error> for ArchiverError {
+	fn from(e:io::Error) -> Self {
+		return match e.kind() {
+			io::ErrorKind::NotFound => ArchiverError::NotFound(msg!("file-not-found",e)),
+			io::ErrorKind::InvalidInput => ArchiverError::Usage(e.to_string()),
+			io::ErrorKind::Unsupported => ArchiverError::Unsupported(e.to_string()),
+			io::ErrorKind::InvalidData|io::ErrorKind::UnexpectedEof => ArchiverError::Corrupt(msg!("archive-unreadable",e)),
+			_ => ArchiverError::Failed(e.to_string())
+		};
+	}
+}
+
+/// メッセージの後ろに標準エラー出力を字下げして加える
+fn with_stderr(message:&str,stderr:&str) -> String {
+	let mut s = message.to_string();
+	for l in stderr.trim_end().lines() {
+		s.push_str("\n  ");
+		s.push_str(l);
+	}
+	return s;
+}
+
+/// 失敗した場合は進行状況の表示を終え、登録された作業ディレクトリを削除する
+pub fn finish<T>(r:Result<T>) -> Result<T> {
+	if r.is_err() {
+		progress::abort();
+		cleanup_on_error();
+	}
+	return r;
+}
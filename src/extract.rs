@@ -1,184 +1,589 @@
-use std::env::current_dir;
 use std::fs;
+use which::which;
 use crate::library::*;
 use crate::args::*;
+use crate::error::*;
+use crate::backend::*;
+use crate::password::*;
+use crate::volume::join_volumes;
+use crate::native::native_contains_tar;
+use crate::filter::*;
+use crate::progress;
+use crate::preserve::*;
+use crate::safety::*;
+use crate::overwrite::*;
+use crate::message::text;
 
-pub fn extract(d:ExtractData) {
+pub fn extract(mut d:ExtractData) -> Result<()> {
+
+	// 展開するファイルの絞り込みの条件
+	let filter = Filter::new(&d.filter,&[],false)?;
+
+	let tmp = work_dir()?;
+	let (input,name) = match is_stdio(&d.input) {
+		// 標準入力はパイプで展開できるものはそのまま展開し、それ以外は作業ディレクトリに保存してから扱う
+		true => {
+			if let (Some(at),false,true) = (d.arc_type,d.backend==Some(BackendType::Native),filter.is_empty()) {
+				if let Some(r) = extract_stdin(&d,&at)? {
+					return r.map_err(|e| e.context(text("extract-failed")) );
+				}
+			}
+			(spool_stdin(&tmp)?,d.input.clone())
+		},
+		false => {
+			// アーカイブが存在することを確認
+			if !Path::new(&d.input).is_file() {
+				return Err(ArchiverError::NotFound(msg!("file-not-found",d.input)));
+			}
+			// 分割されたアーカイブは全てのボリュームを1つにまとめて読み込む
+			join_volumes(&d.input,&tmp)?
+		}
+	};
+	d.input = input;
 
 	// アーカイブの種類を判定
 	let arc_type = match d.arc_type {
 		Some(t) => t,
 		None => {
-			match guess_type(&d.input,true) {
+			match detect_type(&d.input,true) {
 				Some(t) => t,
-				None => ArcType::Tar
+				None => {
+					return Err(ArchiverError::Unsupported(msg!("unknown-type")));
+				}
 			}
 		}
 	};
 
-	// アーカイブが存在することを確認
-	if !Path::new(&d.input).is_file() {
-		error("ファイルが存在しません");
+	// 圧縮された tar アーカイブは解凍と同時に展開する
+	let untar = is_compress(&arc_type) && !d.no_untar && contains_tar(&arc_type,&d.input);
+
+	// 暗号化されている場合はパスワードを用意する
+	load_password(&mut d.pw)?;
+	ask_password(&mut d.pw,&arc_type,&d.input)?;
+
+	// 保存先の存在を確認 (圧縮ファイルの解凍先が既に存在する場合は --overwrite に従う)
+	let archive = untar || !is_compress(&arc_type);
+	let output = check_output(&name,&d.output,if untar { &ArcType::Tar } else { &arc_type })?;
+	let output = match archive {
+		true => output,
+		false => match resolve_output(&d,output)? {
+			Some(o) => o,
+			None => { return Ok(()); }
+		}
+	};
+
+	// アーカイブは展開先の中の作業ディレクトリに展開してから、絞り込みの条件を満たすものを --overwrite に従って移動する
+	// 既存のファイルの扱いを外部コマンドに任せないので、どの種類でも同じように扱える
+	if !archive && !filter.is_empty() {
+		eprintln!("{}",text("filter-ignored"));
+	}
+	let stage = match archive {
+		true => Some(stage_in(&output)?),
+		false => None
+	};
+	let dst = match &stage {
+		Some(s) => s.path().to_string_lossy().to_string(),
+		None => output.clone()
+	};
+
+	// 実装を選択して実行 (進行状況はアーカイブを読み込んだ量で表す)
+	let backend = select_backend(&arc_type,&password_backend(&d.pw,&arc_type,&d.backend),Operation::Extract)?;
+	warn_unsupported(&d.preserve,if untar { &ArcType::Tar } else { &arc_type },backend.native(),true);
+	let total = fs::metadata(&d.input).map(|m| m.len() ).unwrap_or(0);
+
+	// 展開する前にアーカイブ内のファイルを確認し、展開中は展開先の大きさを監視する (圧縮ファイルは展開中の大きさだけを確認する)
+	let limits = Limits::new(&d.safety,total)?;
+	if archive { check_archive(&d.input,&arc_type,untar,&d.pw.password,&d.safety,&limits,&tmp)?; }
+	let watchdog = match is_stdio(&dst) {
+		true => None,
+		false => watch(PathBuf::from(&dst),limits)
+	};
+
+	let progress = progress::start(&d.progress,d.verbose,"extract",total);
+	let r = backend.extract(&d,&arc_type,untar,&dst);
+	// 上限を超えて中止した場合はコマンドの失敗より先に報告する
+	let r = match watchdog {
+		Some(w) => w.stop().and(r),
+		None => r
+	};
+	if let Some(p) = progress { p.finish(r.is_ok()); }
+	r.map_err(|e| e.context(text("extract-failed")) )?;
+	if let Some(s) = stage { move_stage(s,&output,&filter,d.overwrite,d.smart.then(|| smart_name(&name,&arc_type) ))?; }
+	close_dir(tmp)?;
+	return Ok(());
+
+}
+
+/// 展開先の中に作業ディレクトリを作成する (移動するだけで済むように同じファイルシステムに作る)
+fn stage_in(output:&String) -> Result<TempDir> {
+	let s = match tempfile::Builder::new().prefix(".archiver-").tempdir_in(output) {
+		Ok(s) => s,
+		Err(e) => { return Err(ArchiverError::Failed(msg!("tmp-dir-failed",e))); }
+	};
+	remove_on_error(s.path());
+	return Ok(s);
+}
+
+/// 作業ディレクトリに展開したものを展開先に移動する
+/// --smart の場合は smart にまとめるフォルダの名前を指定する
+fn move_stage(stage:TempDir,output:&String,filter:&Filter,policy:Overwrite,smart:Option<String>) -> Result<()> {
+	let mut conflict = Conflict::new(policy);
+	let r = match smart {
+		Some(name) => move_smart(stage.path(),Path::new(output),filter,&name,&mut conflict),
+		None => move_filtered(stage.path(),Path::new(output),filter,&mut conflict)
+	};
+	if let Err(e) = r { return Err(ArchiverError::Failed(msg!("move-extracted-failed",e))); }
+	conflict.report();
+	keep_on_error(stage.path());
+	close_dir(stage)?;
+	return Ok(());
+}
+
+/// ルートに複数のものがあれば name のフォルダにまとめて移動し、1つだけならそのまま移動する (どちらも既存のものと重なれば別名にする)
+fn move_smart(stage:&Path,output:&Path,filter:&Filter,name:&str,conflict:&mut Conflict) -> std::io::Result<()> {
+	// 除外したものを数えないように、先に展開先の中の別の作業ディレクトリに絞り込んで移動する
+	let filtered = match filter.is_empty() {
+		true => None,
+		false => {
+			let f = tempfile::Builder::new().prefix(".archiver-").tempdir_in(output)?;
+			remove_on_error(f.path());
+			move_filtered(stage,f.path(),filter,conflict)?;
+			Some(f)
+		}
+	};
+	let dir = filtered.as_ref().map(|f| f.path() ).unwrap_or(stage);
+	let all = Filter::new(&FilterData::default(),&[],false).map_err(std::io::Error::other)?;
+	let top = fs::read_dir(dir)?.filter_map(|e| e.ok() ).map(|e| e.file_name() ).collect::<Vec<_>>();
+	match top.len() {
+		0 => {},
+		1 => {
+			let dst = output.join(&top[0]);
+			if fs::symlink_metadata(&dst).is_ok() {
+				let is_dir = fs::symlink_metadata(dir.join(&top[0]))?.is_dir();
+				let r = rename_path(&dst,is_dir);
+				fs::rename(dir.join(&top[0]),dir.join(r.file_name().unwrap_or_default()))?;
+				eprintln!("{}",msg!("extract-into",r.to_string_lossy()));
+			}
+			move_filtered(dir,output,&all,conflict)?;
+		},
+		_ => {
+			let mut dst = output.join(name);
+			if fs::symlink_metadata(&dst).is_ok() {
+				dst = rename_path(&dst,true);
+				eprintln!("{}",msg!("extract-into",dst.to_string_lossy()));
+			}
+			fs::create_dir(&dst)?;
+			move_filtered(dir,&dst,&all,conflict)?;
+		}
+	}
+	if let Some(f) = filtered {
+		keep_on_error(f.path());
+		f.close()?;
+	}
+	return Ok(());
+}
+
+/// --smart でまとめるフォルダの名前 (アーカイブの名前から compress_remove_ext と同じように拡張子を除いたもの)
+fn smart_name(input:&String,arc_type:&ArcType) -> String {
+	let file = Path::new(input).file_name().unwrap_or_default().to_string_lossy().to_string();
+	if is_stdio(input) || file.is_empty() { return String::from("archive"); }
+	let stem = match is_compress(arc_type) {
+		// 圧縮ファイルは中身の tar アーカイブの拡張子も除く (既知の拡張子でなければそのまま使う)
+		true => {
+			let s = compress_remove_ext(&file,arc_type);
+			let s = if s==format!("{}.out",file) { file.clone() } else { s };
+			s.strip_suffix(".tar").unwrap_or(&s).to_string()
+		},
+		false => Path::new(&file).file_stem().unwrap_or_default().to_string_lossy().to_string()
+	};
+	return if !stem.is_empty() { stem } else { String::from("archive") };
+}
+
+/// 圧縮ファイルの解凍先が既に存在する場合の扱いを決める (解凍しない場合は None)
+fn resolve_output(d:&ExtractData,output:String) -> Result<Option<String>> {
+	if is_stdio(&output) { return Ok(Some(output)); }
+	let mut conflict = Conflict::new(d.overwrite);
+	let src_time = fs::metadata(&d.input).and_then(|m| m.modified() ).ok();
+	return match conflict.resolve(src_time,Path::new(&output),false) {
+		Action::Replace => {
+			if Path::new(&output).is_dir() { return Err(ArchiverError::Usage(msg!("folder-not-replaced",output))); }
+			if fs::symlink_metadata(&output).is_ok() && fs::remove_file(&output).is_err() {
+				return Err(ArchiverError::Failed(msg!("remove-existing-failed",output)));
+			}
+			Ok(Some(output))
+		},
+		Action::Skip => {
+			conflict.report();
+			Ok(None)
+		},
+		Action::Rename(r) => Ok(Some(r.to_string_lossy().to_string()))
+	};
+}
+
+/// 標準入力の tar アーカイブや圧縮ファイルをパイプで展開する (パイプで扱えない種類は None)
+/// 圧縮ファイルは中身を確認できないので、 --no-untar が指定されていなければ tar アーカイブとして扱う
+/// パイプで展開した場合は Some に展開の結果を入れて返す
+/// tar アーカイブは --unsafe の場合のみパイプで展開し、展開中の大きさは監視する
+fn extract_stdin(d:&ExtractData,arc_type:&ArcType) -> Result<Option<Result<()>>> {
+	let untar = matches!(arc_type,ArcType::Tar|ArcType::Cpio) || (is_compress(arc_type) && !d.no_untar);
+	if !untar && !is_compress(arc_type) { return Ok(None); }
+	// 大きさの比の上限はアーカイブの大きさが分からないと確認できないので、作業ディレクトリに保存してから扱う
+	if d.safety.max_ratio.is_some() { return Ok(None); }
+	// tar アーカイブは展開前に中身を確認するため、 --unsafe を指定しない限り作業ディレクトリに保存してから扱う
+	if untar && !d.safety.allow_unsafe { return Ok(None); }
+	let limits = Limits::new(&d.safety,0)?;
+	let mut l = vec![];
+	if is_compress(arc_type) {
+		match decompress_stdin_cmd(arc_type) {
+			Some(c) => l.push(c),
+			None => { return Ok(None); }
+		}
+	}
+	let mut stage = None;
+	if untar {
+		let output = check_output(&d.input,&d.output,&ArcType::Tar)?;
+		let s = stage_in(&output)?;
+		let mut c = Cmd::new("bsdtar",vs(["-x","-f","-","-C"]));
+		c.args.push(s.path().as_os_str().to_os_string());
+		stage = Some((s,output));
+		if d.verbose { c.args.push(OsString::from("-v")); }
+		c.args.extend(vs(d.target.iter()));
+		add_preserve_args(&mut c,&d.preserve,true);
+		l.push(c);
 	}
+	else {
+		// 単一ファイルは -o で指定したファイルか標準出力に解凍する
+		let output = match resolve_output(d,check_output(&d.input,&d.output,arc_type)?)? {
+			Some(o) => o,
+			None => { return Ok(Some(Ok(()))); }
+		};
+		if !is_stdio(&output) { l[0].stdout = Some(output); }
+	}
+	for c in l.iter_mut() { c.inherit_output = true; }
+	if !l.iter().all(|c| which(&c.prog).is_ok() ) { return Ok(None); }
+	// 展開先 (作業ディレクトリか解凍先のファイル) の大きさを監視する
+	let watchdog = match (&stage,&l.last().and_then(|c| c.stdout.clone() )) {
+		(Some((s,_)),_) => watch(s.path().to_path_buf(),limits),
+		(None,Some(o)) if !untar => watch(PathBuf::from(o),limits),
+		_ => None
+	};
+	// 標準入力は大きさが分からないので読み込んだ量だけを表示する
+	let progress = progress::start(&d.progress,d.verbose,"extract",0);
+	for c in l.iter_mut() { progress::file_events(c); }
+	let r = piped_cmd(l);
+	let r = match watchdog {
+		Some(w) => w.stop().and(r),
+		None => r
+	};
+	if let Some(p) = progress { p.finish(r.is_ok()); }
+	if let (Some((s,output)),true) = (stage,r.is_ok()) { move_stage(s,&output,&Filter::new(&d.filter,&[],false)?,d.overwrite,d.smart.then(|| smart_name(&d.input,arc_type) ))?; }
+	return Ok(Some(r));
+}
+
+/// 外部コマンドによる展開
+pub fn extract_external(d:&ExtractData,arc_type:&ArcType,untar:bool,output:&str) -> Result<()> {
 
-	// 保存先の存在を確認
-	let output = check_output(&d.input,&d.output,&arc_type);
+	let tmp = work_dir()?;
 
-	let tmp = tempdir().expect("作業ディレクトリが作成できませんでした");
+	// パスワードがあればコマンドに渡し、復元する属性を指定する
+	let with_password = |mut c:Cmd| -> Result<Cmd> {
+		if let Some(pw) = &d.pw.password { add_password_args(&mut c,pw,&tmp,false)?; }
+		add_preserve_args(&mut c,&d.preserve,true);
+		return Ok(c);
+	};
+	let with_preserve = |mut c:Cmd| {
+		add_preserve_args(&mut c,&d.preserve,true);
+		c
+	};
 
 	// コマンドを実行
-	match arc_type {
+	let r = match *arc_type {
+		at if untar => {
+			let mut args = vs(["-x","-C",output]);
+			if d.verbose { args.push(OsString::from("-v")); }
+			args.extend(vs(tar_args(&d.preserve,true)));
+			untar_compressed(&at,&d.input,args,&d.target,&tmp)
+		},
+		// unzip にはパスワードを安全に渡せないので 7z で展開する
+		ArcType::Zip if d.pw.password.is_some() => {
+			let mut c = Cmd::new("7z",vs(["x","-ba",&d.input,&format!("-o{}",output)]));
+			if d.verbose { c.args.push(OsString::from("-bb1")); }
+			for t in d.target.iter() {
+				c.args.push(OsString::from(&t));
+			}
+			several_cmd(vec![with_password(c)?])
+		},
 		ArcType::Zip => {
-			let mut c = Cmd::new("unzip",vs(["-q","-d",&output,&d.input]));
+			// 作業ディレクトリに展開するので、同じ名前が重複している場合だけ後のもので置き換える
+			let mut c = Cmd::new("unzip",vs(["-o","-d",output,&d.input]));
+			if !d.verbose { c.args.insert(0,OsString::from("-q")); }
 			for t in d.target.iter() {
 				c.args.push(OsString::from(&t));
 			}
-			if !several_cmd(vec![c]) { error("展開に失敗しました"); }
+			several_cmd(vec![with_password(c)?])
 		},
 		ArcType::SevenZ => {
 			let mut c = Cmd::new("7z",vs(["x","-ba",&d.input,&format!("-o{}",output)]));
+			if d.verbose { c.args.push(OsString::from("-bb1")); }
 			for t in d.target.iter() {
 				c.args.push(OsString::from(&t));
 			}
-			if !several_cmd(vec![c]) { error("展開に失敗しました"); }
+			several_cmd(vec![with_password(c)?])
 		},
 		ArcType::Tar|ArcType::Cpio => {
-			let mut c = Cmd::new("bsdtar",vs(["-x","-f",&d.input,"-C",&output]));
+			// 読み込み量を数えるためにアーカイブは標準入力に流し込む
+			let mut c = Cmd::new("bsdtar",vs(["-x","-f","-","-C",output]));
+			c.input = Some(PathBuf::from(&d.input));
+			if d.verbose { c.args.push(OsString::from("-v")); }
 			for t in d.target.iter() {
 				c.args.push(OsString::from(&t));
 			}
-			if !several_cmd(vec![c]) { error("展開に失敗しました"); }
+			progress::file_events(&mut c);
+			several_cmd(vec![with_preserve(c)])
 		},
 		ArcType::Rar => {
-			let mut c = Cmd::new("rar",vs(["x","-inul",&d.input,&output]));
+			let mut c = Cmd::new("rar",vs(["x",&d.input,output]));
+			if !d.verbose { c.args.insert(1,OsString::from("-inul")); }
 			for t in d.target.iter() {
 				c.args.push(OsString::from(&t));
 			}
-			if !several_cmd(vec![c]) { error("展開に失敗しました"); }
+			several_cmd(vec![with_password(c)?])
 		},
 		ArcType::Aar => {
-			let mut c = Cmd::new("aa",vs(["extract","-i",&d.input,"-d",&output]));
+			let mut c = Cmd::new("aa",vs(["extract","-i",&d.input,"-d",output]));
+			if d.verbose { c.args.push(OsString::from("-v")); }
 			for t in d.target.iter() {
-				c.args.extend(vs(["-include-path",&t]));
+				c.args.extend(vs(["-include-path",t]));
 			}
-			if !several_cmd(vec![c]) { error("展開に失敗しました"); }
+			several_cmd(vec![with_password(c)?])
 		},
 		ArcType::Wim => {
-			if d.target.len()>0 {
-				eprintln!("WIM では --target フラグは無視されます");
+			if !d.target.is_empty() {
+				eprintln!("{}",text("wim-target-ignored"));
 			}
-			let c = Cmd::new("wimapply",vs([&d.input,&output]));
-			if !several_cmd(vec![c]) { error("展開に失敗しました"); }
+			let c = Cmd::new("wimapply",vs([&d.input,output]));
+			several_cmd(vec![with_preserve(c)])
 		},
 		ArcType::Zpaq => {
-			let mut c = Cmd::new("zpaq",vs(["x",&d.input,"-to",&output]));
+			let mut c = Cmd::new("zpaq",vs(["x",&d.input,"-to",output]));
 			for t in d.target.iter() {
 				c.args.push(OsString::from(&t));
 			}
-			if !several_cmd(vec![c]) { error("展開に失敗しました"); }
+			several_cmd(vec![c])
 		},
 		ArcType::Lha => {
-			let mut c = Cmd::new("lha",vs(["-x","-q",&d.input,"-w",&output]));
+			let mut c = Cmd::new("lha",vs(["-x",&d.input,"-w",output]));
+			if !d.verbose { c.args.insert(1,OsString::from("-q")); }
 			for t in d.target.iter() {
 				c.args.push(OsString::from(&t));
 			}
-			if !several_cmd(vec![c]) { error("展開に失敗しました"); }
+			several_cmd(vec![c])
 		},
 		ArcType::Dmg|ArcType::Iso => {
-			error("このファイルは展開に対応していません");
+			Err(ArchiverError::Unsupported(msg!("unsupported-extract")))
+		},
+		// 標準出力に解凍する (読み込み量を数えるために圧縮ファイルは標準入力に流し込む)
+		at if is_stdio(output) => {
+			let mut c = match decompress_stdin_cmd(&at) {
+				Some(c) => c,
+				None => {
+					return Err(ArchiverError::Unsupported(msg!("stdout-decompress-unsupported")));
+				}
+			};
+			c.input = Some(PathBuf::from(&d.input));
+			c.inherit_output = true;
+			several_cmd(vec![c])
+		},
+		// 大きさを監視する場合は解凍しながら保存先に書き出す
+		at if d.safety.has_limits() && decompress_stdin_cmd(&at).is_some() => {
+			let mut c = decompress_stdin_cmd(&at).ok_or_else(|| ArchiverError::Unsupported(msg!("stdout-decompress-unsupported")) )?;
+			c.input = Some(PathBuf::from(&d.input));
+			c.stdout = Some(output.to_string());
+			several_cmd(vec![c])
 		},
 		_ => {
 			let src_name = OsString::from(
-				format!("file.{}",compress_ext(&arc_type))
+				format!("file.{}",compress_ext(arc_type)?)
 			);
 			let src_tmp = tmp.path().join(&src_name);
 			let dst_tmp = tmp.path().join("file");
-			if let Err(_) = fs::hard_link(&d.input,&src_tmp) {
-				if let Err(_) = fs::copy(&d.input,&src_tmp) {
-					error("解凍が開始できませんでした。");
+			if fs::hard_link(&d.input,&src_tmp).is_err() {
+				if let Err(e) = fs::copy(&d.input,&src_tmp) {
+					return Err(ArchiverError::Failed(msg!("decompress-start-failed",e)));
 				}
 			}
 
-			let mut c = match &arc_type {
-				ArcType::Compress => Cmd::new("uncompress",vs([     "-f"])),
-				ArcType::Gzip     => Cmd::new("gzip"      ,vs(["-d","-f"])),
-				ArcType::Bzip2    => Cmd::new("bzip2"     ,vs(["-d","-f"])),
-				ArcType::Xz       => Cmd::new("xz"        ,vs(["-d","-f"])),
-				ArcType::Lzip     => Cmd::new("lzip"      ,vs(["-d",    ])),
-				ArcType::Lzma     => Cmd::new("lzma"      ,vs(["-d","-f"])),
-				ArcType::Lz4      => Cmd::new("lz4"       ,vs(["-d","-q"])),
-				ArcType::Lzop     => Cmd::new("lzop"      ,vs(["-d"     ])),
-				ArcType::Lrzip    => Cmd::new("lrzip"     ,vs(["-d","-q"])),
-				ArcType::Rzip     => Cmd::new("rzip"      ,vs(["-d"     ])),
-				ArcType::Zstd     => Cmd::new("zstd"      ,vs(["-d","-q"])),
-				ArcType::Brotli   => Cmd::new("brotli"    ,vs(["-d","-q"])),
-				ArcType::Lzfse    => Cmd::new("aa"        ,vs(["extract","-o","file","-i","file.lzfse"])),
-				_ => { panic!(); }
-			};
+			let mut c = decompress_cmd(arc_type)?;
 			c.args.push(src_name);
 			c.cwd = tmp.path().to_path_buf();
 
-			if several_cmd(vec![c]) {
-				if !dst_tmp.is_file() {
-					error("解凍に失敗しました");
-				}
-				if let Err(_) = fs::rename(&dst_tmp,&output) {
-					if let Err(_) = fs::copy(&dst_tmp,&output) {
-						error("解凍ファイルの保存に失敗しました。");
-					}
+			several_cmd(vec![c])?;
+			if !dst_tmp.is_file() {
+				return Err(ArchiverError::Failed(msg!("decompress-failed")));
+			}
+			if fs::rename(&dst_tmp,output).is_err() {
+				if let Err(e) = fs::copy(&dst_tmp,output) {
+					return Err(ArchiverError::Failed(msg!("decompress-save-failed",e)));
 				}
 			}
-			else { error("解凍に失敗しました"); }
+			Ok(())
 		}
 	};
 
-	tmp.close().expect("作業ディレクトリが完全には削除されませんでした");
+	close_dir(tmp)?;
+	return r;
+
+}
 
+/// 圧縮ファイルを解凍するコマンド (引数の末尾に file.<拡張子> を追加して使用し、 file に解凍される)
+pub fn decompress_cmd(arc_type:&ArcType) -> Result<Cmd> {
+	return Ok(match arc_type {
+		ArcType::Compress => Cmd::new("uncompress",vs([     "-f"])),
+		ArcType::Gzip     => Cmd::new("gzip"      ,vs(["-d","-f"])),
+		ArcType::Bzip2    => Cmd::new("bzip2"     ,vs(["-d","-f"])),
+		ArcType::Xz       => Cmd::new("xz"        ,vs(["-d","-f"])),
+		ArcType::Lzip     => Cmd::new("lzip"      ,vs(["-d",    ])),
+		ArcType::Lzma     => Cmd::new("lzma"      ,vs(["-d","-f"])),
+		ArcType::Lz4      => Cmd::new("lz4"       ,vs(["-d","-q","-m"])),
+		ArcType::Lzop     => Cmd::new("lzop"      ,vs(["-d"     ])),
+		ArcType::Lrzip    => Cmd::new("lrzip"     ,vs(["-d","-q"])),
+		ArcType::Rzip     => Cmd::new("rzip"      ,vs(["-d"     ])),
+		ArcType::Zstd     => Cmd::new("zstd"      ,vs(["-d","-q"])),
+		ArcType::Brotli   => Cmd::new("brotli"    ,vs(["-d","-q"])),
+		ArcType::Lzfse    => Cmd::new("aa"        ,vs(["extract","-o","file","-i","file.lzfse"])),
+		_ => { return Err(ArchiverError::Unsupported(msg!("unsupported-compress"))); }
+	});
 }
 
-fn check_output(input:&String,output:&Option<String>,arc_type:&ArcType) -> String {
-	match (output,arc_type) {
+/// 圧縮ファイルを標準出力に解凍するコマンド (パイプで解凍できないものは None)
+pub fn decompress_stdout_cmd(arc_type:&ArcType,file:&String) -> Option<Cmd> {
+	let mut c = decompress_stdin_cmd(arc_type)?;
+	c.args.push(OsString::from(file));
+	return Some(c);
+}
+
+/// 標準入力を標準出力に解凍するコマンド (パイプで解凍できないものは None)
+pub fn decompress_stdin_cmd(arc_type:&ArcType) -> Option<Cmd> {
+	return Some(match arc_type {
+		ArcType::Compress => Cmd::new("uncompress",vs([     "-c"])),
+		ArcType::Gzip     => Cmd::new("gzip"      ,vs(["-d","-c"])),
+		ArcType::Bzip2    => Cmd::new("bzip2"     ,vs(["-d","-c"])),
+		ArcType::Xz       => Cmd::new("xz"        ,vs(["-d","-c"])),
+		ArcType::Lzip     => Cmd::new("lzip"      ,vs(["-d","-c"])),
+		ArcType::Lzma     => Cmd::new("lzma"      ,vs(["-d","-c"])),
+		ArcType::Lz4      => Cmd::new("lz4"       ,vs(["-d","-c"])),
+		ArcType::Lzop     => Cmd::new("lzop"      ,vs(["-d","-c"])),
+		ArcType::Lrzip    => Cmd::new("lrzip"     ,vs(["-d","-q","-o","-"])),
+		ArcType::Zstd     => Cmd::new("zstd"      ,vs(["-d","-q","-c"])),
+		ArcType::Brotli   => Cmd::new("brotli"    ,vs(["-d","-c"])),
+		_ => { return None; }
+	});
+}
+
+/// 圧縮ファイルの中身が tar アーカイブであるかを判定
+/// 解凍するコマンドがなければネイティブ実装で判定し、それもできないものは拡張子から判定する
+pub fn contains_tar(arc_type:&ArcType,file:&String) -> bool {
+	match decompress_stdout_cmd(arc_type,file) {
+		Some(c) if which(&c.prog).is_ok() => is_tar_stream(c),
+		_ => native_contains_tar(arc_type,file).unwrap_or_else(|| guess_type(file,false)==Some(ArcType::Tar) )
+	}
+}
+
+/// 圧縮された tar アーカイブを解凍しながら bsdtar に渡す (パイプで解凍できないものは作業ディレクトリに解凍してから渡す)
+pub fn untar_compressed(arc_type:&ArcType,file:&String,tar_args:VS,targets:&[String],tmp:&TempDir) -> Result<()> {
+	let mut c = Cmd::new("bsdtar",tar_args);
+	progress::file_events(&mut c);
+	// 読み込み量を数えるために圧縮ファイルは標準入力に流し込む
+	match decompress_stdin_cmd(arc_type) {
+		Some(mut dc) => {
+			dc.input = Some(PathBuf::from(file));
+			c.args.extend(vs(["-f","-"]));
+			c.args.extend(vs(targets.iter()));
+			return piped_cmd(vec![dc,c]);
+		},
+		None => {
+			let src = tmp.join_str(format!("file.{}",compress_ext(arc_type)?));
+			if fs::hard_link(file,&src).is_err() {
+				if let Err(e) = fs::copy(file,&src) {
+					return Err(ArchiverError::Failed(msg!("decompress-start-failed",e)));
+				}
+			}
+			let tar = decompress_in_tmp(arc_type,tmp)?;
+			c.args.extend([OsString::from("-f"),tar]);
+			c.args.extend(vs(targets.iter()));
+			return several_cmd(vec![c]);
+		}
+	}
+}
+
+/// 作業ディレクトリ内の file.<拡張子> を file に解凍する (後で再圧縮する場合と衝突しないよう圧縮ファイルは削除する)
+pub fn decompress_in_tmp(arc_type:&ArcType,tmp:&TempDir) -> Result<OsString> {
+	let src = tmp.join_str(format!("file.{}",compress_ext(arc_type)?));
+	let dst = tmp.join_str("file");
+	let mut c = decompress_cmd(arc_type)?;
+	c.args.push(OsString::from(format!("file.{}",compress_ext(arc_type)?)));
+	c.cwd = tmp.path().to_path_buf();
+	several_cmd(vec![c]).map_err(|e| e.context(text("decompress-failed")) )?;
+	if Path::new(&src).exists() {
+		if let Err(e) = fs::remove_file(&src) { return Err(ArchiverError::Failed(format!("{}: {}",text("decompress-failed"),e))); }
+	}
+	if !Path::new(&dst).is_file() { return Err(ArchiverError::Failed(msg!("decompress-failed"))); }
+	return Ok(dst);
+}
+
+fn check_output(input:&String,output:&Option<String>,arc_type:&ArcType) -> Result<String> {
+	return Ok(match (output,arc_type) {
 		(oo,ArcType::Zip)|(oo,ArcType::SevenZ)|(oo,ArcType::Tar)|(oo,ArcType::Cpio)|(oo,ArcType::Rar)|(oo,ArcType::Aar)|(oo,ArcType::Wim)|(oo,ArcType::Zpaq)|(oo,ArcType::Lha) => {
 			match oo {
 				Some(o) => {
 					if !Path::new(o).is_dir() {
-						error("保存先のディレクトリが存在しません");
+						return Err(ArchiverError::NotFound(msg!("output-dir-not-found",o)));
 					}
 					o.to_string()
 				},
 				None => {
-					let cd = current_dir().expect("カレントディレクトリが存在しません");
-					cd.to_str().expect("カレントディレクトリに展開できません。パスに非対応の文字が含まれています").to_string()
+					match cwd()?.to_str() {
+						Some(cd) => cd.to_string(),
+						None => { return Err(ArchiverError::Usage(msg!("cwd-not-utf8"))); }
+					}
 				}
 			}
 		},
 		(_,ArcType::Dmg)|(_,ArcType::Iso) => {
-			error("ディスクイメージには対応していません");
-			panic!();
+			return Err(ArchiverError::Unsupported(msg!("disk-image-unsupported")));
 		},
 		(oo,at) => {
 			match oo {
+				// 標準入力からの解凍は、保存先を指定しなければ標準出力に書き出す
+				Some(o) if is_stdio(o) => o.to_string(),
+				None if is_stdio(input) => input.to_string(),
+				// フォルダを指定した場合はその中に解凍する (標準入力は元の名前が分からないので指定できない)
+				Some(o) if Path::new(o).is_dir() => {
+					if is_stdio(input) { return Err(ArchiverError::Usage(msg!("stdin-dest-folder",o))); }
+					let name = compress_remove_ext(input,at);
+					Path::new(o).join(Path::new(&name).file_name().unwrap_or_default()).to_string_lossy().to_string()
+				},
 				Some(o) => {
 					match Path::new(o).parent() {
 						Some(p) => {
-							if !p.is_dir() {
-								error("保存先が存在しません");
+							// ファイル名だけの場合は現在のディレクトリに保存する
+							if !p.as_os_str().is_empty() && !p.is_dir() {
+								return Err(ArchiverError::NotFound(msg!("dest-not-found")));
 							}
 							o.to_string()
 						}
 						None => {
-							error("保存先が正しくありません");
-							panic!();
+							return Err(ArchiverError::Usage(msg!("dest-invalid")));
 						}
 					}
 				},
 				None => compress_remove_ext(input,at)
 			}
 		}
-	}
+	});
 }
 
-fn compress_remove_ext(input:&String,arc_type:&ArcType) -> String {
+pub fn compress_remove_ext(input:&str,arc_type:&ArcType) -> String {
 	macro_rules! replace_suffix {
 		($suffix:expr,$repl:expr) => {
 			if let Some(s) = input.strip_suffix($suffix) { return s.to_string()+&$repl; }
@@ -234,5 +639,5 @@ fn compress_remove_ext(input:&String,arc_type:&ArcType) -> String {
 		},
 		_ => {}
 	}
-	return input.clone()+".out";
+	return input.to_string()+".out";
 }
\ No newline at end of file
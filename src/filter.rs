@@ -0,0 +1,469 @@
+use std::collections::HashMap;
+use std::fs::{self,File};
+use std::os::unix::fs::symlink;
+use crate::library::*;
+use crate::args::*;
+use crate::error::*;
+use crate::overwrite::*;
+
+/// 各フォルダに置くことができる除外の設定ファイル (gitignore と同じ書式)
+pub const IGNORE_FILE:&str = ".archiverignore";
+
+/// --exclude-vcs で除外するバージョン管理システムのファイル
+const VCS_PATTERNS:[&str;16] = [
+	".git/",".gitignore",".gitattributes",".gitmodules",
+	".svn/",".hg/",".hgignore",".hgtags",".bzr/",".bzrignore",
+	"CVS/",".cvsignore","RCS/","SCCS/","_darcs/",".pijul/"
+];
+
+/// アーカイブの種類ごとの既定の除外の条件 (zip と 7z は隠しファイルを除外する)
+pub fn default_excludes(at:&ArcType) -> &'static [&'static str] {
+	return match at {
+		ArcType::Zip|ArcType::SevenZ => &[".*","__MACOSX"],
+		_ => &[]
+	};
+}
+
+/// gitignore の書式の1行
+#[derive(Clone)]
+struct Rule {
+	pattern: Vec<char>,
+	negate: bool,
+	dir_only: bool,
+	/// パターンに / を含む場合は base からの相対パス全体と比較する (含まない場合はファイル名と比較する)
+	anchored: bool,
+	/// 設定ファイルが置かれたフォルダのアーカイブ内のパス
+	base: String
+}
+impl Rule {
+	fn parse(line:&str,base:&str) -> Option<Self> {
+		let mut s = line.trim_end_matches(['\r','\n']);
+		if !s.ends_with("\\ ") { s = s.trim_end_matches(' '); }
+		if s.is_empty() || s.starts_with('#') { return None; }
+		let negate = s.starts_with('!');
+		if negate { s = &s[1..]; }
+		if s.starts_with("\\#") || s.starts_with("\\!") { s = &s[1..]; }
+		let dir_only = s.ends_with('/');
+		let s = s.trim_end_matches('/');
+		let anchored = s.contains('/');
+		let s = s.trim_start_matches('/');
+		if s.is_empty() { return None; }
+		return Some(Rule {
+			pattern: s.chars().collect(),
+			negate,
+			dir_only,
+			anchored,
+			base: base.trim_end_matches('/').to_string()
+		});
+	}
+	fn matches(&self,name:&str,is_dir:bool) -> bool {
+		if self.dir_only && !is_dir { return false; }
+		// 設定ファイルが置かれたフォルダより下にあるものだけが対象になる
+		let rel = match self.base.len() {
+			0 => name,
+			_ => match name.strip_prefix(&self.base).and_then(|r| r.strip_prefix('/') ) {
+				Some(r) => r,
+				None => { return false; }
+			}
+		};
+		let text = match self.anchored {
+			true => rel,
+			false => rel.rsplit('/').next().unwrap_or(rel)
+		};
+		return glob_match(&self.pattern,&text.chars().collect::<Vec<_>>());
+	}
+}
+
+/// --include, --exclude などで指定した絞り込みの条件
+#[derive(Clone)]
+pub struct Filter {
+	/// 後のものほど優先する除外の条件 (先頭から順に 既定のもの, 設定ファイル, コマンドライン)
+	rules: Vec<Rule>,
+	/// コマンドラインで指定したものの開始位置 (設定ファイルの条件はこの前に挿入する)
+	cli_start: usize,
+	/// 指定されている場合は、いずれかに該当するファイルのみを対象にする
+	include: Vec<Rule>,
+	/// フォルダ内の設定ファイルを読み込むかどうか
+	read_ignore_files: bool
+}
+impl Filter {
+	/// defaults はアーカイブの種類ごとの既定の除外の条件
+	pub fn new(d:&FilterData,defaults:&[&str],read_ignore_files:bool) -> Result<Self> {
+		let mut rules = defaults.iter().filter_map(|p| Rule::parse(p,"") ).collect::<Vec<_>>();
+		if d.exclude_vcs {
+			rules.extend(VCS_PATTERNS.iter().filter_map(|p| Rule::parse(p,"") ));
+		}
+		let cli_start = rules.len();
+		for f in d.exclude_from.iter() {
+			match fs::read_to_string(f) {
+				Ok(s) => { rules.extend(s.lines().filter_map(|l| Rule::parse(l,"") )); },
+				Err(e) => { return Err(ArchiverError::Usage(msg!("exclude-from-unreadable",f,e))); }
+			}
+		}
+		rules.extend(d.exclude.iter().filter_map(|p| Rule::parse(p,"") ));
+		return Ok(Filter {
+			rules,
+			cli_start,
+			include: d.include.iter().filter_map(|p| Rule::parse(p,"") ).collect(),
+			read_ignore_files
+		});
+	}
+
+	/// 種類ごとの既定の除外の条件のみのもの (追加するファイルの絞り込みに使う)
+	pub fn defaults(at:&ArcType) -> Self {
+		let rules = default_excludes(at).iter().filter_map(|p| Rule::parse(p,"") ).collect::<Vec<_>>();
+		return Filter { cli_start: rules.len(), rules, include: vec![], read_ignore_files: false };
+	}
+
+	/// 条件が何も指定されていないか (設定ファイルは読み込んでみないと分からない)
+	pub fn is_empty(&self) -> bool {
+		return self.rules.len()==0 && self.include.len()==0 && !self.read_ignore_files;
+	}
+
+	/// フォルダ dir に設定ファイルがあれば、その条件を加えたものを返す (base は dir のアーカイブ内のパス)
+	fn enter(&self,dir:&Path,base:&str) -> Option<Filter> {
+		if !self.read_ignore_files { return None; }
+		let s = fs::read_to_string(dir.join(IGNORE_FILE)).ok()?;
+		let mut f = self.clone();
+		let added = s.lines().filter_map(|l| Rule::parse(l,base) ).collect::<Vec<_>>();
+		f.cli_start += added.len();
+		f.rules.splice(self.cli_start..self.cli_start,added);
+		return Some(f);
+	}
+
+	/// 除外するか (最後に該当した条件に従う)
+	pub fn excluded(&self,name:&str,is_dir:bool) -> bool {
+		return match self.rules.iter().rev().find(|r| r.matches(name,is_dir) ) {
+			Some(r) => !r.negate,
+			None => false
+		};
+	}
+
+	/// --include の条件を満たすか (フォルダは中身で判断するので常に満たす)
+	pub fn included(&self,name:&str,is_dir:bool) -> bool {
+		return is_dir || self.include.is_empty() || self.include.iter().any(|r| r.matches(name,false) );
+	}
+}
+
+/// 絞り込んだ結果の1項目
+struct Kept {
+	src: PathBuf,
+	name: String,
+	is_dir: bool
+}
+
+/// src 以下を絞り込む (除外したものがあれば changed を true にする)
+/// --include が指定されている場合は、該当するファイルを含まないフォルダも除外する
+fn walk(src:&Path,name:&str,filter:&Filter,l:&mut Vec<Kept>,changed:&mut bool) -> Result<bool> {
+	let m = match fs::symlink_metadata(src) {
+		Ok(m) => m,
+		Err(e) => {
+			return Err(ArchiverError::Failed(msg!("read-file-failed",src.to_string_lossy(),e)));
+		}
+	};
+	let is_dir = m.is_dir();
+	if filter.excluded(name,is_dir) || !filter.included(name,is_dir) {
+		*changed = true;
+		return Ok(false);
+	}
+	l.push(Kept { src: src.to_path_buf(), name: name.to_string(), is_dir });
+	if !is_dir { return Ok(true); }
+
+	let index = l.len()-1;
+	let entered = filter.enter(src,name);
+	let filter = entered.as_ref().unwrap_or(filter);
+	let mut children = match fs::read_dir(src) {
+		Ok(rd) => rd.filter_map(|e| e.ok() ).map(|e| e.file_name() ).collect::<Vec<_>>(),
+		Err(e) => {
+			return Err(ArchiverError::Failed(msg!("read-dir-failed",src.to_string_lossy(),e)));
+		}
+	};
+	children.sort();
+	let mut any = false;
+	for c in children {
+		let child = format!("{}/{}",name.trim_end_matches('/'),c.to_string_lossy());
+		any |= walk(&src.join(&c),&child,filter,l,changed)?;
+	}
+	if !filter.include.is_empty() && !any && index>0 {
+		l.truncate(index);
+		return Ok(false);
+	}
+	return Ok(true);
+}
+
+/// 作成するアーカイブの入力ファイルを絞り込み、除外したものがあれば残ったものを作業ディレクトリに同じ構造で配置して入力ファイルを置き換える
+/// 配置したディレクトリ (入力ファイルと同じファイルシステムであればハードリンクにする) は作成が終わるまで残しておく必要がある
+pub fn stage_inputs(d:&mut CreateData,filter:&Filter) -> Result<Option<TempDir>> {
+	if filter.is_empty() { return Ok(None); }
+	let cd = cwd()?;
+
+	// カレントディレクトリの設定ファイルはアーカイブのルート階層に対する条件とする
+	let entered = filter.enter(&cd,"");
+	let filter = entered.as_ref().unwrap_or(filter);
+
+	let mut changed = false;
+	let mut inputs = vec![];
+	for i in d.input.iter() {
+		let p = Path::new(i);
+		// --keep-path の場合はアーカイブ内のパスと同じ構造にするため、先頭の / や .. は取り除く
+		let name = match d.keep_path {
+			true => p.components().filter(|c| matches!(c,std::path::Component::Normal(_)) ).map(|c| c.as_os_str().to_string_lossy().to_string() ).collect::<Vec<_>>().join("/"),
+			false => p.absolute_path().file_name().unwrap_or_default().to_string_lossy().to_string()
+		};
+		let mut l = vec![];
+		if walk(p,&name,filter,&mut l,&mut changed)? { inputs.push((name,l)); }
+	}
+	if !changed { return Ok(None); }
+
+	// 出力先と同じ場所に作業ディレクトリを作成し、できなければ一時ディレクトリに作成する
+	let near = Path::new(&d.output).absolute_path().parent().map(|p| p.to_path_buf() ).unwrap_or(cd.clone());
+	let stage = tempfile::Builder::new().prefix(".archiver-").tempdir_in(&near).or_else(|_| tempdir() );
+	let stage = match stage {
+		Ok(s) => s,
+		Err(e) => { return Err(ArchiverError::Failed(msg!("tmp-dir-failed",e))); }
+	};
+
+	let mut new_input = vec![];
+	for (i,(name,l)) in inputs.iter().enumerate() {
+		// --keep-path でない場合は同じ名前の入力ファイルが衝突しないよう個別のフォルダに配置する
+		let root = match d.keep_path {
+			true => stage.path().to_path_buf(),
+			false => stage.path().join(i.to_string())
+		};
+		if let Err(e) = place(&root,l) {
+			return Err(ArchiverError::Failed(msg!("stage-failed",e)));
+		}
+		new_input.push(root.join(name).to_string_lossy().to_string());
+	}
+
+	// --keep-path の場合はアーカイブ内のパスを配置したディレクトリからの相対パスにする
+	if d.keep_path { d.base = Some(stage.path().to_path_buf()); }
+	d.input = new_input;
+	return Ok(Some(stage));
+}
+
+/// 絞り込んだファイルを root 以下に配置する (ハードリンクできない場合はコピーし、更新日時も元に合わせる)
+fn place(root:&Path,l:&[Kept]) -> std::io::Result<()> {
+	for k in l.iter() {
+		let dst = root.join(&k.name);
+		if let Some(p) = dst.parent() { fs::create_dir_all(p)?; }
+		let m = fs::symlink_metadata(&k.src)?;
+		if m.is_symlink() {
+			symlink(fs::read_link(&k.src)?,&dst)?;
+		}
+		else if k.is_dir {
+			fs::create_dir_all(&dst)?;
+			fs::set_permissions(&dst,m.permissions())?;
+		}
+		else if fs::hard_link(&k.src,&dst).is_err() {
+			fs::copy(&k.src,&dst)?;
+			if let Ok(t) = m.modified() { File::options().write(true).open(&dst)?.set_modified(t)?; }
+		}
+	}
+	// フォルダの更新日時は中身を配置した後に設定する
+	for k in l.iter().rev().filter(|k| k.is_dir ) {
+		if let Ok(t) = fs::metadata(&k.src).and_then(|m| m.modified() ) {
+			let _ = File::open(root.join(&k.name)).and_then(|f| f.set_modified(t) );
+		}
+	}
+	return Ok(());
+}
+
+/// 作業ディレクトリ stage に展開したもののうち条件を満たすものを output に移動する
+/// 既に存在するものは conflict に従って扱い、別名で置いたフォルダや置かなかったフォルダの中身もそれに合わせる
+pub fn move_filtered(stage:&Path,output:&Path,filter:&Filter,conflict:&mut Conflict) -> std::io::Result<()> {
+	let mut children = fs::read_dir(stage)?.filter_map(|e| e.ok() ).map(|e| e.file_name() ).collect::<Vec<_>>();
+	children.sort();
+	let mut changed = false;
+	for c in children {
+		let mut l = vec![];
+		walk(&stage.join(&c),&c.to_string_lossy(),filter,&mut l,&mut changed).map_err(std::io::Error::other)?;
+		// 移動先のフォルダ (置かなかったものは None)
+		let mut dirs:HashMap<String,Option<PathBuf>> = HashMap::new();
+		for k in l.iter() {
+			let dst = match k.name.rsplit_once('/') {
+				Some((parent,base)) => match dirs.get(parent) {
+					Some(Some(d)) => d.join(base),
+					Some(None) => { continue; },
+					None => output.join(&k.name)
+				},
+				None => output.join(&k.name)
+			};
+			let existing = fs::symlink_metadata(&dst).ok();
+			// 既存のフォルダには中身を移動する
+			if k.is_dir && existing.as_ref().is_some_and(|m| m.is_dir() ) {
+				dirs.insert(k.name.clone(),Some(dst));
+				continue;
+			}
+			let src_time = fs::symlink_metadata(&k.src)?.modified().ok();
+			let dst = match conflict.resolve(src_time,&dst,k.is_dir) {
+				Action::Replace => {
+					match &existing {
+						Some(m) if m.is_dir() => {
+							eprintln!("{}",msg!("folder-not-replaced",dst.to_string_lossy()));
+							continue;
+						},
+						Some(_) => { fs::remove_file(&dst)?; },
+						None => {}
+					}
+					dst
+				},
+				Action::Skip => {
+					if k.is_dir { dirs.insert(k.name.clone(),None); }
+					continue;
+				},
+				Action::Rename(r) => r
+			};
+			if k.is_dir {
+				fs::create_dir_all(&dst)?;
+				dirs.insert(k.name.clone(),Some(dst));
+				continue;
+			}
+			if let Some(p) = dst.parent() { fs::create_dir_all(p)?; }
+			if fs::rename(&k.src,&dst).is_err() {
+				// 別のファイルシステムの場合
+				if fs::symlink_metadata(&k.src)?.is_symlink() { symlink(fs::read_link(&k.src)?,&dst)?; }
+				else { fs::copy(&k.src,&dst)?; }
+			}
+		}
+		// フォルダの権限と更新日時を展開したものに合わせる
+		for k in l.iter().rev().filter(|k| k.is_dir ) {
+			let dst = match dirs.get(&k.name) {
+				Some(Some(d)) => d,
+				_ => { continue; }
+			};
+			let m = fs::metadata(&k.src)?;
+			fs::set_permissions(dst,m.permissions())?;
+			if let Ok(t) = m.modified() { let _ = File::open(dst).and_then(|f| f.set_modified(t) ); }
+		}
+	}
+	return Ok(());
+}
+
+/// glob のパターンと比較する (* と ? は / に該当せず、 ** は / を含む任意の文字列に該当する)
+pub fn glob_match(p:&[char],t:&[char]) -> bool {
+	match p.first() {
+		None => t.is_empty(),
+		Some('*') if p.get(1)==Some(&'*') => {
+			// **/ は0個以上のフォルダに該当する
+			let rest = &p[2..];
+			match rest.first() {
+				Some('/') => (0..=t.len()).any(|i| (i==0 || t[i-1]=='/') && glob_match(&rest[1..],&t[i..]) ),
+				_ => (0..=t.len()).any(|i| glob_match(rest,&t[i..]) )
+			}
+		},
+		Some('*') => {
+			for i in 0..=t.len() {
+				if glob_match(&p[1..],&t[i..]) { return true; }
+				if i<t.len() && t[i]=='/' { break; }
+			}
+			false
+		},
+		Some('?') => !t.is_empty() && t[0]!='/' && glob_match(&p[1..],&t[1..]),
+		Some('[') => {
+			match (t.first(),class_match(&p[1..],t.first().copied().unwrap_or('/'))) {
+				(Some(c),Some((true,n))) if *c!='/' => glob_match(&p[1+n..],&t[1..]),
+				(_,Some(_)) => false,
+				// 閉じていない [ はそのままの文字として扱う
+				(_,None) => t.first()==Some(&'[') && glob_match(&p[1..],&t[1..])
+			}
+		},
+		Some('\\') if p.len()>1 => t.first()==Some(&p[1]) && glob_match(&p[2..],&t[1..]),
+		Some(c) => t.first()==Some(c) && glob_match(&p[1..],&t[1..])
+	}
+}
+
+/// [...] の中身と比較する ((該当するか,] までの文字数) を返し、閉じていない場合は None)
+fn class_match(p:&[char],c:char) -> Option<(bool,usize)> {
+	let mut i = 0;
+	let negate = matches!(p.first(),Some('!')|Some('^'));
+	if negate { i += 1; }
+	let mut hit = false;
+	let mut first = true;
+	while i<p.len() {
+		if p[i]==']' && !first { return Some((hit!=negate,i+1)); }
+		first = false;
+		let lo = p[i];
+		if i+2<p.len() && p[i+1]=='-' && p[i+2]!=']' {
+			if lo<=c && c<=p[i+2] { hit = true; }
+			i += 3;
+		}
+		else {
+			if lo==c { hit = true; }
+			i += 1;
+		}
+	}
+	return None;
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn glob(p:&str,t:&str) -> bool {
+		return glob_match(&p.chars().collect::<Vec<_>>(),&t.chars().collect::<Vec<_>>());
+	}
+
+	fn filter(exclude:&[&str],include:&[&str]) -> Filter {
+		let d = FilterData {
+			include: include.iter().map(|s| s.to_string() ).collect(),
+			exclude: exclude.iter().map(|s| s.to_string() ).collect(),
+			..FilterData::default()
+		};
+		return Filter::new(&d,&[],false).unwrap();
+	}
+
+	#[test]
+	fn glob_patterns() {
+		assert!(glob("*.o","main.o"));
+		assert!(!glob("*.o","src/main.o"));
+		assert!(glob("src/**/*.rs","src/a/b/lib.rs"));
+		assert!(glob("src/**/*.rs","src/lib.rs"));
+		assert!(glob("**/target","a/target"));
+		assert!(glob("?.txt","a.txt"));
+		assert!(!glob("?","/"));
+		assert!(glob("[a-c]x","bx"));
+		assert!(!glob("[!a-c]x","bx"));
+		assert!(glob("[x","[x"));
+		assert!(glob("\\*","*"));
+		assert!(!glob("\\*","a"));
+	}
+
+	#[test]
+	fn gitignore_rules() {
+		let f = filter(&["*.log","!keep.log","build/","/top","doc/*.md"],&[]);
+		assert!(f.excluded("a/debug.log",false));
+		assert!(!f.excluded("a/keep.log",false));
+		assert!(f.excluded("x/build",true));
+		assert!(!f.excluded("x/build",false));
+		assert!(f.excluded("top",false));
+		assert!(!f.excluded("a/top",false));
+		assert!(f.excluded("doc/a.md",false));
+		assert!(!f.excluded("a/doc/a.md",false));
+	}
+
+	#[test]
+	fn include_and_defaults() {
+		let f = filter(&[],&["*.rs"]);
+		assert!(f.included("src/lib.rs",false));
+		assert!(!f.included("README.md",false));
+		assert!(f.included("src",true));
+		let z = Filter::defaults(&ArcType::Zip);
+		assert!(z.excluded("a/.git",true));
+		assert!(z.excluded("__MACOSX",true));
+		assert!(!z.excluded("a/b.txt",false));
+		assert!(Filter::defaults(&ArcType::Tar).is_empty());
+	}
+
+	#[test]
+	fn ignore_file_in_folder() {
+		let dir = tempdir().unwrap();
+		fs::write(dir.path().join(IGNORE_FILE),"*.tmp\n").unwrap();
+		let f = Filter::new(&FilterData { exclude: vec!["!b.tmp".to_string()], ..FilterData::default() },&[],true).unwrap();
+		let e = f.enter(dir.path(),"sub").unwrap();
+		assert!(e.excluded("sub/a.tmp",false));
+		assert!(!e.excluded("other/a.tmp",false));
+		// コマンドラインの条件は設定ファイルより優先する
+		assert!(!e.excluded("sub/b.tmp",false));
+	}
+}
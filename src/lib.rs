@@ -0,0 +1,53 @@
+// 既存のコードの書き方 (明示的な return) に合わせるため、 needless_return のみ無効にする
+#![allow(clippy::needless_return)]
+//! アーカイブコマンドのラッパー
+//!
+//! 各操作は CreateOptions などの XxxOptions で指定して実行する。
+//! XxxOptions は clap の Args としてコマンドラインの引数からも作成できる。
+//! 設定ファイルの既定値は Config で XxxOptions に反映する。
+extern crate clap;
+extern crate which;
+extern crate tempfile;
+#[macro_use]
+extern crate itertools;
+extern crate tar;
+extern crate zip;
+extern crate flate2;
+extern crate bzip2;
+extern crate lzma_rs;
+extern crate ruzstd;
+#[macro_use]
+mod message;
+mod args;
+mod library;
+mod error;
+mod options;
+mod config;
+mod create;
+mod list;
+mod entry;
+mod test;
+mod extract;
+mod append;
+mod remove;
+mod rename;
+mod convert;
+mod setup;
+mod backend;
+mod native;
+mod password;
+mod volume;
+mod filter;
+mod progress;
+mod preserve;
+mod safety;
+mod overwrite;
+mod reproducible;
+
+pub use crate::args::{ArcType,BackendType,ListFormat,Preserve,Overwrite,ProgressMode,Lang};
+pub use crate::library::guess_type;
+pub use crate::error::{ArchiverError,Result};
+pub use crate::message::{set_lang,text};
+pub use crate::entry::{Entry,EntryKind};
+pub use crate::options::{CreateOptions,ExtractOptions,ListOptions,TestOptions,AppendOptions,RemoveOptions,RenameOptions,ConvertOptions,SetupOptions};
+pub use crate::config::Config;
@@ -1,23 +1,89 @@
-use std::process::{Command,Stdio,exit};
+use std::process::{Command,Stdio,Child,ChildStdout,ChildStderr,ExitStatus};
 use std::fmt::Display;
 use std::convert::AsRef;
 pub use std::ffi::{OsStr,OsString};
 use std::env::current_dir;
-use std::io::Write;
+use std::time::{SystemTime,UNIX_EPOCH};
+use std::io::{Read,BufRead,Write,Seek,SeekFrom};
+use std::fs::File;
+use std::sync::Mutex;
+use std::thread::{self,JoinHandle,ThreadId};
 pub use std::path::{Path,PathBuf};
 use which::which;
 pub use tempfile::{tempdir,TempDir};
 use crate::args::*;
+use crate::progress;
+use crate::error::*;
 
 pub type VS = Vec<OsString>;
 
-pub fn vs<I,S:Display>(arr:I) -> VS where I:IntoIterator<Item=S>, S:AsRef<OsStr> {
+pub fn vs<I,S>(arr:I) -> VS where I:IntoIterator<Item=S>, S:Display+AsRef<OsStr> {
 	return arr.into_iter().map( |s| OsString::from(s.to_string()) ).collect();
 }
 
-pub fn error<S:Display>(message:S) where S:AsRef<OsStr> {
-	eprintln!("{}",message);
-	exit(1);
+/// エラーで終了する場合に削除する作業ディレクトリ (exit では TempDir が削除されないので、展開先の中に作るものを残さないようにする)
+static CLEANUP:Mutex<Vec<PathBuf>> = Mutex::new(vec![]);
+
+/// 登録された作業ディレクトリを削除する
+pub fn cleanup_on_error() {
+	let l = CLEANUP.lock().map(|l| l.clone() ).unwrap_or_default();
+	for p in l { let _ = std::fs::remove_dir_all(p); }
+}
+
+pub fn remove_on_error(p:&Path) {
+	if let Ok(mut l) = CLEANUP.lock() { l.push(p.to_path_buf()); }
+}
+
+pub fn keep_on_error(p:&Path) {
+	if let Ok(mut l) = CLEANUP.lock() { l.retain(|q| q!=p ); }
+}
+
+/// 作業ディレクトリを作成する
+pub fn work_dir() -> Result<TempDir> {
+	return tempdir().map_err(|e| ArchiverError::Failed(msg!("tmp-dir-failed",e)) );
+}
+
+/// 作業ディレクトリを削除する
+pub fn close_dir(t:TempDir) -> Result<()> {
+	return t.close().map_err(|e| ArchiverError::Failed(msg!("tmp-dir-remove-failed",e)) );
+}
+
+/// カレントディレクトリ
+pub fn cwd() -> Result<PathBuf> {
+	return current_dir().map_err(|e| ArchiverError::Failed(msg!("cwd-missing",e)) );
+}
+
+/// 端末での表示幅 (全角文字は 2 として数える)
+/// ○△× など幅が環境によって異なる記号は 1 とし、同じ種類の記号同士では揃うようにする
+pub fn display_width(s:&str) -> usize {
+	return s.chars().map(|c| if is_wide(c) { 2 } else { 1 } ).sum::<usize>();
+}
+
+/// East Asian Width が W または F の文字
+fn is_wide(c:char) -> bool {
+	return matches!(c as u32,
+		0x1100..=0x115F|0x2E80..=0x303E|0x3041..=0x33FF|0x3400..=0x4DBF|0x4E00..=0x9FFF|0xA000..=0xA4CF|
+		0xAC00..=0xD7A3|0xF900..=0xFAFF|0xFE30..=0xFE4F|0xFF00..=0xFF60|0xFFE0..=0xFFE6|
+		0x1F300..=0x1F64F|0x1F900..=0x1F9FF|0x20000..=0x3FFFD
+	);
+}
+
+/// JSON の文字列
+pub fn json_str(s:&str) -> String {
+	let mut r = String::from("\"");
+	for c in s.chars() {
+		match c {
+			'"'  => r.push_str("\\\""),
+			'\\' => r.push_str("\\\\"),
+			'\n' => r.push_str("\\n"),
+			'\r' => r.push_str("\\r"),
+			'\t' => r.push_str("\\t"),
+			c if (c as u32)<0x20 => r.push_str(&format!("\\u{:04x}",c as u32)),
+			c => r.push(c)
+		}
+	}
+	r.push('"');
+	return r;
 }
 
 pub struct Cmd {
@@ -28,50 +94,65 @@ pub struct Cmd {
 	pub stdin: Option<String>,
 	pub inherit_output: bool,
 	pub stdout: Option<String>,
-	pub stderr: Option<String>
+	pub stderr: Option<String>,
+	/// 進行状況の表示で読み込み量を数えるか (パイプの2番目以降は数えない)
+	pub track: bool,
+	/// 標準入力に流し込むファイル (流し込んだ量を進行状況の読み込み量として数える)
+	pub input: Option<PathBuf>,
+	/// bsdtar -v の出力を処理中のファイルとして読み込むか (Some の値はその行を表示もするか)
+	pub events: Option<bool>
 }
 impl Cmd {
-	pub fn new<S:Display>(prog:S,args:VS) -> Self where S:AsRef<OsStr> {
+	pub fn new<S>(prog:S,args:VS) -> Self where S:Display+AsRef<OsStr> {
 		return Cmd {
 			prog: OsString::from(prog.to_string()),
-			args: args,
-			cwd: current_dir().expect("カレントディレクトリが存在しません"),
+			args,
+			cwd: current_dir().unwrap_or_default(),
 			env: vec![],
 			stdin: None,
 			inherit_output: true,
 			stdout: None,
-			stderr: None
+			stderr: None,
+			track: true,
+			input: None,
+			events: None
 		}
 	}
-	pub fn new_cwd<S:Display>(prog:S,args:VS,cwd:PathBuf) -> Self where S:AsRef<OsStr> {
+	pub fn new_cwd<S>(prog:S,args:VS,cwd:PathBuf) -> Self where S:Display+AsRef<OsStr> {
 		return Cmd {
 			prog: OsString::from(prog.to_string()),
-			args: args,
-			cwd: cwd,
+			args,
+			cwd,
 			env: vec![],
 			stdin: None,
 			inherit_output: true,
 			stdout: None,
-			stderr: None
+			stderr: None,
+			track: true,
+			input: None,
+			events: None
 		}
 	}
-	pub fn new_cwd_stdin<S:Display>(prog:S,args:VS,cwd:PathBuf,stdin:String) -> Self where S:AsRef<OsStr> {
+	pub fn new_cwd_stdin<S>(prog:S,args:VS,cwd:PathBuf,stdin:String) -> Self where S:Display+AsRef<OsStr> {
 		return Cmd {
 			prog: OsString::from(prog.to_string()),
-			args: args,
-			cwd: cwd,
+			args,
+			cwd,
 			env: vec![],
 			stdin: Some(stdin),
 			inherit_output: true,
 			stdout: None,
-			stderr: None
+			stderr: None,
+			track: true,
+			input: None,
+			events: None
 		}
 	}
-	pub fn new_cwd_env<I,S:Display>(prog:S,args:VS,cwd:PathBuf,env:I) -> Self where I:IntoIterator<Item=(S,S)>,S:AsRef<OsStr> {
+	pub fn new_cwd_env<I,S>(prog:S,args:VS,cwd:PathBuf,env:I) -> Self where I:IntoIterator<Item=(S,S)>,S:Display+AsRef<OsStr> {
 		return Cmd {
 			prog: OsString::from(prog.to_string()),
-			args: args,
-			cwd: cwd,
+			args,
+			cwd,
 			env: env.into_iter().map(|(k,v)| {
 				(
 					OsString::from(k.to_string()),
@@ -81,69 +162,295 @@ impl Cmd {
 			stdin: None,
 			inherit_output: true,
 			stdout: None,
-			stderr: None
+			stderr: None,
+			track: true,
+			input: None,
+			events: None
 		}
 	}
 }
 
-pub fn several_cmd<I>(cmd_list:I) -> bool where I:IntoIterator<Item=Cmd> {
+/// 実行中の外部コマンドのプロセスと起動したスレッド (展開の中止で終了させるため)
+static RUNNING:Mutex<Vec<(ThreadId,u32)>> = Mutex::new(vec![]);
+
+fn register(pid:u32) {
+	if let Ok(mut l) = RUNNING.lock() { l.push((thread::current().id(),pid)); }
+}
+
+fn unregister(pid:u32) {
+	if let Ok(mut l) = RUNNING.lock() { l.retain(|(_,p)| *p!=pid ); }
+	progress::untrack(pid);
+}
+
+/// スレッド owner が起動した実行中の外部コマンドを終了させる (実行中のものがなければ false)
+/// 他のスレッドで同時に実行している操作のコマンドは終了させない
+pub fn kill_running(owner:ThreadId) -> bool {
+	let l = match RUNNING.lock() {
+		Ok(l) => l.iter().filter(|(t,_)| *t==owner ).map(|(_,p)| *p ).collect::<Vec<_>>(),
+		Err(_) => { return false; }
+	};
+	for pid in l.iter() {
+		let _ = Command::new("kill").args(["-KILL",&pid.to_string()]).stdout(Stdio::null()).stderr(Stdio::null()).status();
+	}
+	return !l.is_empty();
+}
+
+pub fn several_cmd<I>(cmd_list:I) -> Result<()> where I:IntoIterator<Item=Cmd> {
 	for cmd in cmd_list.into_iter() {
-		let p = match which(&cmd.prog) {
+		let mut c = Command::new(find(&cmd.prog)?);
+		c.args(&cmd.args).current_dir(&cmd.cwd);
+		for t in &cmd.env { c.env(&t.0,&t.1); }
+		// 表示しない場合も標準エラー出力は失敗したときに表示するために受け取る
+		set_output(&mut c,&cmd);
+		redirect(&mut c,&cmd)?;
+		let input = open_input(&cmd)?;
+		match (&cmd.stdin,&input) {
+			(Some(_),_)|(None,Some(_)) => c.stdin(Stdio::piped()),
+			(None,None) => c.stdin(Stdio::inherit())
+		};
+		let mut child = match c.spawn() {
+			Ok(child) => child,
+			Err(e) => { return Err(spawn_error(&cmd,e)); }
+		};
+		register(child.id());
+		if counted(&cmd) { progress::track(child.id()); }
+		let stderr = read_stderr(child.stderr.take(),&cmd);
+		let mut written = true;
+		if let (Some(s),Some(writer)) = (&cmd.stdin,&mut child.stdin) {
+			written = writer.write_all(s.as_bytes()).and_then(|_| writer.flush() ).is_ok();
+		}
+		if let (Some(f),Some(w)) = (input,child.stdin.take()) { feed(f,w); }
+		// 入力を閉じてから終了を待つ
+		drop(child.stdin.take());
+		let es = child.wait();
+		unregister(child.id());
+		check_status(&cmd,es,stderr)?;
+		if !written {
+			return Err(ArchiverError::Failed(msg!("stdin-pass-failed",cmd.prog.to_string_lossy())));
+		}
+	}
+	return Ok(());
+}
+
+/// コマンドの実行ファイルを探す
+fn find(prog:&OsStr) -> Result<PathBuf> {
+	return which(prog).map_err(|_| {
+		ArchiverError::MissingTool(msg!("missing-tool",prog.to_string_lossy()))
+	});
+}
+
+fn spawn_error(cmd:&Cmd,e:std::io::Error) -> ArchiverError {
+	return ArchiverError::Failed(msg!("spawn-failed",cmd.prog.to_string_lossy(),e));
+}
+
+/// 標準出力と標準エラー出力の扱い (bsdtar -v の出力を読み込む場合は標準エラー出力を受け取る)
+fn set_output(c:&mut Command,cmd:&Cmd) {
+	if cmd.inherit_output { c.stdout(Stdio::inherit()).stderr(Stdio::inherit()); }
+	else { c.stdout(Stdio::null()).stderr(Stdio::piped()); }
+	if cmd.events.is_some() { c.stderr(Stdio::piped()); }
+}
+
+/// /proc で読み込み量を数えるか (流し込む量や bsdtar -v の出力で数えるものは二重に数えない)
+fn counted(cmd:&Cmd) -> bool {
+	return cmd.track && cmd.input.is_none() && cmd.events.is_none();
+}
+
+fn open_input(cmd:&Cmd) -> Result<Option<File>> {
+	return match &cmd.input {
+		Some(p) => match File::open(p) {
+			Ok(f) => Ok(Some(f)),
+			Err(e) => Err(ArchiverError::from(e))
+		},
+		None => Ok(None)
+	};
+}
+
+/// ファイルを別のスレッドでコマンドの標準入力に流し込み、流し込んだ量を進行状況に加える
+fn feed<W:Write+Send+'static>(f:File,mut w:W) {
+	thread::spawn(move || {
+		let _ = std::io::copy(&mut progress::Counted(f),&mut w);
+	});
+}
+
+/// 標準エラー出力を別のスレッドで読み込む (読み込まないとパイプが詰まってコマンドが止まる)
+/// bsdtar -v の出力は処理中のファイルとして進行状況に渡し、それ以外の行を失敗したときのために残す
+fn read_stderr(stderr:Option<ChildStderr>,cmd:&Cmd) -> Option<JoinHandle<String>> {
+	let mut e = stderr?;
+	let (events,inherit,cwd) = (cmd.events,cmd.inherit_output,cmd.cwd.clone());
+	return Some(thread::spawn(move || {
+		let echo = match events {
+			Some(echo) => echo,
+			None => {
+				let mut b = vec![];
+				let _ = e.read_to_end(&mut b);
+				return String::from_utf8_lossy(&b).to_string();
+			}
+		};
+		let mut s = String::new();
+		for l in std::io::BufReader::new(e).split(b'\n').map_while(|l| l.ok() ) {
+			let l = String::from_utf8_lossy(&l).to_string();
+			match progress::file_event(&l,&cwd) {
+				true if !echo => {},
+				_ if inherit => eprintln!("{}",l),
+				_ => { s.push_str(&l); s.push('\n'); }
+			}
+		}
+		s
+	}));
+}
+
+/// 終了したコマンドの結果 (失敗した場合は標準エラー出力の末尾を含める)
+fn check_status(cmd:&Cmd,es:std::io::Result<ExitStatus>,stderr:Option<JoinHandle<String>>) -> Result<()> {
+	let stderr = stderr.and_then(|h| h.join().ok() ).unwrap_or_default();
+	let prog = cmd.prog.to_string_lossy();
+	let message = match es {
+		Ok(es) if es.success() => { return Ok(()); },
+		Ok(es) => match es.code() {
+			Some(n) => msg!("exit-status",prog,n),
+			None => msg!("killed",prog)
+		},
+		Err(e) => msg!("wait-failed",prog,e)
+	};
+	let lines = stderr.lines().collect::<Vec<_>>();
+	let tail = lines[lines.len().saturating_sub(STDERR_LINES)..].join("\n");
+	return Err(ArchiverError::Command { message, stderr:tail });
+}
+
+/// 失敗したコマンドの標準エラー出力を表示する行数
+const STDERR_LINES:usize = 20;
+
+/// 出力先のファイルが指定されている場合はリダイレクトする
+fn redirect(c:&mut Command,cmd:&Cmd) -> Result<()> {
+	for (path,is_stdout) in [(&cmd.stdout,true),(&cmd.stderr,false)] {
+		if let Some(p) = path {
+			match File::create(p) {
+				Ok(f) => {
+					if is_stdout { c.stdout(f); }
+					else { c.stderr(f); }
+				},
+				Err(e) => {
+					return Err(ArchiverError::Failed(msg!("redirect-failed",p,e)));
+				}
+			}
+		}
+	}
+	return Ok(());
+}
+
+/// 前のコマンドの標準出力を次のコマンドの標準入力に繋いで実行する
+pub fn piped_cmd(cmd_list:Vec<Cmd>) -> Result<()> {
+	return run_piped(cmd_list,false).map(|_| () );
+}
+
+/// piped_cmd と同様に実行し、最後のコマンドの標準出力を返す
+pub fn piped_output(cmd_list:Vec<Cmd>) -> Result<String> {
+	return run_piped(cmd_list,true);
+}
+
+fn run_piped(cmd_list:Vec<Cmd>,capture:bool) -> Result<String> {
+	let n = cmd_list.len();
+	let mut children:Vec<(Child,Cmd,Option<JoinHandle<String>>)> = vec![];
+	let mut prev:Option<ChildStdout> = None;
+	// 途中で失敗した場合は起動したコマンドを終了させる
+	let stop = |children:Vec<(Child,Cmd,Option<JoinHandle<String>>)>| {
+		for (mut child,_,_) in children {
+			let _ = child.kill();
+			let _ = child.wait();
+			unregister(child.id());
+		}
+	};
+	for (index,cmd) in cmd_list.into_iter().enumerate() {
+		let p = match find(&cmd.prog) {
 			Ok(p) => p,
-			Err(_) => {
-				error(
-					format!(
-						"コマンド {} が見つからないので実行できません",
-						cmd.prog.to_str().unwrap()
-					)
-				);
-				panic!();
+			Err(e) => {
+				stop(children);
+				return Err(e);
 			}
 		};
 		let mut c = Command::new(p);
-		c.args(cmd.args).current_dir(cmd.cwd);
-		for t in cmd.env { c.env(t.0,t.1); }
-		if cmd.inherit_output { c.stdout(Stdio::inherit()).stderr(Stdio::inherit()); }
-		else { c.stdout(Stdio::null()).stderr(Stdio::null()); }
-		match cmd.stdin {
-			Some(_) => c.stdin(Stdio::piped()),
-			None    => c.stdin(Stdio::inherit())
+		c.args(&cmd.args).current_dir(&cmd.cwd);
+		for t in &cmd.env { c.env(&t.0,&t.1); }
+		set_output(&mut c,&cmd);
+		let input = match open_input(&cmd) {
+			Ok(f) => f,
+			Err(e) => {
+				stop(children);
+				return Err(e);
+			}
 		};
-		let mut r = true;
+		match (prev.take(),&cmd.stdin,&input) {
+			(Some(o),_,_)         => c.stdin(o),
+			(None,Some(_),_)      => c.stdin(Stdio::piped()),
+			(None,None,Some(_))   => c.stdin(Stdio::piped()),
+			(None,None,None)      => c.stdin(Stdio::inherit())
+		};
+		if let Err(e) = redirect(&mut c,&cmd) {
+			stop(children);
+			return Err(e);
+		}
+		if index+1<n || capture { c.stdout(Stdio::piped()); }
 		match c.spawn() {
 			Ok(mut child) => {
-				match (&cmd.stdin,&mut child.stdin) {
-					(Some(s),Some(writer)) => {
-						match writer.write_all(s.as_bytes()) {
-							Ok(_) => {},
-							Err(_) => { r = false }
-						}
-						match writer.flush() {
-							Ok(_) => {},
-							Err(_) => { r = false }
-						}
-					},
-					(None,None) => {},
-					_ => { r = false }
-				}
-				match child.wait() {
-					Ok(es) => {
-						match es.code() {
-							Some(0) => {},
-							_ => { r = false; }
-						}
-					},
-					Err(_) => { r = false; }
+				// 前のコマンドから受け取る量は数えない
+				register(child.id());
+				if index==0 && counted(&cmd) { progress::track(child.id()); }
+				prev = child.stdout.take();
+				// 最初のコマンドに渡す入力は別のスレッドで書き込む (出力を読まないと詰まるため)
+				if let Some(mut w) = child.stdin.take() {
+					match (cmd.stdin.clone(),input) {
+						(Some(s),_) => { thread::spawn(move || { let _ = w.write_all(s.as_bytes()); }); },
+						(None,Some(f)) => feed(f,w),
+						(None,None) => {}
+					}
 				}
+				let stderr = read_stderr(child.stderr.take(),&cmd);
+				children.push((child,cmd,stderr));
 			},
 			Err(e) => {
-				eprintln!("コマンドの起動に失敗しました: {:?}",e);
-				r = false;
+				let e = spawn_error(&cmd,e);
+				stop(children);
+				return Err(e);
 			}
-		};
-		if !r { return false; }
+		}
+	}
+	// 出力を読み切ってから終了を待つ
+	let mut output = String::new();
+	if let Some(mut o) = prev {
+		let mut b = vec![];
+		let _ = o.read_to_end(&mut b);
+		output = String::from_utf8_lossy(&b).to_string();
+	}
+	// 最初に失敗したコマンドを報告する (後ろのコマンドは入力が途切れて失敗することが多い)
+	let mut r = Ok(output);
+	for (mut child,cmd,stderr) in children {
+		let es = child.wait();
+		unregister(child.id());
+		let s = check_status(&cmd,es,stderr);
+		if let (Err(e),true) = (s,r.is_ok()) { r = Err(e); }
 	}
-	return true;
+	return r;
+}
+
+/// コマンドの出力の先頭が tar アーカイブであるかを確認する (圧縮ファイルの中身の判定に使用)
+pub fn is_tar_stream(cmd:Cmd) -> bool {
+	let p = match which(&cmd.prog) {
+		Ok(p) => p,
+		Err(_) => { return false; }
+	};
+	let mut c = Command::new(p);
+	c.args(cmd.args).current_dir(cmd.cwd).stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::null());
+	for t in cmd.env { c.env(t.0,t.1); }
+	let mut child = match c.spawn() {
+		Ok(child) => child,
+		Err(_) => { return false; }
+	};
+	let mut h = vec![];
+	if let Some(o) = child.stdout.take() {
+		let _ = o.take(512).read_to_end(&mut h);
+	}
+	let _ = child.kill();
+	let _ = child.wait();
+	return h.len()>=262 && &h[257..262]==b"ustar";
 }
 
 pub trait TDAddition {
@@ -161,7 +468,7 @@ pub trait GetAbsolutePath {
 impl GetAbsolutePath for Path {
 	fn absolute_path(&self) -> PathBuf {
 		if self.is_relative() {
-			let cd = current_dir().expect("カレントディレクトリが存在しません");
+			let cd = current_dir().unwrap_or_default();
 			return cd.join(self);
 		}
 		else { return self.to_path_buf(); }
@@ -170,6 +477,77 @@ impl GetAbsolutePath for Path {
 
 
 
+/// 時刻を UNIX 時間の秒数に変換
+pub fn system_time_secs(t:SystemTime) -> i64 {
+	return match t.duration_since(UNIX_EPOCH) {
+		Ok(d) => d.as_secs() as i64,
+		Err(e) => -(e.duration().as_secs() as i64)
+	};
+}
+
+/// UNIX 時間の秒数を UTC の (年,月,日,時,分,秒) に変換
+pub fn utc_datetime(secs:i64) -> (i64,u32,u32,u32,u32,u32) {
+	let days = secs.div_euclid(86400);
+	let rest = secs.rem_euclid(86400) as u32;
+	// 1970-01-01 からの日数を暦に変換する
+	let z = days + 719468;
+	let era = z.div_euclid(146097);
+	let doe = z.rem_euclid(146097);
+	let yoe = (doe - doe/1460 + doe/36524 - doe/146096) / 365;
+	let doy = doe - (365*yoe + yoe/4 - yoe/100);
+	let mp = (5*doy + 2) / 153;
+	let d = (doy - (153*mp + 2)/5 + 1) as u32;
+	let m = if mp<10 { mp+3 } else { mp-9 } as u32;
+	let y = yoe + era*400 + if m<=2 { 1 } else { 0 };
+	return (y,m,d,rest/3600,rest/60%60,rest%60);
+}
+
+/// UTC の日時を UNIX 時間の秒数に変換 (utc_datetime の逆)
+pub fn utc_epoch(y:i64,m:u32,d:u32,h:u32,mi:u32,s:u32) -> i64 {
+	let y = if m<=2 { y-1 } else { y };
+	let era = y.div_euclid(400);
+	let yoe = y.rem_euclid(400);
+	let mp = if m>2 { m-3 } else { m+9 } as i64;
+	let doy = (153*mp + 2)/5 + d as i64 - 1;
+	let doe = yoe*365 + yoe/4 - yoe/100 + doy;
+	let days = era*146097 + doe - 719468;
+	return days*86400 + (h*3600 + mi*60 + s) as i64;
+}
+
+/// tar アーカイブを読み込んで書き直すコマンド (options で除外や名前の変更を指定する)
+pub fn tar_rewrite_cmd(src:&OsString,dst:&OsString,format:Option<&str>,options:VS) -> Cmd {
+	let mut c = Cmd::new_cwd_env("bsdtar",vs(["-c","-f"]),current_dir().unwrap_or_default(),[("COPYFILE_DISABLE","1")]);
+	c.args.push(dst.clone());
+	if let Some(f) = format { c.args.extend(vs(["--format",f])); }
+	c.args.extend(options);
+	let mut s = OsString::from("@");
+	s.push(src);
+	c.args.push(s);
+	return c;
+}
+
+/// bsdtar の -s オプションで使用する基本正規表現のためにエスケープする
+pub fn bre_escape(s:&str,delimiter:char) -> String {
+	let mut r = String::new();
+	for c in s.chars() {
+		if "\\.[]*^$".contains(c) || c==delimiter { r.push('\\'); }
+		r.push(c);
+	}
+	return r;
+}
+
+/// cpio アーカイブのヘッダからフォーマット名 (bsdtar の --format に指定するもの) を判定
+pub fn cpio_format(file:&OsString) -> &'static str {
+	let mut header = [0u8;6];
+	if let Ok(mut f) = File::open(file) {
+		if f.read_exact(&mut header).is_ok() && (&header==b"070701" || &header==b"070702") {
+			return "newc";
+		}
+	}
+	return "odc";
+}
+
+/// 拡張子からアーカイブの種類を判定する (create が false の場合、圧縮された tar アーカイブは tar として扱う)
 pub fn guess_type(file:&String,create:bool) -> Option<ArcType> {
 	macro_rules! tar_compress {
 		($compress:expr) => { {
@@ -221,8 +599,86 @@ pub fn guess_type(file:&String,create:bool) -> Option<ArcType> {
 	})
 }
 
-pub fn compress_ext(at:&ArcType) -> String {
-	return match at {
+/// ファイルの先頭 (ISO は 0x8001 以降、DMG は末尾) のマジックナンバーからアーカイブの種類を判定
+pub fn sniff_type(file:&String) -> Option<ArcType> {
+	let mut f = File::open(file).ok()?;
+	let mut h = vec![];
+	(&f).take(0x9006).read_to_end(&mut h).ok()?;
+	let at = |offset:usize,magic:&[u8]| h.len()>=offset+magic.len() && &h[offset..offset+magic.len()]==magic;
+
+	let t = match () {
+		_ if at(0,b"PK\x03\x04")||at(0,b"PK\x05\x06")||at(0,b"PK\x07\x08") => ArcType::Zip,
+		_ if at(0,b"7z\xBC\xAF\x27\x1C")            => ArcType::SevenZ,
+		_ if at(0,b"Rar!\x1A\x07")                    => ArcType::Rar,
+		_ if at(257,b"ustar")                         => ArcType::Tar,
+		_ if at(0,b"070707")||at(0,b"070701")||at(0,b"070702")||at(0,b"\xC7\x71")||at(0,b"\x71\xC7") => ArcType::Cpio,
+		_ if at(0,b"MSWIM\0\0\0")                    => ArcType::Wim,
+		_ if at(0,b"AA01")||at(0,b"YAA1")||at(0,b"pbz") => ArcType::Aar,
+		_ if at(0,b"7kSt")||at(0,b"zPQ")              => ArcType::Zpaq,
+		_ if at(2,b"-lh")||at(2,b"-lz")               => ArcType::Lha,
+		_ if at(0x8001,b"CD001")||at(0x8801,b"CD001")||at(0x9001,b"CD001") => ArcType::Iso,
+		_ if at(0,b"\x1F\x9D")                        => ArcType::Compress,
+		_ if at(0,b"\x1F\x8B")                        => ArcType::Gzip,
+		_ if at(0,b"BZh")                             => ArcType::Bzip2,
+		_ if at(0,b"\xFD7zXZ\0")                      => ArcType::Xz,
+		_ if at(0,b"LZIP")                            => ArcType::Lzip,
+		_ if at(0,b"\x5D\0\0")                        => ArcType::Lzma,
+		_ if at(0,b"\x04\x22\x4D\x18")||at(0,b"\x02\x21\x4C\x18") => ArcType::Lz4,
+		_ if at(0,b"\x89LZO\0\r\n\x1A\n")              => ArcType::Lzop,
+		_ if at(0,b"LRZI")                            => ArcType::Lrzip,
+		_ if at(0,b"RZIP")                            => ArcType::Rzip,
+		_ if at(0,b"\x28\xB5\x2F\xFD")                => ArcType::Zstd,
+		_ if at(0,b"bvx")                             => ArcType::Lzfse,
+		_ => {
+			// DMG は末尾 512 バイトの koly ブロックで判定
+			let mut trailer = [0u8;4];
+			f.seek(SeekFrom::End(-512)).ok()?;
+			f.read_exact(&mut trailer).ok()?;
+			if &trailer==b"koly" { ArcType::Dmg }
+			else { return None; }
+		}
+	};
+	return Some(t);
+}
+
+/// 既存のファイルの種類を内容から判定し、判定できないものや紛らわしいものは拡張子で補う
+pub fn detect_type(file:&String,create:bool) -> Option<ArcType> {
+	match (sniff_type(file),guess_type(file,create)) {
+		// 拡張子と一致している場合や圧縮された tar アーカイブ (create=false の場合) は拡張子の方を使う
+		(Some(s),Some(e)) if s==e => Some(e),
+		(Some(s),Some(ArcType::Tar)) if is_compress(&s) && guess_type(file,true)==Some(s) => Some(ArcType::Tar),
+		(Some(s),_) => Some(s),
+		(None,e) => e
+	}
+}
+
+/// 既存のアーカイブを変更する場合の種類の判定 (圧縮された tar アーカイブは圧縮の種類になる)
+pub fn guess_existing_type(file:&String) -> Result<ArcType> {
+	let s = sniff_type(file);
+	return Ok(match (s,guess_type(file,true),guess_type(file,false)) {
+		// 単一ファイルの圧縮
+		(s,Some(c),Some(t)) if is_compress(&c) && is_compress(&t) && s.is_none_or(|s| is_compress(&s)) => {
+			return Err(ArchiverError::Unsupported(msg!("single-compress-immutable")));
+		},
+		// 内容から判定できた場合 (拡張子のない圧縮ファイルは tar アーカイブを圧縮したものとみなす)
+		(Some(s),_,_) => s,
+		(None,Some(t),_) => t,
+		_ => {
+			return Err(ArchiverError::Unsupported(msg!("unknown-type")));
+		}
+	});
+}
+
+/// 単一ファイルの圧縮系のフォーマットかどうか
+pub fn is_compress(at:&ArcType) -> bool {
+	return matches!(at,
+		ArcType::Compress|ArcType::Gzip|ArcType::Bzip2|ArcType::Xz|ArcType::Lzip|ArcType::Lzma|ArcType::Lz4|
+		ArcType::Lzop|ArcType::Lrzip|ArcType::Rzip|ArcType::Zstd|ArcType::Brotli|ArcType::Lzfse
+	);
+}
+
+pub fn compress_ext(at:&ArcType) -> Result<String> {
+	return Ok(match at {
 		ArcType::Compress => "Z"    ,
 		ArcType::Gzip     => "gz"   ,
 		ArcType::Bzip2    => "bz2"  ,
@@ -236,15 +692,15 @@ pub fn compress_ext(at:&ArcType) -> String {
 		ArcType::Zstd     => "zst"  ,
 		ArcType::Brotli   => "br"   ,
 		ArcType::Lzfse    => "lzfse",
-		_ => { panic!(); }
-	}.to_string();
+		_ => { return Err(ArchiverError::Unsupported(msg!("unsupported-compress"))); }
+	}.to_string());
 }
 
-pub fn rate_conversion(rate:&mut u8,arc_type:&ArcType) {
+pub fn rate_conversion(rate:&mut u8,arc_type:&ArcType) -> Result<()> {
 
 	let r = *rate;
 	if r>9 {
-		error("圧縮率は 0-9 の整数で指定します。");
+		return Err(ArchiverError::Usage(msg!("rate-range")));
 	}
 
 	*rate = match arc_type {
@@ -256,8 +712,7 @@ pub fn rate_conversion(rate:&mut u8,arc_type:&ArcType) {
 				3|4 => 3,
 				5|6 => 5,
 				7|8 => 7,
-				9   => 9,
-				_   => { panic!(); }
+				_   => 9
 			}
 		},
 		ArcType::Rar => {
@@ -267,17 +722,15 @@ pub fn rate_conversion(rate:&mut u8,arc_type:&ArcType) {
 				3|4 => 2,
 				5|6 => 3,
 				7|8 => 4,
-				9   => 5,
-				_   => { panic!(); }
+				_   => 5
 			}
 		},
 		ArcType::Brotli => {
 			match r {
-				0|1|2|3|4|5|6 => r,
+				0..=6 => r,
 				7 => 8,
 				8 => 10,
-				9 => 11,
-				_ => { panic!(); }
+				_ => 11
 			}
 		},
 		ArcType::Zstd => {
@@ -291,11 +744,89 @@ pub fn rate_conversion(rate:&mut u8,arc_type:&ArcType) {
 				6 => 13,
 				7 => 15,
 				8 => 17,
-				9 => 19,
-				_ => { panic!(); }
+				_ => 19
 			}
 		},
 		_ => 0
 	};
+	return Ok(());
+
+}
+
+/// 追加や削除でアーカイブを書き直す際の圧縮率 (rate_conversion で変換したもの)
+/// 圧縮された tar アーカイブは指定がなければ元の圧縮率で再圧縮し、元の圧縮率が読み取れなければ指定を求める
+pub fn rewrite_rate(file:&String,rate:Option<u8>,arc_type:&ArcType) -> Result<u8> {
+	let mut r = match rate {
+		Some(r) => r,
+		None if is_compress(arc_type) => match original_rate(file,arc_type) {
+			Some(r) => r,
+			None => { return Err(ArchiverError::Usage(msg!("rate-required"))); }
+		},
+		None => 6
+	};
+	rate_conversion(&mut r,arc_type)?;
+	return Ok(r);
+}
+
+/// 圧縮ファイルのヘッダから読み取れる圧縮率 (0-9)
+/// gzip は XFL (2 は -9 、 4 は -1 、 0 はそれ以外なので既定の 6 とみなす)、 bzip2 はブロックサイズ (-1 から -9 と一致する) から読み取る
+pub fn original_rate(file:&String,arc_type:&ArcType) -> Option<u8> {
+	let mut h = [0u8;10];
+	File::open(file).ok()?.read_exact(&mut h).ok()?;
+	return match arc_type {
+		ArcType::Gzip if h[0..2]==[0x1f,0x8b] => match h[8] {
+			2 => Some(9),
+			4 => Some(1),
+			_ => Some(6)
+		},
+		ArcType::Bzip2 if &h[0..3]==b"BZh" && (b'1'..=b'9').contains(&h[3]) => Some(h[3]-b'0'),
+		_ => None
+	};
+}
+/// "2G" や "500M" のような大きさの指定をバイト数に変換 (単位は 1024 倍ずつ, 単位なしはバイト)
+pub fn parse_size(s:&str) -> Option<u64> {
+	let t = s.trim().to_ascii_uppercase();
+	let t = t.strip_suffix("IB").or_else(|| t.strip_suffix('B') ).unwrap_or(&t);
+	let (num,unit) = match t.find(|c:char| !c.is_ascii_digit() && c!='.' ) {
+		Some(i) => t.split_at(i),
+		None => (t,"")
+	};
+	let shift = match unit {
+		""  => 0,
+		"K" => 10,
+		"M" => 20,
+		"G" => 30,
+		"T" => 40,
+		_ => { return None; }
+	};
+	let n:f64 = num.parse().ok()?;
+	return Some((n * (1u64<<shift) as f64) as u64);
+}
 
-}
\ No newline at end of file
+/// --threads の指定をスレッドの数に変換 (指定がなければ 1 、 0 は CPU の数)
+pub fn thread_count(t:&Option<usize>) -> usize {
+	return match t {
+		None => 1,
+		Some(0) => std::thread::available_parallelism().map(|n| n.get() ).unwrap_or(1),
+		Some(n) => *n
+	};
+}
+
+/// "-" は標準入力,標準出力を表す
+pub fn is_stdio(s:&str) -> bool {
+	return s=="-";
+}
+
+/// 標準入力を作業ディレクトリのファイルに保存する (パイプで扱えない種類を読み込む場合に使用)
+pub fn spool_stdin(tmp:&TempDir) -> Result<String> {
+	let path = tmp.path().join("stdin");
+	let r = File::create(&path).and_then(|mut f| std::io::copy(&mut std::io::stdin().lock(),&mut f) );
+	if let Err(e) = r { return Err(ArchiverError::Failed(msg!("stdin-read-failed",e))); }
+	return Ok(path.to_string_lossy().to_string());
+}
+
+/// ファイルの内容を標準出力に書き出す
+pub fn copy_to_stdout(file:&String) -> bool {
+	let mut out = std::io::stdout().lock();
+	return File::open(file).and_then(|mut f| std::io::copy(&mut f,&mut out) ).and_then(|_| out.flush() ).is_ok();
+}
@@ -1,43 +1,383 @@
+use which::which;
 use crate::library::*;
 use crate::args::*;
+use crate::error::*;
+use crate::backend::*;
+use crate::entry::*;
+use crate::password::*;
+use crate::volume::join_volumes;
+use crate::extract::{contains_tar,decompress_stdout_cmd};
+use crate::message::text;
 
-pub fn list(d:ListData) {
+pub fn list(d:ListData) -> Result<()> {
+	let format = d.format;
+	let l = read_entries(d)?;
+	print_entries(&l,&format);
+	return Ok(());
+}
+
+/// アーカイブの内容を読み込む
+pub fn read_entries(mut d:ListData) -> Result<Vec<Entry>> {
+
+	let tmp = work_dir()?;
+	let (input,_) = match is_stdio(&d.input) {
+		// 標準入力は作業ディレクトリに保存してから読み込む
+		true => (spool_stdin(&tmp)?,d.input.clone()),
+		false => {
+			// アーカイブが存在することを確認
+			if !Path::new(&d.input).is_file() {
+				return Err(ArchiverError::NotFound(msg!("file-not-found",d.input)));
+			}
+			// 分割されたアーカイブは全てのボリュームを1つにまとめて読み込む
+			join_volumes(&d.input,&tmp)?
+		}
+	};
+	d.input = input;
 
 	// アーカイブの種類を判定
 	let arc_type = match d.arc_type {
 		Some(t) => t,
 		None => {
-			match guess_type(&d.input,true) {
+			match detect_type(&d.input,true) {
 				Some(t) => t,
-				None => ArcType::Tar
+				None => {
+					return Err(ArchiverError::Unsupported(msg!("unknown-type")));
+				}
 			}
 		}
 	};
 
-	// アーカイブが存在することを確認
-	if !Path::new(&d.input).is_file() {
-		error("ファイルが存在しません");
+	// ヘッダまで暗号化されている場合はパスワードを用意する (zip は暗号化されていてもファイル名を読み込める)
+	load_password(&mut d.pw)?;
+	if arc_type!=ArcType::Zip { ask_password(&mut d.pw,&arc_type,&d.input)?; }
+
+	// 実装を選択して実行 (コマンドが読み込めなかったものは破損として扱う)
+	let backend = select_backend(&arc_type,&d.backend,Operation::List)?;
+	let l = backend.list(&d,&arc_type).map_err(|e| e.corrupt(text("list-failed")) )?;
+	close_dir(tmp)?;
+	return Ok(l);
+
+}
+
+/// 外部コマンドによる内容の読み込み
+pub fn list_external(d:&ListData,arc_type:&ArcType) -> Result<Vec<Entry>> {
+	let tmp = work_dir()?;
+	let l = list_entries(&d.input,arc_type,&d.pw.password,d.checksum,&tmp)?;
+	close_dir(tmp)?;
+	return Ok(l);
+}
+
+/// 各コマンドの詳細な出力を読み込んで一覧にする
+/// checksum が true の場合はチェックサムを記録しない tar なども全てのデータを読み込んで計算する
+pub fn list_entries(input:&String,arc_type:&ArcType,password:&Option<String>,checksum:bool,tmp:&TempDir) -> Result<Vec<Entry>> {
+
+	// パスワードがあればコマンドに渡す
+	let with_password = |mut c:Cmd| -> Result<Cmd> {
+		if let Some(pw) = password { add_password_args(&mut c,pw,tmp,false)?; }
+		return Ok(c);
+	};
+
+	// 圧縮された tar アーカイブは解凍しながら読み込む
+	if is_compress(arc_type) {
+		if !contains_tar(arc_type,input) {
+			return Err(ArchiverError::Unsupported(msg!("unsupported-list")));
+		}
+		return mtree_entries(arc_type,input,checksum);
 	}
 
-	// コマンドを用意
-	let c = match arc_type {
-		ArcType::Zip    => Cmd::new("zipinfo",vs(["-1",&d.input])),
-		ArcType::Tar|ArcType::Lha => {
-			Cmd::new("bsdtar",vs(["-t","-f",&d.input]))
+	// 各コマンドの出力に含まれない情報は、読み込めれば bsdtar で補う
+	let mut l = match arc_type {
+		ArcType::Tar|ArcType::Cpio|ArcType::Lha|ArcType::Iso => {
+			return mtree_entries(arc_type,input,checksum);
+		},
+		ArcType::Zip    => zipinfo_entries(&output(Cmd::new("zipinfo",vs(["-v",input])))?),
+		ArcType::SevenZ => sevenz_entries(&output(with_password(Cmd::new("7z",vs(["l","-slt","-ba",input])))?)?),
+		ArcType::Rar    => rar_entries(&output(with_password(Cmd::new("rar",vs(["lt",input])))?)?),
+		ArcType::Zpaq   => zpaq_entries(&output(Cmd::new("zpaq",vs(["l",input])))?),
+		ArcType::Wim    => {
+			return Ok(wimdir_entries(&output(Cmd::new("wimdir",vs([input,"--detailed"])))?));
+		},
+		ArcType::Aar    => {
+			let o = output(with_password(Cmd::new("aa",vs(["list","-i",input])))?)?;
+			return Ok(o.lines().filter(|p| !p.is_empty() ).map(|p| Entry::new(p.to_string()) ).collect());
 		},
-		ArcType::SevenZ => Cmd::new("7z"     ,vs(["l","-ba",&d.input])),
-		ArcType::Rar    => Cmd::new("rar"    ,vs(["lb",&d.input])),
-		ArcType::Cpio   => Cmd::new("cpio"   ,vs(["-t","-I",&d.input])),
-		ArcType::Aar    => Cmd::new("aa"     ,vs(["list","-i",&d.input])),
-		ArcType::Wim    => Cmd::new("wimdir" ,vs([&d.input])),
-		ArcType::Zpaq   => Cmd::new("zpaq"   ,vs(["l",&d.input])),
 		_ => {
-			error("このファイルは内容の表示に対応していません");
-			panic!();
+			return Err(ArchiverError::Unsupported(msg!("unsupported-list")));
 		}
 	};
+	// 暗号化されている場合は bsdtar がパスワードを要求するので補わない
+	if which("bsdtar").is_ok() && !l.iter().any(|e| e.encrypted ) {
+		if let Ok(m) = mtree_entries(arc_type,input,false) { fill_missing(&mut l,m); }
+	}
+	return Ok(l);
 
-	// コマンドを実行
-	if !several_cmd(vec![c]) { error("内容の表示に失敗しました"); }
+}
 
-}
\ No newline at end of file
+/// コマンドを実行して標準出力を取得する
+fn output(mut c:Cmd) -> Result<String> {
+	c.inherit_output = true;
+	return piped_output(vec![c]);
+}
+
+/// bsdtar で mtree 形式に変換して読み込む (digest はデータを読み込んでハッシュ値を求めるかどうか)
+fn mtree_entries(arc_type:&ArcType,input:&String,digest:bool) -> Result<Vec<Entry>> {
+	let options = match digest {
+		true  => "!all,type,mode,uid,gid,size,time,link,sha256,!use-set",
+		false => "!all,type,mode,uid,gid,size,time,link,!use-set"
+	};
+	let mut c = Cmd::new("bsdtar",vs(["-c","-f","-","--format","mtree","--options",options]));
+	c.inherit_output = true;
+	let l = match decompress_stdout_cmd(arc_type,input) {
+		Some(dc) if is_compress(arc_type) => {
+			c.args.push(OsString::from("@-"));
+			vec![dc,c]
+		},
+		_ => {
+			c.args.push(OsString::from(format!("@{}",input)));
+			vec![c]
+		}
+	};
+	return Ok(parse_mtree(&piped_output(l)?));
+}
+
+/// 先頭の n 個の空白区切りの値と、残りの部分 (パスなど空白を含むもの) に分ける
+pub fn split_fields(line:&str,n:usize) -> Option<(Vec<&str>,&str)> {
+	let mut fields = vec![];
+	let mut rest = line.trim_start();
+	for _ in 0..n {
+		let end = rest.find(char::is_whitespace)?;
+		fields.push(&rest[..end]);
+		rest = rest[end..].trim_start();
+	}
+	return Some((fields,rest));
+}
+
+/// ls -l と同様の形式のパーミッションを読み込む
+pub fn parse_mode_string(s:&str) -> Option<(EntryKind,u32)> {
+	let c = s.chars().collect::<Vec<_>>();
+	if c.len()!=10 { return None; }
+	let kind = match c[0] {
+		'-' => EntryKind::File,
+		'd' => EntryKind::Dir,
+		'l' => EntryKind::Symlink,
+		'h' => EntryKind::HardLink,
+		'c'|'b'|'p'|'s' => EntryKind::Other,
+		_ => { return None; }
+	};
+	let mut mode = 0;
+	for (i,ch) in c[1..].iter().enumerate() {
+		let bit = 0o400>>i;
+		match (i,ch) {
+			(_,'-') => {},
+			(_,'r')|(_,'w')|(_,'x') => { mode |= bit; },
+			(2,'s') => { mode |= bit|0o4000; },
+			(2,'S') => { mode |= 0o4000; },
+			(5,'s') => { mode |= bit|0o2000; },
+			(5,'S') => { mode |= 0o2000; },
+			(8,'t') => { mode |= bit|0o1000; },
+			(8,'T') => { mode |= 0o1000; },
+			_ => { return None; }
+		}
+	}
+	return Some((kind,mode));
+}
+
+/// "2026 Oct 18 07:52:06" のような英語の月名を含む日時の月
+fn month(name:&str) -> Option<u32> {
+	let months = ["Jan","Feb","Mar","Apr","May","Jun","Jul","Aug","Sep","Oct","Nov","Dec"];
+	return months.iter().position(|m| *m==name ).map(|i| i as u32+1 );
+}
+
+/// "07:52:06" のような時刻
+fn hms(s:&str) -> Option<(u32,u32,u32)> {
+	let v = s.split(':').map(|t| t.parse::<u32>().ok() ).collect::<Option<Vec<_>>>()?;
+	if v.len()!=3 { return None; }
+	return Some((v[0],v[1],v[2]));
+}
+
+/// zipinfo -v の出力
+fn zipinfo_entries(s:&str) -> Vec<Entry> {
+	let mut l:Vec<Entry> = vec![];
+	let mut lines = s.lines();
+	while let Some(line) = lines.next() {
+		if line.starts_with("Central directory entry #") {
+			// 区切り線と空行の後にファイル名がある
+			let name = lines.by_ref().skip(1).find(|l| !l.is_empty() ).unwrap_or("");
+			let mut e = Entry::new(name.strip_prefix("  ").unwrap_or(name).to_string());
+			if e.path.ends_with('/') { e.kind = EntryKind::Dir; }
+			l.push(e);
+			continue;
+		}
+		let e = match l.last_mut() {
+			Some(e) => e,
+			None => { continue; }
+		};
+		let (k,v) = match line.split_once(':') {
+			Some((k,v)) => (k.trim(),v.trim()),
+			None => { continue; }
+		};
+		match k {
+			"32-bit CRC value (hex)" => { e.checksum = Some(format!("crc32:{}",v)); },
+			"compressed size"   => { e.compressed_size = v.trim_end_matches(" bytes").parse().ok(); },
+			"uncompressed size" => { e.size = v.trim_end_matches(" bytes").parse().ok(); },
+			"file security status" => { e.encrypted = v.starts_with("encrypted"); },
+			"file last modified on (UT extra field modtime)" if v.ends_with("UTC") => {
+				if let Some((f,_)) = split_fields(v,4) {
+					if let (Ok(y),Some(m),Ok(d),Some((h,mi,sec))) = (f[0].parse(),month(f[1]),f[2].parse(),hms(f[3])) {
+						e.mtime = Some(utc_epoch(y,m,d,h,mi,sec));
+					}
+				}
+			},
+			k if k.starts_with("Unix file attributes (") => {
+				let octal = k.trim_start_matches("Unix file attributes (").split(' ').next().unwrap_or("");
+				if let Ok(m) = u32::from_str_radix(octal,8) {
+					e.mode = Some(m & 0o7777);
+					if m & 0o170000!=0 { e.kind = EntryKind::from_mode(m); }
+				}
+			},
+			_ => {}
+		}
+	}
+	return l;
+}
+
+/// 7z l -slt の出力
+fn sevenz_entries(s:&str) -> Vec<Entry> {
+	let mut l:Vec<Entry> = vec![];
+	for line in s.lines() {
+		let (k,v) = match line.split_once(" = ") {
+			Some(kv) => kv,
+			None => { continue; }
+		};
+		if k=="Path" {
+			l.push(Entry::new(v.to_string()));
+			continue;
+		}
+		let e = match l.last_mut() {
+			Some(e) => e,
+			None => { continue; }
+		};
+		match k {
+			"Folder" if v=="+" => { e.kind = EntryKind::Dir; },
+			"Size" => { e.size = v.parse().ok(); },
+			"Packed Size" => { e.compressed_size = v.parse().ok(); },
+			"CRC" if !v.is_empty() => { e.checksum = Some(format!("crc32:{}",v.to_lowercase())); },
+			"Encrypted" => { e.encrypted = v=="+"; },
+			"Symbolic Link" if !v.is_empty() => {
+				e.link = Some(v.to_string());
+				e.kind = EntryKind::Symlink;
+			},
+			"Attributes" => {
+				// "A -rw-r--r--" のように Windows の属性の後に Unix のパーミッションが続く
+				if let Some((kind,mode)) = v.split(' ').find_map(parse_mode_string) {
+					e.kind = kind;
+					e.mode = Some(mode);
+				}
+			},
+			_ => {}
+		}
+	}
+	return l;
+}
+
+/// rar lt の出力
+fn rar_entries(s:&str) -> Vec<Entry> {
+	let mut l:Vec<Entry> = vec![];
+	for line in s.lines() {
+		let (k,v) = match line.split_once(": ") {
+			Some((k,v)) => (k.trim(),v.trim()),
+			None => { continue; }
+		};
+		if k=="Name" {
+			l.push(Entry::new(v.to_string()));
+			continue;
+		}
+		let e = match l.last_mut() {
+			Some(e) => e,
+			None => { continue; }
+		};
+		match k {
+			"Type" => {
+				e.kind = match v {
+					"File" => EntryKind::File,
+					"Directory" => EntryKind::Dir,
+					t if t.contains("symbolic link") || t.contains("Symbolic link") => EntryKind::Symlink,
+					t if t.contains("hard link") || t.contains("Hard link") => EntryKind::HardLink,
+					_ => EntryKind::Other
+				};
+			},
+			"Target" => { e.link = Some(v.to_string()); },
+			"Size" => { e.size = v.parse().ok(); },
+			"Packed size" => { e.compressed_size = v.parse().ok(); },
+			"CRC32" => { e.checksum = Some(format!("crc32:{}",v.to_lowercase())); },
+			"Flags" => { e.encrypted = v.contains("encrypted"); },
+			"Attributes" => {
+				if let Some((_,mode)) = parse_mode_string(v) { e.mode = Some(mode); }
+			},
+			_ => {}
+		}
+	}
+	return l;
+}
+
+/// zpaq l の出力 ("- 日付 時刻 サイズ 属性 パス" の行)
+fn zpaq_entries(s:&str) -> Vec<Entry> {
+	let mut l = vec![];
+	for line in s.lines() {
+		if !line.starts_with("- ") { continue; }
+		let (f,path) = match split_fields(line,5) {
+			Some(r) => r,
+			None => { continue; }
+		};
+		let mut e = Entry::new(path.to_string());
+		if path.ends_with('/') { e.kind = EntryKind::Dir; }
+		e.size = f[3].parse().ok();
+		// zpaq は UTC で記録している
+		let date = f[1].split('-').map(|t| t.parse::<u32>().ok() ).collect::<Option<Vec<_>>>();
+		if let (Some(date),Some((h,mi,sec))) = (date,hms(f[2])) {
+			if date.len()==3 { e.mtime = Some(utc_epoch(date[0] as i64,date[1],date[2],h,mi,sec)); }
+		}
+		if let Ok(m) = u32::from_str_radix(f[4],8) {
+			e.mode = Some(m & 0o7777);
+			if m & 0o170000!=0 { e.kind = EntryKind::from_mode(m); }
+		}
+		l.push(e);
+	}
+	return l;
+}
+
+/// wimdir --detailed の出力
+fn wimdir_entries(s:&str) -> Vec<Entry> {
+	let mut l:Vec<Entry> = vec![];
+	for line in s.lines() {
+		let t = line.trim();
+		if let Some(p) = t.strip_prefix("Full Path:") {
+			let p = p.trim().trim_matches('"').trim_start_matches('/');
+			l.push(Entry::new(p.to_string()));
+			continue;
+		}
+		let e = match l.last_mut() {
+			Some(e) => e,
+			None => { continue; }
+		};
+		if t=="FILE_ATTRIBUTE_DIRECTORY is set" { e.kind = EntryKind::Dir; }
+		else if t=="FILE_ATTRIBUTE_REPARSE_POINT is set" { e.kind = EntryKind::Symlink; }
+		else if let Some(v) = t.strip_prefix("Uncompressed size = ") {
+			if e.size.is_none() { e.size = v.trim_end_matches(" bytes").parse().ok(); }
+		}
+		else if let Some(v) = t.strip_prefix("Hash = 0x") {
+			if e.checksum.is_none() { e.checksum = Some(format!("sha1:{}",v)); }
+		}
+		else if let Some(v) = t.strip_prefix("Last Write Time:") {
+			// "Sun Oct 18 07:52:06 2026 UTC"
+			if let Some((f,_)) = split_fields(v,5) {
+				if let (Some(m),Ok(d),Some((h,mi,sec)),Ok(y)) = (month(f[1]),f[2].parse(),hms(f[3]),f[4].parse()) {
+					e.mtime = Some(utc_epoch(y,m,d,h,mi,sec));
+				}
+			}
+		}
+	}
+	// ルートディレクトリは含めない
+	l.retain(|e| !e.path.is_empty() );
+	return l;
+}
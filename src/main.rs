@@ -1,32 +1,95 @@
-extern crate clap;
-extern crate which;
-extern crate tempfile;
-#[macro_use]
-extern crate itertools;
-mod args;
-mod library;
-mod create;
-mod list;
-mod extract;
-
-use crate::library::*;
-use crate::args::*;
-use crate::create::create;
-use crate::list::list;
-use crate::extract::extract;
+// 既存のコードの書き方 (明示的な return など) に合わせるため、スタイル系の lint は無効にする
+#![allow(clippy::needless_return)]
+extern crate archiver;
+use std::process::exit;
+use clap::{Parser,Subcommand,ArgEnum,CommandFactory,FromArgMatches,ValueSource};
+use archiver::{CreateOptions,ExtractOptions,ListOptions,TestOptions,AppendOptions,RemoveOptions,RenameOptions,ConvertOptions,SetupOptions};
+use archiver::{ArchiverError,Config,Lang,set_lang,text};
 
-fn main() {
+#[derive(Parser)]
+#[clap(about=text("about"),after_help=text("exit-codes"))]
+pub struct Arguments {
+	#[clap(subcommand)]
+	pub command: ArcCmd,
+	#[clap(long,global=true,arg_enum,help=text("help.lang"))]
+	pub lang: Option<Lang>,
+	#[clap(long,global=true,help=text("help.profile"))]
+	pub profile: Option<String>
+}
 
-	let args = Arguments::parse();
+#[derive(Subcommand)]
+pub enum ArcCmd {
+	#[clap(name="create",aliases=&["c","archive"],about=text("about.create"))]
+	Create(CreateOptions),
+	#[clap(name="append",aliases=&["a","add"],about=text("about.append"))]
+	Append(AppendOptions),
+	#[clap(name="remove",aliases=&["rm","delete"],about=text("about.remove"))]
+	Remove(RemoveOptions),
+	#[clap(name="rename",aliases=&["mv","move"],about=text("about.rename"))]
+	Rename(RenameOptions),
+	#[clap(name="convert",aliases=&["cv","repack"],about=text("about.convert"))]
+	Convert(ConvertOptions),
+	#[clap(about=text("about.extract"))]
+	Extract(ExtractOptions),
+	#[clap(about=text("about.list"))]
+	List(ListOptions),
+	#[clap(name="test",aliases=&["t","check","verify"],about=text("about.test"))]
+	Test(TestOptions),
+	#[clap(about=text("about.setup"))]
+	Setup(SetupOptions),
+	#[clap(about=text("about.help"))]
+	Help
+}
 
-	match args.command {
-		ArcCmd::Create(d)  =>  create(d),
-		ArcCmd::List(d)    =>    list(d),
-		ArcCmd::Extract(d) => extract(d),
-		ArcCmd::Help => {},
-		_ => {
-			error("この機能は未実装です");
-		}
+/// ヘルプの言語を決めるため、引数を解析する前に --lang を読み込む
+fn lang_arg() -> Option<Lang> {
+	let args = std::env::args().collect::<Vec<_>>();
+	for (i,a) in args.iter().enumerate() {
+		if a=="--" { break; }
+		let v = match a.strip_prefix("--lang=") {
+			Some(v) => v,
+			None if a=="--lang" => match args.get(i+1) {
+				Some(v) => v.as_str(),
+				None => { break; }
+			},
+			None => { continue; }
+		};
+		return Lang::from_str(v,false).ok();
 	}
+	return None;
+}
+
+/// エラーを表示し、失敗の種類ごとの終了コードで終了する
+fn exit_with(e:&ArchiverError) -> ! {
+	eprintln!("{}",e);
+	exit(e.exit_code());
+}
+
+fn main() {
+
+	if let Some(l) = lang_arg() { set_lang(l); }
+	let m = Arguments::command().get_matches();
+	let args = Arguments::from_arg_matches(&m).unwrap_or_else(|e| e.exit() );
+	if let Some(l) = args.lang { set_lang(l); }
+
+	// 設定ファイルの値はコマンドラインで指定されていない引数にのみ使う (設定を使わないコマンドでは読み込まない)
+	let config = || Config::load(args.profile.as_deref()).unwrap_or_else(|e| exit_with(&e) );
+	let given = |id:&str| m.subcommand().is_some_and(|(_,s)| s.value_source(id)==Some(ValueSource::CommandLine) );
+
+	let r = match args.command {
+		ArcCmd::Create(o)  => config().create(o,&given).run(),
+		ArcCmd::List(o)    => config().list(o).run(),
+		ArcCmd::Test(o)    => config().test(o).run(),
+		ArcCmd::Extract(o) => config().extract(o,&given).run(),
+		ArcCmd::Append(o)  => config().append(o,&given).run(),
+		ArcCmd::Remove(o)  => config().remove(o).run(),
+		ArcCmd::Rename(o)  => config().rename(o).run(),
+		ArcCmd::Convert(o) => config().convert(o,&given).run(),
+		ArcCmd::Setup(o)   => o.run(),
+		ArcCmd::Help => Ok(())
+	};
+
+	// 失敗の種類ごとの終了コードで終了する
+	if let Err(e) = r { exit_with(&e); }
 
 }
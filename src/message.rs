@@ -0,0 +1,356 @@
+use std::env;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU8,Ordering};
+use crate::args::Lang;
+
+/// ID のメッセージを現在の言語で取り出し、 {} を順に引数で置き換える
+macro_rules! msg {
+	($id:expr) => { String::from($crate::message::text($id)) };
+	($id:expr,$($a:expr),+) => { $crate::message::fill($crate::message::text($id),&[$(&$a as &dyn std::fmt::Display),+]) };
+}
+
+/// 使用する言語 (0 は未定)
+static LANG:AtomicU8 = AtomicU8::new(0);
+
+/// メッセージとヘルプの言語を指定する
+pub fn set_lang(l:Lang) {
+	LANG.store(match l { Lang::Ja => 1, Lang::En => 2 },Ordering::Relaxed);
+}
+
+/// 現在の言語 (指定されていなければ LC_ALL, LC_MESSAGES, LANG の順に調べ、 ja で始まれば日本語、それ以外は英語にする)
+pub fn lang() -> Lang {
+	match LANG.load(Ordering::Relaxed) {
+		1 => { return Lang::Ja; },
+		2 => { return Lang::En; },
+		_ => {}
+	}
+	let l = ["LC_ALL","LC_MESSAGES","LANG"].iter().filter_map(|v| env::var(v).ok() ).find(|s| !s.is_empty() );
+	let l = match l {
+		Some(s) if s.starts_with("ja") => Lang::Ja,
+		_ => Lang::En
+	};
+	set_lang(l);
+	return l;
+}
+
+/// ID のメッセージ (一覧にない ID はそのまま返す)
+pub fn text(id:&'static str) -> &'static str {
+	return match MESSAGES.iter().find(|(i,_,_)| *i==id ) {
+		Some((_,ja,en)) => match lang() {
+			Lang::Ja => ja,
+			Lang::En => en
+		},
+		None => {
+			debug_assert!(false,"メッセージの ID がありません: {}",id);
+			id
+		}
+	};
+}
+
+/// s の {} を順に args で置き換える
+pub fn fill(s:&str,args:&[&dyn Display]) -> String {
+	let mut r = String::new();
+	let mut args = args.iter();
+	let mut parts = s.split("{}").peekable();
+	while let Some(p) = parts.next() {
+		r.push_str(p);
+		if parts.peek().is_some() {
+			if let Some(a) = args.next() { r.push_str(&a.to_string()); }
+		}
+	}
+	return r;
+}
+
+/// メッセージの一覧 (ID, 日本語, 英語)
+const MESSAGES:&[(&str,&str,&str)] = &[
+	// 共通
+	("file-not-found","ファイルが存在しません: {}","File not found: {}"),
+	("archive-not-found","アーカイブが存在しません: {}","Archive not found: {}"),
+	("unknown-type","アーカイブの種類が判定できません。 --type で指定してください","Cannot determine the archive type. Specify it with --type"),
+	("archive-unreadable","アーカイブが読み込めません: {}","Cannot read the archive: {}"),
+	("output-dir-missing","アーカイブの保存先が存在していません","The directory to save the archive in does not exist"),
+	("output-invalid","アーカイブの保存先が正しくありません","The path to save the archive to is invalid"),
+	("output-is-dir","アーカイブの保存先にフォルダが存在します","A folder exists where the archive would be saved"),
+	("copy-failed","アーカイブの複製に失敗しました: {}","Failed to copy the archive: {}"),
+	("save-failed","アーカイブの保存に失敗しました。 ({})","Failed to save the archive. ({})"),
+	("not-created","アーカイブは作成されていません。","The archive was not created."),
+	("create-failed","アーカイブの作成に失敗しました","Failed to create the archive"),
+	("create-dir-failed","アーカイブ作成ができませんでした: {}","Could not create the archive: {}"),
+	("extract-failed","展開に失敗しました","Failed to extract the archive"),
+	("list-failed","内容の表示に失敗しました","Failed to list the contents"),
+	("append-failed","ファイルの追加に失敗しました","Failed to add the files"),
+	("remove-failed","ファイルの削除に失敗しました","Failed to remove the files"),
+	("rename-failed","名前の変更に失敗しました","Failed to rename"),
+	("convert-failed","変換に失敗しました","Failed to convert the archive"),
+	("decompress-failed","解凍に失敗しました","Failed to decompress"),
+	("decompress-start-failed","解凍が開始できませんでした。 ({})","Could not start decompressing. ({})"),
+	("compress-start-failed","圧縮が開始できませんでした。","Could not start compressing."),
+	("tmp-dir-failed","作業ディレクトリが作成できませんでした: {}","Could not create a working directory: {}"),
+	("tmp-dir-remove-failed","作業ディレクトリが完全には削除されませんでした: {}","The working directory was not removed completely: {}"),
+	("path-not-utf8","パスに非対応の文字が含まれています: {}","The path contains unsupported characters: {}"),
+	("cwd-not-utf8","カレントディレクトリに展開できません。パスに非対応の文字が含まれています","Cannot extract into the current directory. Its path contains unsupported characters"),
+	("cwd-missing","カレントディレクトリが存在しません: {}","The current directory does not exist: {}"),
+	("unsupported-compress","この種類の圧縮には対応していません","This type of compression is not supported"),
+	("read-file-failed","ファイルが読み込めません: {} ({})","Cannot read the file: {} ({})"),
+	("read-dir-failed","フォルダが読み込めません: {} ({})","Cannot read the folder: {} ({})"),
+	("folder-not-replaced","フォルダはファイルで置き換えられません: {}","A folder cannot be replaced with a file: {}"),
+	("native-rate-ignored","ネイティブ実装では {} の圧縮率を指定できないため、圧縮率の指定は無視されます","The native backend cannot set the compression level of {}, so the given level is ignored"),
+	("native-unsupported","ネイティブ実装はこの種類のアーカイブのこの操作に対応していません","The native backend does not support this operation for this type of archive"),
+	("native-failed","ネイティブ実装での処理に失敗しました: {}","The native backend failed: {}"),
+	("unsupported-list","このファイルは内容の表示に対応していません","Listing the contents of this file is not supported"),
+	("unsupported-test","このファイルは検査に対応していません","Testing this file is not supported"),
+	("unsupported-extract","このファイルは展開に対応していません","Extracting this file is not supported"),
+	("unsupported-append","このファイルはファイルの追加に対応していません","Adding files to this file is not supported"),
+	("unsupported-remove","このファイルはファイルの削除に対応していません","Removing files from this file is not supported"),
+	("unsupported-rename","このファイルは名前の変更に対応していません","Renaming entries in this file is not supported"),
+	("single-compress-immutable","単一ファイルの圧縮ファイルは変更できません","A compressed single file cannot be modified"),
+	("rate-required","元の圧縮率が読み取れないので、再圧縮する圧縮率を --rate で指定してください。","The original compression level cannot be read. Specify the level to recompress with using --rate."),
+	("rate-range","圧縮率は 0-9 の整数で指定します。","Specify the compression level as an integer from 0 to 9."),
+	// 外部コマンド
+	("missing-tool","コマンド {} が見つからないので実行できません","Cannot run {} because the command was not found"),
+	("spawn-failed","コマンド {} の起動に失敗しました: {}","Failed to start {}: {}"),
+	("stdin-pass-failed","コマンド {} に入力を渡せませんでした","Could not pass the input to {}"),
+	("exit-status","{} が終了コード {} で終了しました","{} exited with status {}"),
+	("killed","{} が異常終了しました","{} terminated abnormally"),
+	("wait-failed","{} の終了を待てませんでした: {}","Could not wait for {} to finish: {}"),
+	("redirect-failed","出力先のファイルが作成できません: {} ({})","Cannot create the output file: {} ({})"),
+	("stdin-read-failed","標準入力が読み込めません: {}","Cannot read the standard input: {}"),
+	// 作成
+	("stdout-needs-type","標準出力に書き出す場合は --type でアーカイブの種類を指定してください","Specify the archive type with --type when writing to the standard output"),
+	("stdout-no-split","標準出力に書き出す場合は分割できません","The archive cannot be split when writing to the standard output"),
+	("stdout-write-failed","標準出力への書き出しに失敗しました","Failed to write to the standard output"),
+	("empty-rar","空の RAR アーカイブは作成できません","An empty RAR archive cannot be created"),
+	("split-single","分割した RAR や 7z アーカイブは同じフォルダにある入力から作成するか --keep-path を指定してください","Create a split RAR or 7z archive from inputs in the same folder or specify --keep-path"),
+	("empty-zpaq","空の ZPAQ アーカイブは作成できません","An empty ZPAQ archive cannot be created"),
+	("image-single-folder","WIM/DMG/ISO は単一のフォルダから作成することができます。","WIM/DMG/ISO can only be created from a single folder."),
+	("path-no-name","パスからファイル名が取り出せません: {}","Cannot get a file name from the path: {}"),
+	// 展開
+	("filter-ignored","圧縮ファイルの解凍では絞り込みの条件は無視されます","Filters are ignored when decompressing a compressed file"),
+	("move-extracted-failed","展開したファイルの移動に失敗しました: {}","Failed to move the extracted files: {}"),
+	("extract-into","{} に展開します","Extracting into {}"),
+	("remove-existing-failed","既存のファイルが削除できません: {}","Cannot remove the existing file: {}"),
+	("wim-target-ignored","WIM では --target フラグは無視されます","--target is ignored for WIM"),
+	("stdout-decompress-unsupported","この種類の圧縮ファイルは標準出力に解凍できません","This type of compressed file cannot be decompressed to the standard output"),
+	("decompress-save-failed","解凍ファイルの保存に失敗しました。 ({})","Failed to save the decompressed file. ({})"),
+	("output-dir-not-found","保存先のディレクトリが存在しません: {}","The output directory does not exist: {}"),
+	("disk-image-unsupported","ディスクイメージには対応していません","Disk images are not supported"),
+	("dest-not-found","保存先が存在しません","The destination does not exist"),
+	("dest-invalid","保存先が正しくありません","The destination is invalid"),
+	("stdin-dest-folder","標準入力から解凍する場合は保存先にフォルダを指定できません: {}","A folder cannot be the destination when decompressing the standard input: {}"),
+	("unsafe-path-skipped","安全でないパスのため展開しません: {}","Skipping an unsafe path: {}"),
+	// 追加、削除、名前の変更、変換
+	("append-nothing","アーカイブにファイルが追加されていません。","No files were added to the archive."),
+	("remove-disk-image","ディスクイメージからはファイルを削除できません","Files cannot be removed from a disk image"),
+	("remove-nothing","アーカイブからファイルが削除されていません。","No files were removed from the archive."),
+	("rename-empty","空のパスには変更できません","Cannot rename to an empty path"),
+	("rename-nothing","アーカイブ内の名前が変更されていません。","No entries in the archive were renamed."),
+	("map-unreadable","対応表が読み込めません: {}","Cannot read the map file: {}"),
+	("map-not-tab","対応表の {} 行目がタブ区切りになっていません","Line {} of the map file is not tab-separated"),
+	("convert-unknown-from","変換元のアーカイブの種類が判定できません。 --from で指定してください","Cannot determine the type of the source archive. Specify it with --from"),
+	("convert-same-file","変換元と同じファイルには保存できません","Cannot save to the source archive itself"),
+	("convert-read-extracted","展開したファイルが読み込めません: {}","Cannot read the extracted files: {}"),
+	// 検査
+	("test-problem","アーカイブに問題が見つかりました: {}","Problems were found in the archive: {}"),
+	("test-ok","問題は見つかりませんでした: {}","No problems were found: {}"),
+	("broken-files","{} 個のファイルに問題があります","{} files have problems"),
+	("thread-panicked","圧縮のスレッドが異常終了しました","A compression thread terminated abnormally"),
+	// 一覧
+	("list.mode","種類/権限","Mode"),
+	("list.owner","所有者","Owner"),
+	("list.size","サイズ","Size"),
+	("list.compressed","圧縮後","Compressed"),
+	("list.mtime","更新日時","Modified"),
+	("list.path","パス","Path"),
+	("list.encrypted","{} (暗号化)","{} (encrypted)"),
+	// 絞り込み
+	("exclude-from-unreadable","除外するファイルの一覧が読み込めません: {} ({})","Cannot read the exclude list: {} ({})"),
+	("stage-failed","ファイルの配置に失敗しました: {}","Failed to stage the files: {}"),
+	// 上書き
+	("ask-extract","{} は既に存在します。置き換えますか? [y]es, [n]o, [A]ll, [N]one, [r]ename: ","{} already exists. Replace it? [y]es, [n]o, [A]ll, [N]one, [r]ename: "),
+	("skipped-existing","既に存在する {} 個のファイルは展開しませんでした (--overwrite で扱いを指定できます)","Skipped {} existing files (use --overwrite to choose how to handle them)"),
+	("archive-exists","アーカイブが既に存在します: {} (--overwrite で扱いを指定できます)","The archive already exists: {} (use --overwrite to choose how to handle it)"),
+	("archive-up-to-date","アーカイブは入力ファイルより新しいので作成しませんでした: {}","The archive is newer than the input files, so it was not recreated: {}"),
+	("ask-create","{} は既に存在します。置き換えますか? [y]es, [n]o, [r]ename: ","{} already exists. Replace it? [y]es, [n]o, [r]ename: "),
+	("create-cancelled","アーカイブの作成を中止しました","Cancelled creating the archive"),
+	("replace-archive-failed","作成したアーカイブを保存先に移動できません: {}","Cannot move the created archive to the destination: {}"),
+	("save-as","{} に保存します","Saving as {}"),
+	// パスワード
+	("password-file-unreadable","パスワードファイルが読み込めません: {}","Cannot read the password file: {}"),
+	("password-env-unset","環境変数 {} が設定されていません","The environment variable {} is not set"),
+	("encryption-unsupported","この種類のアーカイブは暗号化に対応していません","This type of archive does not support encryption"),
+	("password-prompt","パスワード: ","Password: "),
+	("password-required","暗号化されたアーカイブです。 --password, --password-file, --password-env のいずれかでパスワードを指定してください","The archive is encrypted. Specify the password with --password, --password-file or --password-env"),
+	("password-no-safe-channel","{} にはパスワードを安全に渡せません。 --backend native を指定してください","Cannot pass the password to {} safely. Specify --backend native"),
+	("password-pass-failed","パスワードを渡すためのファイルが作成できません: {}","Cannot create a file to pass the password: {}"),
+	// 属性
+	("preserve-native"," (ネイティブ実装)"," (native backend)"),
+	("preserve-warning","警告: {}{} では次の属性を保存できません","Warning: {}{} cannot preserve the following attributes"),
+	// 進行状況
+	("eta","  残り {}","  {} left"),
+	// 分割
+	("split-size-invalid","分割の大きさが正しくありません: {}","Invalid split size: {}"),
+	("split-failed","アーカイブの分割に失敗しました","Failed to split the archive"),
+	("join-failed","ボリュームの結合に失敗しました","Failed to join the volumes"),
+	("volume-not-found","ボリュームが見つかりません: {}","Volume not found: {}"),
+	// 再現可能なアーカイブ
+	("reproducible-native","ネイティブ実装では --reproducible に対応していません","The native backend does not support --reproducible"),
+	("reproducible-encrypt","暗号化する場合は --reproducible に対応していません","--reproducible is not supported with encryption"),
+	("reproducible-type","{} は --reproducible に対応していません","{} does not support --reproducible"),
+	("source-date-epoch-invalid","SOURCE_DATE_EPOCH の値が正しくありません: {}","Invalid SOURCE_DATE_EPOCH: {}"),
+	("file-list-failed","ファイルの一覧が作成できません: {}","Cannot create the file list: {}"),
+	("reproducible-special","--reproducible では特殊なファイルは追加できません: {}","Special files cannot be added with --reproducible: {}"),
+	// 安全性の確認
+	("max-size-invalid","--max-size の指定が正しくありません: {}","Invalid --max-size: {}"),
+	("max-ratio-invalid","--max-ratio の指定が正しくありません: {}","Invalid --max-ratio: {}"),
+	("limit-size","展開後の大きさ ({} バイト) が --max-size の {} バイトを超える","the extracted size ({} bytes) exceeds --max-size of {} bytes"),
+	("limit-ratio","展開後の大きさ ({} バイト) がアーカイブの {} 倍を超える","the extracted size ({} bytes) exceeds {} times the archive size"),
+	("limit-files","ファイルの数 ({}) が --max-files の {} を超える","the number of files ({}) exceeds --max-files of {}"),
+	("limit-abort","{}ので展開を中止しました","Aborted extraction because {}"),
+	("extract-aborted","展開を中止しました","Extraction was aborted"),
+	("scan-size-only","アーカイブ内のファイルが確認できないので、展開中の大きさだけを確認します","Cannot inspect the files in the archive, so only the size is checked while extracting"),
+	("scan-failed","アーカイブ内のファイルが確認できないので展開を中止しました。 --unsafe を指定すると確認せずに展開します","Aborted extraction because the files in the archive cannot be inspected. Specify --unsafe to extract without checking"),
+	("unsafe-found","次のファイルは安全に展開できません","The following files cannot be extracted safely"),
+	("unsafe-abort","安全でないファイルを含むので展開を中止しました。 --unsafe を指定すると確認せずに展開します","Aborted extraction because the archive contains unsafe files. Specify --unsafe to extract without checking"),
+	("unsafe.absolute","絶対パス","absolute path"),
+	("unsafe.parent","../ を含むパス","path containing ../"),
+	("unsafe.special","デバイスファイルなどの特殊なファイル","device or other special file"),
+	("unsafe.unknown-link","リンク先が確認できないリンク","link with an unknown target"),
+	("unsafe.symlink-out","展開先の外を指すシンボリックリンク","symbolic link pointing outside the destination"),
+	("unsafe.hardlink-out","展開先の外を指すハードリンク","hard link pointing outside the destination"),
+	("unsafe.through-link","シンボリックリンクを経由するパス","path through a symbolic link"),
+	// setup
+	("setup.type","種類","Type"),
+	("setup.create","作成","create"),
+	("setup.list","一覧","list"),
+	("setup.extract","展開","extract"),
+	("setup.append","追加","append"),
+	("setup.remove","削除","remove"),
+	("setup.rename","改名","rename"),
+	("setup.test","検査","test"),
+	("setup.legend","(○: 使用可能 △: ネイティブ実装のみ使用可能 ×: コマンドが不足 -: 非対応)","(○: available △: native backend only ×: missing commands -: not supported)"),
+	("setup.all-installed","必要なコマンドは全てインストールされています","All required commands are installed"),
+	("setup.missing","見つからないコマンド: {}","Missing commands: {}"),
+	("setup.no-package-manager","対応しているパッケージマネージャ (apt, dnf, pacman, brew) が見つかりません","No supported package manager (apt, dnf, pacman, brew) was found"),
+	("setup.unavailable","次のコマンドはパッケージマネージャからインストールできません: {}","The following commands cannot be installed with the package manager: {}"),
+	("setup.install-command","インストールコマンド: {}","Install command: {}"),
+	("setup.install-failed","パッケージのインストールに失敗しました","Failed to install the packages"),
+	("setup.need-yes","実行するには --yes を指定してください","Specify --yes to run it"),
+	// 設定ファイル
+	("config-unreadable","設定ファイルが読み込めません: {} ({})","Cannot read the configuration file: {} ({})"),
+	("config-syntax","{}: 設定ファイルが正しくありません\n{}","{}: Invalid configuration file\n{}"),
+	("config-section","{}: 不明なセクションです: {}","{}: Unknown section: {}"),
+	("config-unknown-key","{}: 不明な設定です: {}","{}: Unknown setting: {}"),
+	("config-invalid-choice","使用できない値です: {}","invalid value: {}"),
+	("config-not-positive","1 以上の値を指定してください","expected a value of 1 or more"),
+	("profile-not-found","プロファイルが設定ファイルにありません: {}","Profile not found in the configuration files: {}"),
+	// 終了コード
+	("exit-codes","終了コード:
+    0  成功
+    1  外部コマンドやファイルの読み書きの失敗
+    2  引数の誤り、ファイルが存在しない
+    3  必要なコマンドが見つからない
+    4  対応していない種類や操作
+    5  アーカイブが壊れているか読み込めない
+    6  安全でないファイルを含むか --max-* の上限を超える","EXIT STATUS:
+    0  Success
+    1  An external command or reading/writing a file failed
+    2  Invalid arguments or a file does not exist
+    3  A required command was not found
+    4  Unsupported type or operation
+    5  The archive is corrupt or unreadable
+    6  The archive contains unsafe files or exceeds a --max-* limit"),
+	// ヘルプ
+	("about","アーカイブコマンドのラッパー","A wrapper for archive commands"),
+	("about.create","新しいアーカイブを作成します。単一ファイルの圧縮やディスクイメージの作成にも対応しています。","Create a new archive. Also compresses a single file or creates a disk image."),
+	("about.append","アーカイブにファイルを追加します。","Add files to an archive."),
+	("about.remove","アーカイブからファイルを削除します。","Remove files from an archive."),
+	("about.rename","アーカイブ内のファイル名を変更します。","Rename files in an archive."),
+	("about.convert","アーカイブを別の種類のアーカイブに変換します。","Convert an archive into another type of archive."),
+	("about.extract","アーカイブを展開します。","Extract an archive."),
+	("about.list","アーカイブの内容を表示します。","List the contents of an archive."),
+	("about.test","アーカイブが破損していないか検査します。","Test whether an archive is corrupt."),
+	("about.setup","このコマンドで使用する外部コマンドの有無を確認し、不足しているパッケージのインストールコマンドを表示します","Check for the external commands used by this tool and show the command to install missing packages"),
+	("about.help","ヘルプを表示します。","Show help."),
+	("help.lang","メッセージとヘルプの言語を指定します。標準では環境変数 LC_ALL, LC_MESSAGES, LANG から判定し、 ja で始まる場合は日本語、それ以外は英語になります。","Language of messages and help. By default it is taken from LC_ALL, LC_MESSAGES or LANG: Japanese if it starts with ja, English otherwise."),
+	("help.profile","設定ファイル (~/.config/archiver/config.toml と .archiver.toml) の [profile.名前] の設定を使います。コマンドラインで指定した引数は設定より優先されます。","Use the [profile.NAME] settings of the configuration files (~/.config/archiver/config.toml and .archiver.toml). Arguments given on the command line take precedence over the settings."),
+	("value.lang.ja","日本語","Japanese"),
+	("value.lang.en","英語","English"),
+	("help.create.input","アーカイブに追加するファイルを指定します。指定しない場合は、空のアーカイブが作成されることがあります。","Files to add to the archive. Without any, an empty archive may be created."),
+	("help.create.output","出力先となるアーカイブを指定します。 - を指定すると標準出力に書き出します (--type が必要です)。","The archive to create. - writes to the standard output (requires --type)."),
+	("help.create.arc-type","アーカイブの種類を変更します。標準では出力ファイルの拡張子から判定します。圧縮系のフォーマットは複数の入力ファイルに対して自動的に tar アーカイブにした上で圧縮します。","Type of the archive. By default it is taken from the extension of the output file. Compression formats put multiple input files into a tar archive and compress it."),
+	("help.backend","使用する実装を指定します。標準では外部コマンドが揃っていればそれを使い、なければネイティブ実装を使います。","Implementation to use. By default the external commands are used if they are all installed, otherwise the native implementation."),
+	("help.create.rate","圧縮を伴うアーカイブにおいて圧縮率を指定します。","Compression level for archives that compress."),
+	("help.verbose-files","ファイルごとの処理状況などを出力します","Show what is done for each file"),
+	("help.progress","進行状況の表示方法を指定します。 auto は標準エラー出力が端末の場合にバーを表示し (-v を指定した場合を除く)、 json は標準エラー出力にイベントを1行ずつ JSON で出力します。","How to show progress. auto shows a bar if the standard error is a terminal (unless -v is given), and json writes events to the standard error as one JSON object per line."),
+	("help.create.overwrite","保存先のアーカイブが既に存在する場合の扱いを指定します。 newer は入力ファイルのいずれかがアーカイブより新しい場合に作成し直し、 rename は \"名前 (1).zip\" のような名前で保存します。 ask は標準入力が端末でなければ never と同じです。","What to do if the archive already exists. newer recreates it if any input file is newer than the archive, and rename saves it under a name like \"name (1).zip\". ask is the same as never if the standard input is not a terminal."),
+	("help.create.image-name","DMG,ISO の場合にディスクの名前を指定します。フォルダから作成する場合は無視されます。","Volume name for DMG and ISO. Ignored when creating from a folder."),
+	("help.keep-path","追加するファイルのパスをアーカイブの階層構造に使用します。指定しない場合は追加するファイルをルート階層に配置します。","Keep the paths of the added files in the archive. Otherwise the files are placed at the root of the archive."),
+	("help.create.threads","圧縮に使用するスレッドの数を指定します (0 は CPU の数)。 pigz, pbzip2, xz -T, zstd -T, plzip などの並列に圧縮できるコマンドを使い、 gzip と bzip2 でそれらのコマンドがない場合はネイティブ実装で並列に圧縮します。","Number of threads to compress with (0 is the number of CPUs). Uses parallel compressors such as pigz, pbzip2, xz -T, zstd -T and plzip, and compresses gzip and bzip2 in parallel with the native implementation if they are not installed."),
+	("help.create.preserve","保存する属性をカンマ区切りで指定します (xattrs,acls,selinux,owner,sparse,hardlinks)。アーカイブの種類で保存できないものは警告を表示します。","Comma-separated attributes to store (xattrs,acls,selinux,owner,sparse,hardlinks). Shows a warning for those the archive type cannot store."),
+	("help.create.split-size","指定した大きさ (2G, 500M など) ごとにアーカイブを分割します。 zip は .z01, 7z は .001, rar は .part1.rar, それ以外は .part001 の形式になります。","Split the archive into volumes of this size (2G, 500M, etc.). zip uses .z01, 7z .001, rar .part1.rar and the others .part001."),
+	("help.create.reproducible","同じ入力から常に同じアーカイブを作成します。ファイルを名前順に並べ、更新日時を環境変数 SOURCE_DATE_EPOCH の時刻 (設定されていなければ 1980-01-01) より新しければその時刻にし、所有者を 0 に、パーミッションを 644/755 に揃え、拡張属性を除きます。 tar, cpio, zip, 7z と圧縮ファイルに対応しています。","Always create the same archive from the same input. Sorts files by name, clamps modification times to SOURCE_DATE_EPOCH (1980-01-01 if unset), sets the owner to 0, normalises permissions to 644/755 and drops extended attributes. Supports tar, cpio, zip, 7z and compression formats."),
+	("help.append.input","アーカイブに追加するファイルを指定します。","Files to add to the archive."),
+	("help.append.output","ファイルを追加する既存のアーカイブを指定します。","The existing archive to add the files to."),
+	("help.arc-type-existing","アーカイブの種類を変更します。標準ではファイルの内容と拡張子から判定します。圧縮系のフォーマットを指定した場合は tar アーカイブを圧縮したものとして扱います。","Type of the archive. By default it is determined from the contents and the extension. A compression format is treated as a compressed tar archive."),
+	("help.append.rate","圧縮を伴うアーカイブにおいて追加するファイルの圧縮率を指定します (既定は 6)。圧縮された tar アーカイブは、指定がなければ元の圧縮率で再圧縮します。元の圧縮率が読み取れるのは gzip と bzip2 のみで、それ以外では指定が必要です。","Compression level for the added files in archives that compress (6 by default). A compressed tar archive is recompressed with its original level unless this is given. The original level can only be read from gzip and bzip2, so this is required for the others."),
+	("help.verbose","進行状況などを出力します","Show progress and other details"),
+	("help.remove.input","ファイルを削除するアーカイブを指定します。","The archive to remove files from."),
+	("help.remove.target","削除するアーカイブ内のパスを指定します。フォルダを指定した場合は中身も削除されます。","Paths in the archive to remove. The contents of a folder are removed as well."),
+	("help.rate","圧縮された tar アーカイブを再圧縮する際の圧縮率を指定します。指定がなければ元の圧縮率で再圧縮します。元の圧縮率が読み取れるのは gzip と bzip2 のみで、それ以外では指定が必要です。","Compression level to recompress a compressed tar archive with. The original level is kept unless this is given. The original level can only be read from gzip and bzip2, so this is required for the others."),
+	("help.rename.input","名前を変更するアーカイブを指定します。","The archive to rename files in."),
+	("help.rename.old","変更前のアーカイブ内のパスを指定します。フォルダを指定した場合は中身も移動します。","Path in the archive before renaming. The contents of a folder are moved as well."),
+	("help.rename.new","変更後のアーカイブ内のパスを指定します。","Path in the archive after renaming."),
+	("help.rename.map","変更前と変更後のパスをタブ区切りで1行ずつ記述したファイルを指定し、まとめて名前を変更します。","File listing the old and new paths separated by a tab, one pair per line, to rename them all at once."),
+	("help.convert.input","変換元のアーカイブを指定します。","The archive to convert."),
+	("help.convert.output","変換先のアーカイブを指定します。","The archive to convert into."),
+	("help.convert.from","変換元のアーカイブの種類を指定します。標準ではファイルの内容から判定し、判定できない場合は拡張子から判定します。","Type of the source archive. By default it is determined from the contents, or from the extension if that fails."),
+	("help.convert.arc-type","変換先のアーカイブの種類を指定します。標準では出力ファイルの拡張子から判定します。","Type of the converted archive. By default it is taken from the extension of the output file."),
+	("help.convert.rate","変換先のアーカイブの圧縮率を指定します。","Compression level of the converted archive."),
+	("help.convert.overwrite","変換先のアーカイブが既に存在する場合の扱いを指定します。 newer は変換元のアーカイブの方が新しい場合に変換し直し、 rename は \"名前 (1).zip\" のような名前で保存します。 ask は標準入力が端末でなければ never と同じです。","What to do if the converted archive already exists. newer converts again if the source archive is newer, and rename saves it under a name like \"name (1).zip\". ask is the same as never if the standard input is not a terminal."),
+	("help.list.input","リスト表示するアーカイブを指定します。 - を指定すると標準入力から読み込みます。","The archive to list. - reads from the standard input."),
+	("help.arc-type","アーカイブの種類を変更します。標準ではファイルの内容から判定し、判定できない場合は拡張子から判定します。","Type of the archive. By default it is determined from the contents, or from the extension if that fails."),
+	("help.list.checksum","tar などのチェックサムを記録しない形式でも、全てのデータを読み込んで SHA-256 を計算します。","Read all the data and compute SHA-256 even for formats that store no checksum, such as tar."),
+	("help.list.format","出力の形式を指定します。 table 以外はスクリプトで扱うための形式です。","Output format. The formats other than table are meant for scripts."),
+	("help.test.input","検査するアーカイブを指定します。 - を指定すると標準入力から読み込みます。","The archive to test. - reads from the standard input."),
+	("help.test.verbose","問題のないファイルも含めて、ファイルごとの検査結果を出力します","Show the result for every file, including those without problems"),
+	("help.extract.input","展開するアーカイブファイルを指定します。 - を指定すると標準入力から読み込みます。圧縮ファイルは --type を指定すればそのままパイプで解凍し、 tar アーカイブは --unsafe も指定した場合だけパイプで展開します。それ以外の場合 (--type を指定しない場合や --max-ratio を指定した場合など) は作業ディレクトリに保存し、展開前の確認をしてから展開します。","The archive to extract. - reads from the standard input. Compressed files are decompressed through a pipe if --type is given, and tar archives are extracted through a pipe only if --unsafe is also given. Otherwise, e.g. without --type or with --max-ratio, the input is saved to a working directory and checked before extracting."),
+	("help.extract.output","アーカイブの展開先となるディレクトリを指定します。或いは、解凍した圧縮ファイルの保存先を指定します (- は標準出力)。指定しない場合は現在のディレクトリに展開/解凍されます。","Directory to extract the archive into, or where to save a decompressed file (- is the standard output). Defaults to the current directory."),
+	("help.extract.target","展開対象のファイルを指定します。","Files to extract."),
+	("help.extract.no-untar","圧縮された tar アーカイブを展開せず、解凍した tar アーカイブをそのまま保存します。","Save the decompressed tar archive instead of extracting it."),
+	("help.extract.smart","アーカイブのルートに複数のファイルがある場合は、アーカイブの名前から拡張子を除いた名前のフォルダにまとめて展開します。1つのフォルダやファイルだけの場合はそのまま展開します。どちらも既存のものと名前が重なる場合は \"名前 (1)\" のような名前にします。","If the archive has several files at its root, extract them into a folder named after the archive without its extension. A single folder or file is extracted as is. Either way a name like \"name (1)\" is used if it clashes with an existing one."),
+	("help.extract.preserve","復元する属性をカンマ区切りで指定します (xattrs,acls,selinux,owner,sparse,hardlinks)。アーカイブの種類で保存できないものは警告を表示します。","Comma-separated attributes to restore (xattrs,acls,selinux,owner,sparse,hardlinks). Shows a warning for those the archive type cannot store."),
+	("help.extract.overwrite","展開先に同じ名前のファイルが既に存在する場合の扱いを指定します。 newer はアーカイブ内のファイルの方が新しい場合に置き換え、 rename は \"名前 (1).txt\" のような名前で保存します。 ask は標準入力が端末でなければ never と同じです。","What to do if a file with the same name already exists. newer replaces it if the file in the archive is newer, and rename saves it under a name like \"name (1).txt\". ask is the same as never if the standard input is not a terminal."),
+	("help.safety.allow-unsafe","展開前の確認を行いません。標準では ../ や絶対パスを含むファイル、デバイスファイル、展開先の外を指すリンクがあれば展開せずに終了します。","Do not check the archive before extracting. By default nothing is extracted if it contains paths with ../ or absolute paths, device files, or links pointing outside the destination."),
+	("help.safety.max-size","展開後の大きさの上限を指定します (例: 10G)。超える場合は展開前、或いは展開中に中止します。","Maximum size after extraction (e.g. 10G). Extraction is aborted before or during extraction if it is exceeded."),
+	("help.safety.max-files","展開するファイルの数の上限を指定します。","Maximum number of files to extract."),
+	("help.safety.max-ratio","展開後の大きさとアーカイブの大きさの比の上限を指定します (例: 100)。標準出力への解凍では展開中の大きさは確認されません。","Maximum ratio of the extracted size to the archive size (e.g. 100). The size is not checked while decompressing to the standard output."),
+	("help.filter.include","指定したパターン (gitignore と同じ書式) に該当するファイルのみを対象にします。複数指定できます。","Only process files matching the pattern (same syntax as gitignore). Can be given more than once."),
+	("help.filter.exclude","指定したパターン (gitignore と同じ書式) に該当するファイルを除外します。 / を含むパターンはアーカイブ内のパスと比較し、 ! で始まるパターンは除外を取り消します。複数指定できます。各フォルダの .archiverignore も同様に読み込みます。","Exclude files matching the pattern (same syntax as gitignore). Patterns containing / are matched against the path in the archive, and patterns starting with ! cancel an exclusion. Can be given more than once. .archiverignore in each folder is read as well."),
+	("help.filter.exclude-from","除外するパターンを1行ずつ記述したファイルを指定します。","File with one pattern to exclude per line."),
+	("help.filter.exclude-vcs",".git などのバージョン管理システムのファイルを除外します。","Exclude version control files such as .git."),
+	("help.password.password","暗号化に使用するパスワードを指定します。コマンドラインに残るため、 --password-file か --password-env の使用を推奨します。","Password for encryption. It remains in the command line history, so --password-file or --password-env is recommended."),
+	("help.password.password-file","パスワードを1行目に記述したファイルを指定します。","File with the password on its first line."),
+	("help.password.password-env","パスワードを設定した環境変数の名前を指定します。","Name of the environment variable holding the password."),
+	("help.setup.yes","不足しているパッケージを検出したパッケージマネージャで実際にインストールします。","Actually install the missing packages with the detected package manager."),
+	("value.format.table","人が読むための表","Table for humans"),
+	("value.format.json","全てのファイルを1つの配列にした JSON","JSON with all files in one array"),
+	("value.format.ndjson","ファイルごとに1行の JSON","One JSON object per file and line"),
+	("value.format.csv","見出し行つきの CSV","CSV with a header row"),
+	("value.preserve.xattrs","拡張属性","Extended attributes"),
+	("value.preserve.acls","ACL","ACLs"),
+	("value.preserve.selinux","SELinux のラベル","SELinux labels"),
+	("value.preserve.owner","所有者とグループ","Owner and group"),
+	("value.preserve.sparse","スパースファイル","Sparse files"),
+	("value.preserve.hardlinks","ハードリンク","Hard links"),
+	("value.overwrite.never","既存のものを残す","Keep the existing one"),
+	("value.overwrite.always","常に置き換える","Always replace"),
+	("value.overwrite.ask","端末で確認する (端末でなければ never と同じ)","Ask on the terminal (same as never without one)"),
+	("value.overwrite.newer","新しい場合だけ置き換える","Replace only if newer"),
+	("value.overwrite.rename","重ならない名前で保存する","Save under a name that does not clash"),
+	("value.progress.auto","端末であればバーを表示する","Show a bar on a terminal"),
+	("value.progress.bar","常にバーを表示する","Always show a bar"),
+	("value.progress.json","開始、処理中のファイル、進行状況、終了のイベントを1行ずつ JSON で出力する","Write start, current file, progress and finish events as one JSON object per line"),
+	("value.progress.none","表示しない","Show nothing"),
+	("value.backend.native","Rust で実装したもの (zip, tar, gzip, bzip2, xz, zstd のみ)","Implemented in Rust (zip, tar, gzip, bzip2, xz and zstd only)"),
+	("value.backend.external","外部コマンドを実行するもの","Runs external commands"),
+];
@@ -0,0 +1,630 @@
+use std::fs::{self,File,OpenOptions,Permissions};
+use std::io::{self,Read,Write,Seek,SeekFrom,BufReader,BufWriter};
+use std::os::unix::fs::{PermissionsExt,symlink};
+use flate2::write::GzEncoder;
+use flate2::read::MultiGzDecoder;
+use bzip2::write::BzEncoder;
+use bzip2::read::MultiBzDecoder;
+use ruzstd::encoding::CompressionLevel;
+use ruzstd::decoding::StreamingDecoder;
+use zip::{ZipWriter,ZipArchive,CompressionMethod,DateTime,ExtraField,AesMode};
+use zip::write::{FileOptions,SimpleFileOptions};
+use zip::read::ZipFile;
+use zip::result::ZipResult;
+use crate::library::*;
+use crate::args::*;
+use crate::error::*;
+use crate::backend::*;
+use crate::entry::*;
+use crate::create::{get_pi,create_type,CreateType};
+use crate::safety::Abortable;
+use crate::filter::Filter;
+use crate::progress::{self,Counted};
+use crate::message::text;
+
+/// ネイティブ実装が対応している種類と操作
+pub fn native_supports(arc_type:&ArcType,op:Operation) -> bool {
+	return match (arc_type,op) {
+		(_,Operation::Rename) => false,
+		(ArcType::Zip,_)|(ArcType::Tar,_) => true,
+		(ArcType::Gzip,_)|(ArcType::Bzip2,_)|(ArcType::Xz,_)|(ArcType::Zstd,_) => true,
+		_ => false
+	};
+}
+
+/// 圧縮ファイルの中身が tar アーカイブであるかをネイティブ実装で判定 (解凍できない種類か読み込めない場合は None)
+pub fn native_contains_tar(arc_type:&ArcType,file:&String) -> Option<bool> {
+	if !is_compress(arc_type) || !native_supports(arc_type,Operation::Extract) { return None; }
+	return contains_tar(arc_type,file).ok();
+}
+
+/// Rust のクレートで処理する実装 (外部コマンドが使えない環境向け)
+pub struct Native;
+impl Backend for Native {
+	fn create(&self,d:&CreateData,arc_type:&ArcType) -> Result<()> {
+		return report(native_create(d,arc_type));
+	}
+	fn list(&self,d:&ListData,arc_type:&ArcType) -> Result<Vec<Entry>> {
+		return report(native_list(d,arc_type));
+	}
+	fn test(&self,d:&TestData,arc_type:&ArcType) -> Result<()> {
+		return report(native_test(d,arc_type));
+	}
+	fn extract(&self,d:&ExtractData,arc_type:&ArcType,untar:bool,output:&str) -> Result<()> {
+		return report(native_extract(d,arc_type,untar,output));
+	}
+	fn append(&self,d:&AppendData,arc_type:&ArcType) -> Result<()> {
+		return report(native_append(d,arc_type));
+	}
+	fn remove(&self,d:&RemoveData,arc_type:&ArcType) -> Result<()> {
+		return report(native_remove(d,arc_type));
+	}
+	fn native(&self) -> bool {
+		return true;
+	}
+}
+
+/// 読み書きの失敗を ArchiverError に変換する
+fn report<T>(r:io::Result<T>) -> Result<T> {
+	return r.map_err(|e| {
+		match ArchiverError::from(e) {
+			ArchiverError::Failed(m) => ArchiverError::Failed(msg!("native-failed",m)),
+			e => e
+		}
+	});
+}
+
+fn native_create(d:&CreateData,arc_type:&ArcType) -> io::Result<()> {
+
+	let ip = d.input.iter().map(Path::new).collect::<Vec<_>>();
+	let tmp = tempdir()?;
+	let archive = tmp.path().join("archive");
+	let threads = thread_count(&d.threads);
+
+	match (create_type(&ip),arc_type) {
+		(_,ArcType::Zip) => {
+			zip_create(&walk_inputs(&ip,&d.keep_path,&d.base,None)?,File::create(&archive)?,d.rate,&d.pw.password,d.verbose)?;
+		},
+		(_,ArcType::Tar) => {
+			tar_create(&walk_inputs(&ip,&d.keep_path,&d.base,None)?,File::create(&archive)?,d.verbose)?;
+		},
+		// 並列に圧縮する場合は tar アーカイブを一時ファイルに書き出さずに圧縮する
+		(ct,at) if threads>1 && matches!(at,ArcType::Gzip|ArcType::Bzip2) => {
+			let mut e = ParallelEncoder::new(*at,BufWriter::new(File::create(&archive)?),d.rate,threads);
+			match ct {
+				CreateType::SingleFile => { io::copy(&mut Counted(File::open(ip[0])?),&mut e)?; },
+				_ => { e = tar_create(&walk_inputs(&ip,&d.keep_path,&d.base,None)?,e,d.verbose)?; }
+			}
+			e.finish()?.flush()?;
+		},
+		// 単一ファイルの圧縮
+		(CreateType::SingleFile,at) => {
+			compress(at,Counted(File::open(ip[0])?),File::create(&archive)?,d.rate)?;
+		},
+		// tar アーカイブにしてから圧縮
+		(_,at) => {
+			let mut t = tar_create(&walk_inputs(&ip,&d.keep_path,&d.base,None)?,tempfile::tempfile()?,d.verbose)?;
+			t.seek(SeekFrom::Start(0))?;
+			compress(at,t,File::create(&archive)?,d.rate)?;
+		}
+	}
+
+	if fs::hard_link(&archive,&d.output).is_err() {
+		fs::copy(&archive,&d.output)?;
+	}
+	tmp.close()?;
+	return Ok(());
+
+}
+
+fn native_list(d:&ListData,arc_type:&ArcType) -> io::Result<Vec<Entry>> {
+	return native_entries(&d.input,arc_type);
+}
+
+/// アーカイブ内のファイルの一覧 (展開前の確認にも使う)
+pub fn native_entries(input:&String,arc_type:&ArcType) -> io::Result<Vec<Entry>> {
+	let mut l = vec![];
+	match arc_type {
+		ArcType::Zip => {
+			let mut z = ZipArchive::new(File::open(input)?)?;
+			for i in 0..z.len() {
+				let f = z.by_index_raw(i)?;
+				let mut e = Entry::new(f.name().to_string());
+				e.kind = match (f.is_dir(),f.is_symlink()) {
+					(true,_) => EntryKind::Dir,
+					(_,true) => EntryKind::Symlink,
+					_ => EntryKind::File
+				};
+				e.size = Some(f.size());
+				e.compressed_size = Some(f.compressed_size());
+				e.mode = f.unix_mode().map(|m| m & 0o7777 );
+				e.checksum = Some(format!("crc32:{:08x}",f.crc32()));
+				e.encrypted = f.encrypted();
+				// DOS 形式の日時はタイムゾーンが分からないので、拡張タイムスタンプがある場合のみ使う
+				for x in f.extra_data_fields() {
+					if let ExtraField::ExtendedTimestamp(t) = x {
+						e.mtime = t.mod_time().map(|t| t as i64 );
+					}
+				}
+				l.push(e);
+			}
+		},
+		at => {
+			if is_compress(at) && !contains_tar(at,input)? {
+				return Err(io::Error::new(io::ErrorKind::Unsupported,text("unsupported-list")));
+			}
+			let mut a = tar::Archive::new(decompress_reader(at,File::open(input)?)?);
+			for e in a.entries()? {
+				let e = e?;
+				let h = e.header();
+				let mut n = Entry::new(String::from_utf8_lossy(&e.path_bytes()).to_string());
+				n.kind = match h.entry_type() {
+					t if t.is_file() => EntryKind::File,
+					t if t.is_dir() => EntryKind::Dir,
+					t if t.is_symlink() => EntryKind::Symlink,
+					t if t.is_hard_link() => EntryKind::HardLink,
+					_ => EntryKind::Other
+				};
+				n.size = h.size().ok();
+				n.mode = h.mode().ok().map(|m| m & 0o7777 );
+				n.mtime = h.mtime().ok().map(|t| t as i64 );
+				n.uid = h.uid().ok().map(|u| u as u32 );
+				n.gid = h.gid().ok().map(|g| g as u32 );
+				n.link = e.link_name()?.map(|p| p.to_string_lossy().to_string() );
+				l.push(n);
+			}
+		}
+	}
+	return Ok(l);
+}
+
+fn native_test(d:&TestData,arc_type:&ArcType) -> io::Result<()> {
+	// 全てのデータを読み込み、問題のあったファイルを数える
+	let mut broken = 0;
+	match arc_type {
+		ArcType::Zip => {
+			let mut z = ZipArchive::new(File::open(&d.input)?)?;
+			for i in 0..z.len() {
+				let name = z.by_index_raw(i)?.name().to_string();
+				let r = zip_by_index(&mut z,i,&d.pw.password).map_err(io::Error::from).and_then(|mut f| io::copy(&mut f,&mut io::sink()) );
+				test_report(&name,r,d.verbose,&mut broken);
+			}
+		},
+		at if is_compress(at) && !contains_tar(at,&d.input)? => {
+			let r = decompress_reader(at,File::open(&d.input)?).and_then(|mut r| io::copy(&mut r,&mut io::sink()) );
+			test_report(&d.input,r,d.verbose,&mut broken);
+		},
+		at => {
+			let mut a = tar::Archive::new(decompress_reader(at,File::open(&d.input)?)?);
+			for e in a.entries()? {
+				let mut e = e?;
+				let name = String::from_utf8_lossy(&e.path_bytes()).to_string();
+				test_report(&name,io::copy(&mut e,&mut io::sink()),d.verbose,&mut broken);
+			}
+		}
+	}
+	if broken>0 {
+		return Err(io::Error::other(msg!("broken-files",broken)));
+	}
+	return Ok(());
+}
+
+/// ファイルごとの検査結果を出力する (問題のないファイルは verbose の場合のみ)
+fn test_report(name:&str,r:io::Result<u64>,verbose:bool,broken:&mut usize) {
+	match r {
+		Ok(_) => { if verbose { println!("OK: {}",name); } },
+		Err(e) => {
+			eprintln!("NG: {} ({})",name,e);
+			*broken += 1;
+		}
+	}
+}
+
+fn native_extract(d:&ExtractData,arc_type:&ArcType,untar:bool,output:&str) -> io::Result<()> {
+	match arc_type {
+		ArcType::Zip => {
+			let mut z = ZipArchive::new(Abortable(Counted(File::open(&d.input)?)))?;
+			for i in 0..z.len() {
+				let mut f = zip_by_index(&mut z,i,&d.pw.password)?;
+				if !d.target.is_empty() && !matches_target(f.name(),&d.target) { continue; }
+				if d.verbose { eprintln!("{}",f.name()); }
+				progress::file(f.name(),None);
+				let path = match f.enclosed_name() {
+					Some(p) => Path::new(output).join(p),
+					None => {
+						eprintln!("{}",msg!("unsafe-path-skipped",f.name()));
+						continue;
+					}
+				};
+				if f.is_dir() {
+					fs::create_dir_all(&path)?;
+				}
+				else {
+					if let Some(p) = path.parent() { fs::create_dir_all(p)?; }
+					if f.is_symlink() {
+						let mut target = String::new();
+						f.read_to_string(&mut target)?;
+						symlink(target,&path)?;
+						continue;
+					}
+					io::copy(&mut f,&mut File::create(&path)?)?;
+				}
+				if let Some(m) = f.unix_mode() {
+					fs::set_permissions(&path,Permissions::from_mode(m & 0o7777))?;
+				}
+			}
+		},
+		at if untar || *at==ArcType::Tar => {
+			let mut a = tar::Archive::new(decompress_reader(at,Abortable(Counted(File::open(&d.input)?)))?);
+			a.set_preserve_permissions(true);
+			a.set_preserve_mtime(true);
+			a.set_preserve_ownerships(d.preserve.contains(&Preserve::Owner));
+			a.set_unpack_xattrs(d.preserve.contains(&Preserve::Xattrs));
+			if d.target.is_empty() && !d.verbose && !progress::active() { a.unpack(output)?; }
+			else {
+				for e in a.entries()? {
+					let mut e = e?;
+					let name = String::from_utf8_lossy(&e.path_bytes()).to_string();
+					if d.target.is_empty() || matches_target(&name,&d.target) {
+						if d.verbose { eprintln!("{}",name); }
+						progress::file(&name,None);
+						e.unpack_in(output)?;
+					}
+				}
+			}
+		},
+		// 単一ファイルの解凍 ("-" は標準出力)
+		at => {
+			let mut r = decompress_reader(at,Abortable(Counted(File::open(&d.input)?)))?;
+			if is_stdio(output) { io::copy(&mut r,&mut io::stdout().lock())?; }
+			else { io::copy(&mut r,&mut File::create(output)?)?; }
+		}
+	}
+	return Ok(());
+}
+
+fn native_append(d:&AppendData,arc_type:&ArcType) -> io::Result<()> {
+
+	let ip = d.input.iter().map(Path::new).collect::<Vec<_>>();
+	let tmp = tempdir()?;
+	let archive = tmp.path().join("archive");
+	// 外部コマンドでの追加と同じく、種類ごとの既定の除外の条件で絞り込む
+	let filter = Filter::defaults(arc_type);
+
+	match arc_type {
+		ArcType::Zip => {
+			fs::copy(&d.output,&archive)?;
+			let mut z = ZipWriter::new_append(OpenOptions::new().read(true).write(true).open(&archive)?)?;
+			let options = zip_options(d.level,&None);
+			for (src,name) in walk_inputs(&ip,&d.keep_path,&None,Some(&filter))?.iter() {
+				zip_add(&mut z,src,name,options)?;
+			}
+			z.finish()?;
+		},
+		// tar アーカイブは既存のエントリを書き写した後に追加する
+		at => {
+			let r = decompress_reader(at,File::open(&d.output)?)?;
+			let added = walk_inputs(&ip,&d.keep_path,&None,Some(&filter))?;
+			tar_rewrite(at,r,&archive,d.level,|_| true,&added)?;
+		}
+	}
+
+	if fs::rename(&archive,&d.output).is_err() {
+		fs::copy(&archive,&d.output)?;
+	}
+	tmp.close()?;
+	return Ok(());
+
+}
+
+fn native_remove(d:&RemoveData,arc_type:&ArcType) -> io::Result<()> {
+
+	let tmp = tempdir()?;
+	let archive = tmp.path().join("archive");
+
+	match arc_type {
+		ArcType::Zip => {
+			let mut src = ZipArchive::new(File::open(&d.input)?)?;
+			let mut z = ZipWriter::new(File::create(&archive)?);
+			for i in 0..src.len() {
+				let mut f = src.by_index(i)?;
+				if matches_target(f.name(),&d.target) { continue; }
+				// そのまま書き写すとフォルダやシンボリックリンクの種別が失われるので作り直す
+				let (name,options) = (f.name().to_string(),f.options().unix_permissions(f.unix_mode().unwrap_or(0o755)));
+				if f.is_dir() {
+					z.add_directory(name,options)?;
+					continue;
+				}
+				if f.is_symlink() {
+					let mut target = String::new();
+					f.read_to_string(&mut target)?;
+					z.add_symlink(name,target,options)?;
+					continue;
+				}
+				drop(f);
+				z.raw_copy_file(src.by_index_raw(i)?)?;
+			}
+			z.finish()?;
+		},
+		at => {
+			let r = decompress_reader(at,File::open(&d.input)?)?;
+			tar_rewrite(at,r,&archive,d.level,|name| !matches_target(name,&d.target),&[])?;
+		}
+	}
+
+	if fs::rename(&archive,&d.input).is_err() {
+		fs::copy(&archive,&d.input)?;
+	}
+	tmp.close()?;
+	return Ok(());
+
+}
+
+/// アーカイブ内のパスが対象 (フォルダの場合は中身も含む) に該当するか
+fn matches_target(name:&str,target:&[String]) -> bool {
+	let n = name.trim_end_matches('/');
+	return target.iter().any(|t| {
+		let t = t.trim_end_matches('/');
+		n==t || n.starts_with(&format!("{}/",t))
+	});
+}
+
+/// 入力ファイルを (実際のパス,アーカイブ内のパス) に展開する (フォルダは中身も再帰的に含める)
+/// 作成では入力ファイルを既に絞り込んであるので filter は None にする
+fn walk_inputs(ip:&Vec<&Path>,keep_path:&bool,base:&Option<PathBuf>,filter:Option<&Filter>) -> io::Result<Vec<(PathBuf,String)>> {
+	let mut l = vec![];
+	let pi = get_pi(ip,keep_path,base).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput,e.to_string()) )?;
+	for (cwd,name) in pi {
+		let name = name.to_string_lossy().trim_start_matches('/').to_string();
+		walk(&cwd.join(&name),&name,filter,&mut l)?;
+	}
+	return Ok(l);
+}
+
+fn walk(src:&Path,name:&str,filter:Option<&Filter>,l:&mut Vec<(PathBuf,String)>) -> io::Result<()> {
+	let m = fs::symlink_metadata(src)?;
+	if filter.is_some_and(|f| f.excluded(name,m.is_dir()) ) { return Ok(()); }
+	l.push((src.to_path_buf(),name.to_string()));
+	if m.is_dir() {
+		let mut children = fs::read_dir(src)?.collect::<io::Result<Vec<_>>>()?;
+		children.sort_by_key(|e| e.file_name() );
+		for c in children {
+			let child_name = format!("{}/{}",name.trim_end_matches('/'),c.file_name().to_string_lossy());
+			walk(&c.path(),&child_name,filter,l)?;
+		}
+	}
+	return Ok(());
+}
+
+/// パスワードが指定されている場合は AES-256 で暗号化する
+fn zip_options(rate:u8,password:&Option<String>) -> FileOptions<'_,()> {
+	let options = match rate {
+		0 => SimpleFileOptions::default().compression_method(CompressionMethod::Stored),
+		r => SimpleFileOptions::default().compression_method(CompressionMethod::Deflated).compression_level(Some(r as i64))
+	};
+	return match password {
+		Some(p) => options.with_aes_encryption(AesMode::Aes256,p),
+		None => options
+	};
+}
+
+/// パスワードが指定されている場合は復号して読み込む
+fn zip_by_index<'a,R:Read+Seek>(z:&'a mut ZipArchive<R>,i:usize,password:&Option<String>) -> ZipResult<ZipFile<'a,R>> {
+	return match password {
+		Some(p) => z.by_index_decrypt(i,p.as_bytes()),
+		None => z.by_index(i)
+	};
+}
+
+fn zip_create(entries:&[(PathBuf,String)],out:File,rate:u8,password:&Option<String>,verbose:bool) -> io::Result<()> {
+	let mut z = ZipWriter::new(BufWriter::new(out));
+	let options = zip_options(rate,password);
+	for (src,name) in entries.iter() {
+		if verbose { eprintln!("{}",name); }
+		progress::file(name,None);
+		zip_add(&mut z,src,name,options)?;
+	}
+	z.finish()?;
+	return Ok(());
+}
+
+fn zip_add<W:Write+Seek>(z:&mut ZipWriter<W>,src:&Path,name:&String,options:FileOptions<()>) -> io::Result<()> {
+	let m = fs::symlink_metadata(src)?;
+	let mut options = options.unix_permissions(m.permissions().mode() & 0o7777);
+	if let Ok(t) = m.modified() {
+		let (y,mo,d,h,mi,s) = utc_datetime(system_time_secs(t));
+		if let Ok(dt) = DateTime::from_date_and_time(y as u16,mo as u8,d as u8,h as u8,mi as u8,s as u8) {
+			options = options.last_modified_time(dt);
+		}
+	}
+	if m.is_symlink() {
+		z.add_symlink(name,fs::read_link(src)?.to_string_lossy(),options)?;
+	}
+	else if m.is_dir() {
+		z.add_directory(format!("{}/",name.trim_end_matches('/')),options)?;
+	}
+	else {
+		z.start_file(name,options)?;
+		io::copy(&mut Counted(File::open(src)?),z)?;
+	}
+	return Ok(());
+}
+
+fn tar_create<W:Write>(entries:&[(PathBuf,String)],out:W,verbose:bool) -> io::Result<W> {
+	let mut b = tar::Builder::new(out);
+	b.follow_symlinks(false);
+	for (src,name) in entries.iter() {
+		if verbose { eprintln!("{}",name); }
+		// tar のライブラリがファイルを読み込むので、追加したファイルの大きさを読み込み量とする
+		let m = fs::symlink_metadata(src)?;
+		progress::file(name,m.is_file().then_some(m.len()));
+		b.append_path_with_name(src,name)?;
+	}
+	return b.into_inner();
+}
+
+/// 既存の tar アーカイブのエントリのうち keep を満たすものを書き写し、 added を追加して圧縮する
+fn tar_rewrite<R:Read,F:Fn(&str) -> bool>(
+	at:&ArcType,r:R,dst:&Path,rate:u8,keep:F,added:&[(PathBuf,String)]
+) -> io::Result<()> {
+	let mut b = tar::Builder::new(tempfile::tempfile()?);
+	b.follow_symlinks(false);
+	let mut a = tar::Archive::new(r);
+	for e in a.entries()? {
+		let mut e = e?;
+		let path = e.path()?.to_path_buf();
+		if !keep(&String::from_utf8_lossy(&e.path_bytes())) { continue; }
+		let mut h = e.header().clone();
+		match (h.entry_type().is_symlink() || h.entry_type().is_hard_link(),e.link_name()?) {
+			(true,Some(l)) => {
+				let l = l.to_path_buf();
+				b.append_link(&mut h,&path,&l)?;
+			},
+			_ => { b.append_data(&mut h,&path,&mut e)?; }
+		}
+	}
+	for (src,name) in added.iter() {
+		b.append_path_with_name(src,name)?;
+	}
+	let mut t = b.into_inner()?;
+	t.seek(SeekFrom::Start(0))?;
+	match at {
+		ArcType::Tar => { io::copy(&mut t,&mut File::create(dst)?)?; },
+		at => { compress(at,t,File::create(dst)?,rate)?; }
+	}
+	return Ok(());
+}
+
+fn compress<R:Read>(at:&ArcType,mut r:R,w:File,rate:u8) -> io::Result<()> {
+	match at {
+		ArcType::Gzip => {
+			let mut e = GzEncoder::new(BufWriter::new(w),flate2::Compression::new(rate as u32));
+			io::copy(&mut r,&mut e)?;
+			e.finish()?.flush()?;
+		},
+		ArcType::Bzip2 => {
+			let mut e = BzEncoder::new(BufWriter::new(w),bzip2::Compression::new(rate.max(1) as u32));
+			io::copy(&mut r,&mut e)?;
+			e.finish()?.flush()?;
+		},
+		ArcType::Xz => {
+			// lzma_rs は圧縮率を指定できない
+			eprintln!("{}",msg!("native-rate-ignored","xz"));
+			let mut o = BufWriter::new(w);
+			lzma_rs::xz_compress(&mut BufReader::new(r),&mut o)?;
+			o.flush()?;
+		},
+		ArcType::Zstd => {
+			// ruzstd は最速の圧縮率 (zstd -1 相当) のみ実装されている
+			if rate>1 { eprintln!("{}",msg!("native-rate-ignored","zstd")); }
+			let mut o = BufWriter::new(w);
+			ruzstd::encoding::compress(r,&mut o,CompressionLevel::Fastest);
+			o.flush()?;
+		},
+		_ => { return Err(io::Error::new(io::ErrorKind::Unsupported,text("native-unsupported"))); }
+	}
+	return Ok(());
+}
+
+/// 並列に圧縮する際のブロックの大きさ
+const PARALLEL_BLOCK:usize = 4<<20;
+
+/// 入力をブロックに分けて並列に圧縮し、それぞれを gzip / bzip2 のメンバーとして順に書き出す
+/// メンバーを連結したものは通常の解凍コマンドでそのまま解凍できる
+struct ParallelEncoder<W:Write> {
+	at: ArcType,
+	w: W,
+	rate: u8,
+	threads: usize,
+	buf: Vec<u8>,
+	blocks: Vec<Vec<u8>>,
+	/// 1つ以上のメンバーを書き出したか
+	written: bool
+}
+impl<W:Write> ParallelEncoder<W> {
+	fn new(at:ArcType,w:W,rate:u8,threads:usize) -> Self {
+		return ParallelEncoder { at, w, rate, threads, buf:vec![], blocks:vec![], written:false };
+	}
+
+	/// 溜めたブロックをスレッドごとに圧縮して順に書き出す
+	fn flush_blocks(&mut self) -> io::Result<()> {
+		let (at,rate) = (self.at,self.rate);
+		let l = std::thread::scope(|s| {
+			let h = self.blocks.iter().map(|b| s.spawn(move || compress_block(&at,b,rate) )).collect::<Vec<_>>();
+			h.into_iter().map(|h| h.join().unwrap_or_else(|_| Err(io::Error::other(text("thread-panicked"))) )).collect::<Vec<_>>()
+		});
+		for c in l { self.w.write_all(&c?)?; }
+		self.written |= !self.blocks.is_empty();
+		self.blocks.clear();
+		return Ok(());
+	}
+
+	/// 残りを圧縮して書き出し、書き出し先を返す
+	fn finish(mut self) -> io::Result<W> {
+		// 空の入力も1つのメンバーにする
+		if !self.buf.is_empty() || !self.written {
+			let b = std::mem::take(&mut self.buf);
+			self.blocks.push(b);
+		}
+		self.flush_blocks()?;
+		return Ok(self.w);
+	}
+}
+impl<W:Write> Write for ParallelEncoder<W> {
+	fn write(&mut self,data:&[u8]) -> io::Result<usize> {
+		self.buf.extend_from_slice(data);
+		while self.buf.len()>=PARALLEL_BLOCK {
+			let rest = self.buf.split_off(PARALLEL_BLOCK);
+			let b = std::mem::replace(&mut self.buf,rest);
+			self.blocks.push(b);
+			if self.blocks.len()>=self.threads { self.flush_blocks()?; }
+		}
+		return Ok(data.len());
+	}
+	fn flush(&mut self) -> io::Result<()> {
+		return self.w.flush();
+	}
+}
+
+fn compress_block(at:&ArcType,b:&[u8],rate:u8) -> io::Result<Vec<u8>> {
+	match at {
+		ArcType::Gzip => {
+			let mut e = GzEncoder::new(vec![],flate2::Compression::new(rate as u32));
+			e.write_all(b)?;
+			return e.finish();
+		},
+		ArcType::Bzip2 => {
+			let mut e = BzEncoder::new(vec![],bzip2::Compression::new(rate.max(1) as u32));
+			e.write_all(b)?;
+			return e.finish();
+		},
+		_ => { return Err(io::Error::new(io::ErrorKind::Unsupported,text("native-unsupported"))); }
+	}
+}
+
+/// 解凍しながら読み込む (tar アーカイブはそのまま読み込む)
+fn decompress_reader<R:Read+'static>(at:&ArcType,f:R) -> io::Result<Box<dyn Read>> {
+	let r:Box<dyn Read> = match at {
+		ArcType::Gzip  => Box::new(MultiGzDecoder::new(BufReader::new(f))),
+		ArcType::Bzip2 => Box::new(MultiBzDecoder::new(BufReader::new(f))),
+		ArcType::Xz    => {
+			// lzma-rs はストリームとして読み込めないので一時ファイルに解凍する
+			let mut t = tempfile::tempfile()?;
+			lzma_rs::xz_decompress(&mut BufReader::new(f),&mut t).map_err(|e| io::Error::other(format!("{:?}",e)) )?;
+			t.seek(SeekFrom::Start(0))?;
+			Box::new(BufReader::new(t))
+		},
+		ArcType::Zstd  => {
+			Box::new(StreamingDecoder::new(BufReader::new(f)).map_err(|e| io::Error::other(format!("{:?}",e)) )?)
+		},
+		_ => Box::new(BufReader::new(f))
+	};
+	return Ok(r);
+}
+
+/// 圧縮ファイルの中身が tar アーカイブであるか
+fn contains_tar(at:&ArcType,file:&String) -> io::Result<bool> {
+	let mut h = vec![];
+	decompress_reader(at,File::open(file)?)?.take(512).read_to_end(&mut h)?;
+	return Ok(h.len()>=262 && &h[257..262]==b"ustar");
+}
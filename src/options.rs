@@ -0,0 +1,367 @@
+use crate::args::*;
+use crate::error::*;
+use crate::entry::*;
+use crate::create::create;
+use crate::extract::extract;
+use crate::list::{list,read_entries};
+use crate::test::test;
+use crate::append::append;
+use crate::remove::remove;
+use crate::rename::rename;
+use crate::convert::convert;
+use crate::setup::setup;
+
+/// コマンドラインの引数として使えるようにする (引数の構造体はそのまま使い、公開はしない)
+macro_rules! command_args {
+	($o:ident,$d:ident) => {
+		impl clap::FromArgMatches for $o {
+			fn from_arg_matches(m:&clap::ArgMatches) -> std::result::Result<Self,clap::Error> {
+				return $d::from_arg_matches(m).map(|d| $o { d } );
+			}
+			fn update_from_arg_matches(&mut self,m:&clap::ArgMatches) -> std::result::Result<(),clap::Error> {
+				return self.d.update_from_arg_matches(m);
+			}
+		}
+		impl clap::Args for $o {
+			fn augment_args(c:clap::Command<'_>) -> clap::Command<'_> { return $d::augment_args(c); }
+			fn augment_args_for_update(c:clap::Command<'_>) -> clap::Command<'_> { return $d::augment_args_for_update(c); }
+		}
+	};
+}
+
+/// アーカイブの作成の指定
+/// 進行状況は表示せず、保存先が既に存在する場合は作成しない (コマンドラインとは既定値が異なる)
+pub struct CreateOptions {
+	pub(crate) d: CreateData
+}
+impl CreateOptions {
+	/// output に作成する (- は標準出力)
+	pub fn new<S:Into<String>>(output:S) -> Self {
+		return CreateOptions { d: CreateData {
+			input: vec![],
+			output: output.into(),
+			arc_type: None,
+			backend: None,
+			rate: 6,
+			verbose: false,
+			progress: ProgressMode::None,
+			overwrite: Overwrite::Never,
+			image_name: String::from("Untitled"),
+			keep_path: false,
+			threads: None,
+			preserve: vec![],
+			split_size: None,
+			reproducible: false,
+			filter: FilterData::default(),
+			pw: PasswordData::default(),
+			base: None
+		} };
+	}
+	/// アーカイブに追加するファイル
+	pub fn input<S:Into<String>>(mut self,input:S) -> Self { self.d.input.push(input.into()); return self; }
+	/// アーカイブに追加するファイル (まとめて指定する)
+	pub fn inputs<I:IntoIterator<Item=S>,S:Into<String>>(mut self,inputs:I) -> Self { self.d.input.extend(inputs.into_iter().map(|i| i.into() )); return self; }
+	/// アーカイブの種類 (指定しない場合は出力ファイルの拡張子から判定する)
+	pub fn arc_type(mut self,arc_type:ArcType) -> Self { self.d.arc_type = Some(arc_type); return self; }
+	pub fn backend(mut self,backend:BackendType) -> Self { self.d.backend = Some(backend); return self; }
+	pub fn rate(mut self,rate:u8) -> Self { self.d.rate = rate; return self; }
+	pub fn verbose(mut self,verbose:bool) -> Self { self.d.verbose = verbose; return self; }
+	pub fn progress(mut self,progress:ProgressMode) -> Self { self.d.progress = progress; return self; }
+	pub fn overwrite(mut self,overwrite:Overwrite) -> Self { self.d.overwrite = overwrite; return self; }
+	pub fn image_name<S:Into<String>>(mut self,name:S) -> Self { self.d.image_name = name.into(); return self; }
+	pub fn keep_path(mut self,keep_path:bool) -> Self { self.d.keep_path = keep_path; return self; }
+	pub fn threads(mut self,threads:usize) -> Self { self.d.threads = Some(threads); return self; }
+	pub fn preserve(mut self,preserve:Preserve) -> Self { self.d.preserve.push(preserve); return self; }
+	/// 分割する大きさ ("2G" や "500M" など)
+	pub fn split_size<S:Into<String>>(mut self,size:S) -> Self { self.d.split_size = Some(size.into()); return self; }
+	pub fn reproducible(mut self,reproducible:bool) -> Self { self.d.reproducible = reproducible; return self; }
+	/// 対象にするファイルのパターン (gitignore と同じ書式)
+	pub fn include<S:Into<String>>(mut self,pattern:S) -> Self { self.d.filter.include.push(pattern.into()); return self; }
+	/// 除外するファイルのパターン (gitignore と同じ書式)
+	pub fn exclude<S:Into<String>>(mut self,pattern:S) -> Self { self.d.filter.exclude.push(pattern.into()); return self; }
+	pub fn exclude_vcs(mut self,exclude_vcs:bool) -> Self { self.d.filter.exclude_vcs = exclude_vcs; return self; }
+	pub fn password<S:Into<String>>(mut self,password:S) -> Self { self.d.pw.password = Some(password.into()); return self; }
+
+	pub fn run(self) -> Result<()> {
+		return finish(create(self.d));
+	}
+}
+command_args!(CreateOptions,CreateData);
+
+/// アーカイブの展開の指定
+/// 進行状況は表示せず、展開先に既に存在するファイルは置き換えない (コマンドラインとは既定値が異なる)
+pub struct ExtractOptions {
+	pub(crate) d: ExtractData
+}
+impl ExtractOptions {
+	/// input を展開する (- は標準入力)
+	pub fn new<S:Into<String>>(input:S) -> Self {
+		return ExtractOptions { d: ExtractData {
+			input: input.into(),
+			output: None,
+			target: vec![],
+			arc_type: None,
+			backend: None,
+			no_untar: false,
+			smart: false,
+			preserve: vec![],
+			verbose: false,
+			progress: ProgressMode::None,
+			overwrite: Overwrite::Never,
+			filter: FilterData::default(),
+			safety: SafetyData::default(),
+			pw: PasswordData::default()
+		} };
+	}
+	/// 展開先のフォルダ、或いは解凍した圧縮ファイルの保存先 (指定しない場合は現在のディレクトリ)
+	pub fn output<S:Into<String>>(mut self,output:S) -> Self { self.d.output = Some(output.into()); return self; }
+	/// 展開するアーカイブ内のパス (指定しない場合は全て)
+	pub fn target<S:Into<String>>(mut self,target:S) -> Self { self.d.target.push(target.into()); return self; }
+	/// アーカイブの種類 (指定しない場合はファイルの内容と拡張子から判定する)
+	pub fn arc_type(mut self,arc_type:ArcType) -> Self { self.d.arc_type = Some(arc_type); return self; }
+	pub fn backend(mut self,backend:BackendType) -> Self { self.d.backend = Some(backend); return self; }
+	pub fn no_untar(mut self,no_untar:bool) -> Self { self.d.no_untar = no_untar; return self; }
+	pub fn smart(mut self,smart:bool) -> Self { self.d.smart = smart; return self; }
+	pub fn preserve(mut self,preserve:Preserve) -> Self { self.d.preserve.push(preserve); return self; }
+	pub fn verbose(mut self,verbose:bool) -> Self { self.d.verbose = verbose; return self; }
+	pub fn progress(mut self,progress:ProgressMode) -> Self { self.d.progress = progress; return self; }
+	pub fn overwrite(mut self,overwrite:Overwrite) -> Self { self.d.overwrite = overwrite; return self; }
+	/// 対象にするファイルのパターン (gitignore と同じ書式)
+	pub fn include<S:Into<String>>(mut self,pattern:S) -> Self { self.d.filter.include.push(pattern.into()); return self; }
+	/// 除外するファイルのパターン (gitignore と同じ書式)
+	pub fn exclude<S:Into<String>>(mut self,pattern:S) -> Self { self.d.filter.exclude.push(pattern.into()); return self; }
+	pub fn exclude_vcs(mut self,exclude_vcs:bool) -> Self { self.d.filter.exclude_vcs = exclude_vcs; return self; }
+	/// 展開前に安全でないファイルを確認しない
+	pub fn allow_unsafe(mut self,allow_unsafe:bool) -> Self { self.d.safety.allow_unsafe = allow_unsafe; return self; }
+	/// 展開後の大きさの上限 (バイト)
+	pub fn max_size(mut self,size:u64) -> Self { self.d.safety.max_size = Some(size.to_string()); return self; }
+	pub fn max_files(mut self,files:u64) -> Self { self.d.safety.max_files = Some(files); return self; }
+	/// 展開後の大きさとアーカイブの大きさの比の上限
+	pub fn max_ratio(mut self,ratio:f64) -> Self { self.d.safety.max_ratio = Some(ratio); return self; }
+	pub fn password<S:Into<String>>(mut self,password:S) -> Self { self.d.pw.password = Some(password.into()); return self; }
+
+	pub fn run(self) -> Result<()> {
+		return finish(extract(self.d));
+	}
+}
+command_args!(ExtractOptions,ExtractData);
+
+/// アーカイブの内容の一覧の指定
+pub struct ListOptions {
+	pub(crate) d: ListData
+}
+impl ListOptions {
+	/// input の内容を読み込む (- は標準入力)
+	pub fn new<S:Into<String>>(input:S) -> Self {
+		return ListOptions { d: ListData {
+			input: input.into(),
+			arc_type: None,
+			backend: None,
+			format: ListFormat::Table,
+			checksum: false,
+			pw: PasswordData::default()
+		} };
+	}
+	/// アーカイブの種類 (指定しない場合はファイルの内容と拡張子から判定する)
+	pub fn arc_type(mut self,arc_type:ArcType) -> Self { self.d.arc_type = Some(arc_type); return self; }
+	pub fn backend(mut self,backend:BackendType) -> Self { self.d.backend = Some(backend); return self; }
+	/// run で出力する形式
+	pub fn format(mut self,format:ListFormat) -> Self { self.d.format = format; return self; }
+	/// チェックサムを記録しない形式でも全てのデータを読み込んで計算する
+	pub fn checksum(mut self,checksum:bool) -> Self { self.d.checksum = checksum; return self; }
+	pub fn password<S:Into<String>>(mut self,password:S) -> Self { self.d.pw.password = Some(password.into()); return self; }
+
+	/// 一覧を標準出力に出力する
+	pub fn run(self) -> Result<()> {
+		return finish(list(self.d));
+	}
+	/// 一覧を出力せずに返す
+	pub fn entries(self) -> Result<Vec<Entry>> {
+		return finish(read_entries(self.d));
+	}
+}
+command_args!(ListOptions,ListData);
+
+/// アーカイブの検査の指定
+pub struct TestOptions {
+	pub(crate) d: TestData
+}
+impl TestOptions {
+	/// input を検査する (- は標準入力)
+	pub fn new<S:Into<String>>(input:S) -> Self {
+		return TestOptions { d: TestData {
+			input: input.into(),
+			arc_type: None,
+			backend: None,
+			verbose: false,
+			pw: PasswordData::default()
+		} };
+	}
+	/// アーカイブの種類 (指定しない場合はファイルの内容と拡張子から判定する)
+	pub fn arc_type(mut self,arc_type:ArcType) -> Self { self.d.arc_type = Some(arc_type); return self; }
+	pub fn backend(mut self,backend:BackendType) -> Self { self.d.backend = Some(backend); return self; }
+	pub fn verbose(mut self,verbose:bool) -> Self { self.d.verbose = verbose; return self; }
+	pub fn password<S:Into<String>>(mut self,password:S) -> Self { self.d.pw.password = Some(password.into()); return self; }
+
+	pub fn run(self) -> Result<()> {
+		return finish(test(self.d));
+	}
+}
+command_args!(TestOptions,TestData);
+
+/// 既存のアーカイブへの追加の指定
+pub struct AppendOptions {
+	pub(crate) d: AppendData
+}
+impl AppendOptions {
+	/// 既存のアーカイブ output に追加する
+	pub fn new<S:Into<String>>(output:S) -> Self {
+		return AppendOptions { d: AppendData {
+			input: vec![],
+			output: output.into(),
+			arc_type: None,
+			backend: None,
+			rate: None,
+			level: 0,
+			verbose: false,
+			keep_path: false
+		} };
+	}
+	/// 追加するファイル
+	pub fn input<S:Into<String>>(mut self,input:S) -> Self { self.d.input.push(input.into()); return self; }
+	/// 追加するファイル (まとめて指定する)
+	pub fn inputs<I:IntoIterator<Item=S>,S:Into<String>>(mut self,inputs:I) -> Self { self.d.input.extend(inputs.into_iter().map(|i| i.into() )); return self; }
+	/// アーカイブの種類 (指定しない場合はファイルの内容と拡張子から判定する)
+	pub fn arc_type(mut self,arc_type:ArcType) -> Self { self.d.arc_type = Some(arc_type); return self; }
+	pub fn backend(mut self,backend:BackendType) -> Self { self.d.backend = Some(backend); return self; }
+	pub fn rate(mut self,rate:u8) -> Self { self.d.rate = Some(rate); return self; }
+	pub fn verbose(mut self,verbose:bool) -> Self { self.d.verbose = verbose; return self; }
+	pub fn keep_path(mut self,keep_path:bool) -> Self { self.d.keep_path = keep_path; return self; }
+
+	pub fn run(self) -> Result<()> {
+		return finish(append(self.d));
+	}
+}
+command_args!(AppendOptions,AppendData);
+
+/// アーカイブ内のファイルの削除の指定
+pub struct RemoveOptions {
+	pub(crate) d: RemoveData
+}
+impl RemoveOptions {
+	/// 既存のアーカイブ input から削除する
+	pub fn new<S:Into<String>>(input:S) -> Self {
+		return RemoveOptions { d: RemoveData {
+			input: input.into(),
+			target: vec![],
+			arc_type: None,
+			backend: None,
+			rate: None,
+			level: 0,
+			verbose: false
+		} };
+	}
+	/// 削除するアーカイブ内のパス
+	pub fn target<S:Into<String>>(mut self,target:S) -> Self { self.d.target.push(target.into()); return self; }
+	/// アーカイブの種類 (指定しない場合はファイルの内容と拡張子から判定する)
+	pub fn arc_type(mut self,arc_type:ArcType) -> Self { self.d.arc_type = Some(arc_type); return self; }
+	pub fn backend(mut self,backend:BackendType) -> Self { self.d.backend = Some(backend); return self; }
+	pub fn rate(mut self,rate:u8) -> Self { self.d.rate = Some(rate); return self; }
+	pub fn verbose(mut self,verbose:bool) -> Self { self.d.verbose = verbose; return self; }
+
+	pub fn run(self) -> Result<()> {
+		return finish(remove(self.d));
+	}
+}
+command_args!(RemoveOptions,RemoveData);
+
+/// アーカイブ内のファイルの名前の変更の指定
+pub struct RenameOptions {
+	pub(crate) d: RenameData
+}
+impl RenameOptions {
+	/// 既存のアーカイブ input 内の old を new に変更する
+	pub fn new<S:Into<String>,O:Into<String>,N:Into<String>>(input:S,old:O,new:N) -> Self {
+		return RenameOptions { d: RenameData {
+			input: input.into(),
+			old: Some(old.into()),
+			new: Some(new.into()),
+			map: None,
+			arc_type: None,
+			rate: None,
+			level: 0,
+			verbose: false
+		} };
+	}
+	/// 既存のアーカイブ input 内の名前を対応表のファイル map の通りに変更する
+	pub fn with_map<S:Into<String>,M:Into<String>>(input:S,map:M) -> Self {
+		let mut o = RenameOptions::new(input,"","");
+		o.d.old = None;
+		o.d.new = None;
+		o.d.map = Some(map.into());
+		return o;
+	}
+	/// アーカイブの種類 (指定しない場合はファイルの内容と拡張子から判定する)
+	pub fn arc_type(mut self,arc_type:ArcType) -> Self { self.d.arc_type = Some(arc_type); return self; }
+	pub fn rate(mut self,rate:u8) -> Self { self.d.rate = Some(rate); return self; }
+	pub fn verbose(mut self,verbose:bool) -> Self { self.d.verbose = verbose; return self; }
+
+	pub fn run(self) -> Result<()> {
+		return finish(rename(self.d));
+	}
+}
+command_args!(RenameOptions,RenameData);
+
+/// アーカイブの種類の変換の指定
+pub struct ConvertOptions {
+	pub(crate) d: ConvertData
+}
+impl ConvertOptions {
+	/// input を output に変換する
+	pub fn new<S:Into<String>,T:Into<String>>(input:S,output:T) -> Self {
+		return ConvertOptions { d: ConvertData {
+			input: input.into(),
+			output: output.into(),
+			from: None,
+			arc_type: None,
+			backend: None,
+			rate: 6,
+			overwrite: Overwrite::Never,
+			verbose: false
+		} };
+	}
+	/// 変換元の種類 (指定しない場合はファイルの内容と拡張子から判定する)
+	pub fn from(mut self,from:ArcType) -> Self { self.d.from = Some(from); return self; }
+	/// 変換先の種類 (指定しない場合は出力ファイルの拡張子から判定する)
+	pub fn arc_type(mut self,arc_type:ArcType) -> Self { self.d.arc_type = Some(arc_type); return self; }
+	pub fn backend(mut self,backend:BackendType) -> Self { self.d.backend = Some(backend); return self; }
+	pub fn rate(mut self,rate:u8) -> Self { self.d.rate = rate; return self; }
+	pub fn overwrite(mut self,overwrite:Overwrite) -> Self { self.d.overwrite = overwrite; return self; }
+	pub fn verbose(mut self,verbose:bool) -> Self { self.d.verbose = verbose; return self; }
+
+	pub fn run(self) -> Result<()> {
+		return finish(convert(self.d));
+	}
+}
+command_args!(ConvertOptions,ConvertData);
+
+/// 外部コマンドの確認とインストールの指定
+pub struct SetupOptions {
+	pub(crate) d: SetupData
+}
+impl SetupOptions {
+	/// 不足している外部コマンドを確認する
+	pub fn new() -> Self {
+		return SetupOptions { d: SetupData { yes: false } };
+	}
+	/// 不足しているパッケージを実際にインストールする
+	pub fn yes(mut self,yes:bool) -> Self { self.d.yes = yes; return self; }
+
+	pub fn run(self) -> Result<()> {
+		return finish(setup(self.d));
+	}
+}
+impl Default for SetupOptions {
+	fn default() -> Self { return SetupOptions::new(); }
+}
+command_args!(SetupOptions,SetupData);
@@ -0,0 +1,225 @@
+use std::fs::{self,OpenOptions};
+use std::io::{Write,BufRead,BufReader,IsTerminal};
+use std::time::SystemTime;
+use crate::library::*;
+use crate::args::*;
+use crate::error::*;
+
+/// 既存のファイルに対する扱い
+pub enum Action {
+	/// 既存のものを削除して置く
+	Replace,
+	/// 置かない
+	Skip,
+	/// 別の名前で置く
+	Rename(PathBuf)
+}
+
+/// 展開したファイルを置く場合の --overwrite の扱い (ask で全てに同じ答えを選ぶと以降はそれに従う)
+pub struct Conflict {
+	policy: Overwrite,
+	/// 置かなかったファイルの数
+	skipped: usize
+}
+
+impl Conflict {
+	pub fn new(policy:Overwrite) -> Self {
+		return Conflict { policy:interactive(policy), skipped:0 };
+	}
+
+	/// src_time に更新された src を dst に置く場合の扱い (dst が存在しなければ Replace)
+	pub fn resolve(&mut self,src_time:Option<SystemTime>,dst:&Path,is_dir:bool) -> Action {
+		let m = match fs::symlink_metadata(dst) {
+			Ok(m) => m,
+			Err(_) => { return Action::Replace; }
+		};
+		let a = match self.policy {
+			Overwrite::Always => Action::Replace,
+			Overwrite::Never => Action::Skip,
+			// 更新日時が分からない場合は置き換えない
+			Overwrite::Newer => match (src_time,m.modified().ok()) {
+				(Some(s),Some(d)) if s>d => Action::Replace,
+				_ => Action::Skip
+			},
+			Overwrite::Rename => Action::Rename(rename_path(dst,is_dir)),
+			Overwrite::Ask => {
+				match ask(&msg!("ask-extract",dst.to_string_lossy()),"ynANr") {
+					'y' => Action::Replace,
+					'A' => {
+						self.policy = Overwrite::Always;
+						Action::Replace
+					},
+					'N' => {
+						self.policy = Overwrite::Never;
+						Action::Skip
+					},
+					'r' => Action::Rename(rename_path(dst,is_dir)),
+					_ => Action::Skip
+				}
+			}
+		};
+		if let Action::Skip = a { self.skipped += 1; }
+		return a;
+	}
+
+	/// 置かなかったファイルがあれば表示する
+	pub fn report(&self) {
+		if self.skipped>0 {
+			eprintln!("{}",msg!("skipped-existing",self.skipped));
+		}
+	}
+}
+
+/// 標準入力が端末でなければ確認できないので ask は never として扱う
+fn interactive(policy:Overwrite) -> Overwrite {
+	return match policy {
+		Overwrite::Ask if !std::io::stdin().is_terminal() => Overwrite::Never,
+		p => p
+	};
+}
+
+/// 作成するアーカイブの保存先 output が既に存在する場合の扱いを決め、保存先を返す (作成しない場合は None)
+/// 置き換える場合も既存のアーカイブはここでは削除せず、 staged_output に作成してから commit_output で置き換える
+pub fn prepare_output(policy:Overwrite,output:&String,inputs:&[String]) -> Result<Option<String>> {
+	let p = Path::new(output);
+	let m = match fs::symlink_metadata(p) {
+		Ok(m) => m,
+		Err(_) => { return Ok(Some(output.clone())); }
+	};
+	if m.is_dir() { return Err(ArchiverError::Usage(msg!("output-is-dir"))); }
+	let action = match interactive(policy) {
+		Overwrite::Always => Action::Replace,
+		Overwrite::Never => {
+			return Err(ArchiverError::Usage(msg!("archive-exists",output)));
+		},
+		Overwrite::Newer => {
+			match (inputs.iter().filter_map(|i| newest(Path::new(i)) ).max(),m.modified().ok()) {
+				(Some(s),Some(d)) if s<=d => {
+					eprintln!("{}",msg!("archive-up-to-date",output));
+					Action::Skip
+				},
+				_ => Action::Replace
+			}
+		},
+		Overwrite::Rename => Action::Rename(rename_path(p,false)),
+		Overwrite::Ask => {
+			match ask(&msg!("ask-create",output),"ynr") {
+				'y' => Action::Replace,
+				'r' => Action::Rename(rename_path(p,false)),
+				_ => {
+					return Err(ArchiverError::Failed(msg!("create-cancelled")));
+				}
+			}
+		}
+	};
+	return match action {
+		Action::Replace => Ok(Some(output.clone())),
+		Action::Skip => Ok(None),
+		Action::Rename(r) => {
+			eprintln!("{}",msg!("save-as",r.to_string_lossy()));
+			Ok(Some(r.to_string_lossy().to_string()))
+		}
+	};
+}
+
+/// 保存先 output と同じフォルダに作業ディレクトリを作り、その中の同じ名前のパスを返す
+/// 外部コマンドが既存のアーカイブに追加せず、失敗した場合も既存のアーカイブが残るようにする
+pub fn staged_output(output:&str) -> Result<(TempDir,String)> {
+	let p = Path::new(output).absolute_path();
+	let dir = p.parent().unwrap_or(Path::new("."));
+	let tmp = tempfile::Builder::new().prefix(".archiver-").tempdir_in(dir).map_err(|e| ArchiverError::Failed(msg!("tmp-dir-failed",e)) )?;
+	let staged = tmp.path().join(p.file_name().unwrap_or_default()).to_string_lossy().to_string();
+	return Ok((tmp,staged));
+}
+
+/// staged_output に作成したアーカイブを保存先 output に移動する (既存のアーカイブはここで置き換わる)
+pub fn commit_output(tmp:TempDir,staged:&str,output:&str) -> Result<()> {
+	if let Err(e) = fs::rename(staged,output) {
+		return Err(ArchiverError::Failed(msg!("replace-archive-failed",e)));
+	}
+	return close_dir(tmp);
+}
+
+/// 端末で choices のいずれかの1文字を答えさせる (読み込めない場合は n)
+fn ask(message:&str,choices:&str) -> char {
+	let mut tty = match OpenOptions::new().read(true).write(true).open("/dev/tty") {
+		Ok(t) => t,
+		Err(_) => { return 'n'; }
+	};
+	loop {
+		let _ = write!(tty,"{}",message);
+		let _ = tty.flush();
+		let mut line = String::new();
+		match BufReader::new(&tty).read_line(&mut line) {
+			Ok(n) if n>0 => {},
+			_ => { return 'n'; }
+		}
+		let mut c = line.trim().chars();
+		if let (Some(c),None) = (c.next(),c.next()) {
+			if choices.contains(c) { return c; }
+		}
+	}
+}
+
+/// 既存のものと重ならない名前 (a.txt は "a (1).txt" 、 a.tar.gz は "a (1).tar.gz" のようにする)
+pub fn rename_path(p:&Path,is_dir:bool) -> PathBuf {
+	let name = p.file_name().unwrap_or_default().to_string_lossy().to_string();
+	// 先頭の . は拡張子の区切りとして扱わない (隠しファイル)
+	let split = match is_dir {
+		true => None,
+		false => name.char_indices().skip(1).filter(|(_,c)| *c=='.' ).map(|(i,_)| i ).last()
+	};
+	let (stem,ext) = match split {
+		Some(i) => {
+			let (s,e) = name.split_at(i);
+			match s.strip_suffix(".tar").filter(|s| !s.is_empty() ) {
+				Some(s) => (s.to_string(),format!(".tar{}",e)),
+				None => (s.to_string(),e.to_string())
+			}
+		},
+		None => (name.clone(),String::new())
+	};
+	let mut i = 1;
+	loop {
+		let c = p.with_file_name(format!("{} ({}){}",stem,i,ext));
+		if fs::symlink_metadata(&c).is_err() { return c; }
+		i += 1;
+	}
+}
+
+/// フォルダの中で最も新しい更新日時 (ファイルはその更新日時)
+fn newest(p:&Path) -> Option<SystemTime> {
+	let m = fs::symlink_metadata(p).ok()?;
+	let t = m.modified().ok();
+	if !m.is_dir() { return t; }
+	let children = fs::read_dir(p).ok()?.filter_map(|e| e.ok() ).filter_map(|e| newest(&e.path()) ).max();
+	return t.max(children);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn renamed(dir:&TempDir,name:&str,is_dir:bool) -> String {
+		let p = rename_path(&dir.path().join(name),is_dir);
+		return p.file_name().unwrap().to_string_lossy().to_string();
+	}
+
+	#[test]
+	fn rename_keeps_extension() {
+		let dir = tempdir().unwrap();
+		assert_eq!(renamed(&dir,"a.txt",false),"a (1).txt");
+		assert_eq!(renamed(&dir,"a.tar.gz",false),"a (1).tar.gz");
+		assert_eq!(renamed(&dir,".profile",false),".profile (1)");
+		assert_eq!(renamed(&dir,"README",false),"README (1)");
+		assert_eq!(renamed(&dir,"v1.2",true),"v1.2 (1)");
+	}
+
+	#[test]
+	fn rename_skips_existing() {
+		let dir = tempdir().unwrap();
+		fs::write(dir.path().join("a (1).txt"),"").unwrap();
+		fs::create_dir(dir.path().join("a (2).txt")).unwrap();
+		assert_eq!(renamed(&dir,"a.txt",false),"a (3).txt");
+	}
+}
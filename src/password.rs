@@ -0,0 +1,153 @@
+use std::fs::{self,File,OpenOptions};
+use std::io::{Read,Write,BufRead,BufReader,IsTerminal};
+use std::os::unix::fs::OpenOptionsExt;
+use std::process::{Command,Stdio};
+use which::which;
+use crate::library::*;
+use crate::args::*;
+use crate::error::*;
+use crate::message::text;
+
+/// --password, --password-file, --password-env の順に確認してパスワードを読み込み、 password に設定する
+pub fn load_password(p:&mut PasswordData) -> Result<()> {
+	if p.password.is_some() { return Ok(()); }
+	if let Some(f) = &p.password_file {
+		match fs::read_to_string(f) {
+			// 最初の行をパスワードとする
+			Ok(s) => { p.password = Some(s.lines().next().unwrap_or("").to_string()); },
+			Err(_) => { return Err(ArchiverError::Usage(msg!("password-file-unreadable",f))); }
+		}
+		return Ok(());
+	}
+	if let Some(e) = &p.password_env {
+		match std::env::var(e) {
+			Ok(s) => { p.password = Some(s); },
+			Err(_) => { return Err(ArchiverError::Usage(msg!("password-env-unset",e))); }
+		}
+	}
+	return Ok(());
+}
+
+/// zip の暗号化と暗号化された zip の読み込みは 7z で行うので、 7z がなければネイティブ実装を使う
+pub fn password_backend(p:&PasswordData,at:&ArcType,backend:&Option<BackendType>) -> Option<BackendType> {
+	if backend.is_none() && p.password.is_some() && *at==ArcType::Zip && which("7z").is_err() {
+		return Some(BackendType::Native);
+	}
+	return *backend;
+}
+
+/// 暗号化に対応している種類か
+pub fn supports_encryption(at:&ArcType) -> bool {
+	return matches!(at,ArcType::Zip|ArcType::SevenZ|ArcType::Rar|ArcType::Aar);
+}
+
+/// パスワードが指定されている場合に、暗号化に対応していない種類であればエラーにする
+pub fn check_encryption(p:&PasswordData,at:&ArcType) -> Result<()> {
+	if p.password.is_some() && !supports_encryption(at) {
+		return Err(ArchiverError::Unsupported(msg!("encryption-unsupported")));
+	}
+	return Ok(());
+}
+
+/// 暗号化されたアーカイブでパスワードが指定されていない場合は、端末であれば入力させる
+pub fn ask_password(p:&mut PasswordData,at:&ArcType,file:&String) -> Result<()> {
+	if p.password.is_some() || !is_encrypted(at,file) { return Ok(()); }
+	match prompt_password(text("password-prompt")) {
+		Some(pw) => { p.password = Some(pw); },
+		None => { return Err(ArchiverError::Usage(msg!("password-required"))); }
+	}
+	return Ok(());
+}
+
+/// アーカイブが暗号化されているか (ヘッダが暗号化されていて内容が読めない場合も含む)
+pub fn is_encrypted(at:&ArcType,file:&String) -> bool {
+	match at {
+		ArcType::Zip => {
+			let f = match File::open(file) {
+				Ok(f) => f,
+				Err(_) => { return false; }
+			};
+			let mut z = match zip::ZipArchive::new(f) {
+				Ok(z) => z,
+				Err(_) => { return false; }
+			};
+			return (0..z.len()).any(|i| z.by_index_raw(i).map(|f| f.encrypted() ).unwrap_or(false) );
+		},
+		ArcType::Aar => {
+			// 暗号化されたものは Apple Encrypted Archive になる
+			let mut h = [0u8;4];
+			return File::open(file).and_then(|mut f| f.read_exact(&mut h) ).is_ok() && &h==b"AEA1";
+		},
+		ArcType::SevenZ|ArcType::Rar => {
+			// 誤ったパスワードで一覧を取得し、失敗するか暗号化の印があれば暗号化されている
+			let (prog,args,mark) = match at {
+				ArcType::SevenZ => ("7z" ,vs(["l","-slt","-ba","-p-",file]),"Encrypted = +"),
+				_               => ("rar",vs(["lt","-p-",file]),"encrypted")
+			};
+			if which(prog).is_err() { return false; }
+			return match Command::new(prog).args(args).stdin(Stdio::null()).stderr(Stdio::null()).output() {
+				Ok(o) => !o.status.success() || String::from_utf8_lossy(&o.stdout).contains(mark),
+				Err(_) => false
+			};
+		},
+		_ => false
+	}
+}
+
+/// 端末からパスワードを入力させる (標準入力が端末でない場合は None)
+pub fn prompt_password(message:&str) -> Option<String> {
+	if !std::io::stdin().is_terminal() { return None; }
+	let mut tty = OpenOptions::new().read(true).write(true).open("/dev/tty").ok()?;
+	let _ = write!(tty,"{}",message);
+	let _ = tty.flush();
+	// 入力した文字を表示しないようにする
+	let stty = |arg:&str| {
+		if let Ok(t) = File::open("/dev/tty") {
+			let _ = Command::new("stty").arg(arg).stdin(t).status();
+		}
+	};
+	stty("-echo");
+	let mut line = String::new();
+	let r = BufReader::new(&tty).read_line(&mut line);
+	stty("echo");
+	let _ = writeln!(tty);
+	r.ok()?;
+	return Some(line.trim_end_matches(['\n','\r']).to_string());
+}
+
+/// コマンドにパスワードを渡す (create は作成時のヘッダの暗号化を含む)
+/// ps などで見えてしまうので、パスワードは引数には入れない
+/// 7z と rar は -p に値を付けないと入力を求めるので標準入力から渡し (作成時は確認のため2回)、
+/// パスワードをファイルから読み込めるコマンドには tmp に作成したファイルで渡す
+pub fn add_password_args(c:&mut Cmd,password:&String,tmp:&TempDir,create:bool) -> Result<()> {
+	let prog = c.prog.to_str().unwrap_or("").to_string();
+	let input = match create {
+		true  => format!("{}\n{}\n",password,password),
+		false => format!("{}\n",password)
+	};
+	match prog.as_str() {
+		"7z" => {
+			let mut a = vs(["-p"]);
+			if create && c.args.iter().any(|a| a=="-t7z" ) { a.push(OsString::from("-mhe=on")); }
+			c.args.splice(1..1,a);
+			c.stdin = Some(input);
+		},
+		"rar" => {
+			c.args.insert(1,OsString::from(if create { "-hp" } else { "-p" }));
+			c.stdin = Some(input);
+		},
+		// zip, unzip, bsdtar は引数か端末からしかパスワードを読み込めない (zip の暗号化と読み込みは 7z かネイティブ実装で行う)
+		"zip"|"unzip"|"bsdtar" => {
+			return Err(ArchiverError::Unsupported(msg!("password-no-safe-channel",prog)));
+		},
+		"aa" => {
+			let f = tmp.path().join("password");
+			let w = OpenOptions::new().write(true).create_new(true).mode(0o600).open(&f)
+				.and_then(|mut w| w.write_all(password.as_bytes()) );
+			if let Err(e) = w { return Err(ArchiverError::Failed(msg!("password-pass-failed",e))); }
+			c.args.extend([OsString::from("-password-file"),f.into_os_string()]);
+		},
+		_ => {}
+	}
+	return Ok(());
+}
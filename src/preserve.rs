@@ -0,0 +1,115 @@
+use crate::library::*;
+use crate::args::*;
+use crate::message::text;
+
+impl Preserve {
+	fn name(&self) -> &'static str {
+		return match self {
+			Preserve::Xattrs    => "xattrs",
+			Preserve::Acls      => "acls",
+			Preserve::Selinux   => "selinux",
+			Preserve::Owner     => "owner",
+			Preserve::Sparse    => "sparse",
+			Preserve::Hardlinks => "hardlinks"
+		};
+	}
+	fn description(&self) -> &'static str {
+		return match self {
+			Preserve::Xattrs    => text("value.preserve.xattrs"),
+			Preserve::Acls      => text("value.preserve.acls"),
+			Preserve::Selinux   => text("value.preserve.selinux"),
+			Preserve::Owner     => text("value.preserve.owner"),
+			Preserve::Sparse    => text("value.preserve.sparse"),
+			Preserve::Hardlinks => text("value.preserve.hardlinks")
+		};
+	}
+}
+
+/// 種類ごとに保存できる属性 (native はネイティブ実装の場合、 extract は展開の場合)
+/// 圧縮系のフォーマットは tar アーカイブを圧縮する場合は Tar として扱う
+fn supported(at:&ArcType,native:bool,extract:bool) -> &'static [Preserve] {
+	use Preserve::*;
+	if native {
+		return match at {
+			// tar クレートは拡張属性を展開時にのみ扱える
+			ArcType::Tar if extract => &[Xattrs,Owner],
+			ArcType::Tar => &[Owner],
+			_ => &[]
+		};
+	}
+	return match at {
+		ArcType::Tar    => &[Xattrs,Acls,Selinux,Owner,Sparse,Hardlinks],
+		ArcType::Cpio   => &[Owner,Hardlinks],
+		ArcType::Zip    => &[Owner],
+		ArcType::SevenZ => &[Owner,Hardlinks],
+		ArcType::Rar    => &[Owner,Hardlinks],
+		ArcType::Aar    => &[Xattrs,Acls,Owner],
+		ArcType::Wim    => &[Xattrs,Owner,Hardlinks],
+		ArcType::Dmg    => &[Xattrs,Acls,Owner,Hardlinks],
+		ArcType::Lha    => &[Owner],
+		_ => &[]
+	};
+}
+
+/// 指定された属性のうち、この種類のアーカイブで保存できないものを表にして警告する
+pub fn warn_unsupported(l:&[Preserve],at:&ArcType,native:bool,extract:bool) {
+	let s = supported(at,native,extract);
+	let missing = l.iter().filter(|p| !s.contains(p) ).collect::<Vec<_>>();
+	if missing.is_empty() { return; }
+	let name = at.to_possible_value().map(|v| v.get_name() ).unwrap_or("");
+	let backend = if native { text("preserve-native") } else { "" };
+	eprintln!("{}",msg!("preserve-warning",name,backend));
+	for p in missing {
+		eprintln!("  {:<10} {}",p.name(),p.description());
+	}
+}
+
+/// コマンドに属性を保存 (extract は復元) する引数を追加する
+pub fn add_preserve_args(c:&mut Cmd,l:&[Preserve],extract:bool) {
+	if l.is_empty() { return; }
+	let has = |p:Preserve| l.contains(&p);
+	let mut a:Vec<&str> = vec![];
+	match c.prog.to_str().unwrap_or("") {
+		"bsdtar" => {
+			c.args.splice(0..0,vs(tar_args(l,extract)));
+			return;
+		},
+		"7z" => {
+			if has(Preserve::Owner) { a.extend(["-snoi","-snon"]); }
+			if has(Preserve::Hardlinks) { a.extend(["-snh","-snl"]); }
+			c.args.splice(1..1,vs(a));
+			return;
+		},
+		"rar" => {
+			if has(Preserve::Owner) { a.push("-ow"); }
+			if has(Preserve::Hardlinks) && !extract { a.push("-oh"); }
+			c.args.splice(1..1,vs(a));
+			return;
+		},
+		// unzip は -X で所有者を復元する (zip は既定で保存する)
+		"unzip" => {
+			if has(Preserve::Owner) { c.args.insert(0,OsString::from("-X")); }
+			return;
+		},
+		"aa" if !extract => {
+			if has(Preserve::Xattrs) { a.extend(["-include-field","xat"]); }
+			if has(Preserve::Acls) { a.extend(["-include-field","acl"]); }
+		},
+		"wimcapture"|"wimapply" if has(Preserve::Owner) || has(Preserve::Xattrs) => {
+			a.push("--unix-data");
+		},
+		_ => {}
+	}
+	c.args.extend(vs(a));
+}
+
+/// bsdtar に渡す引数 (SELinux のラベルは拡張属性として扱われる)
+pub fn tar_args(l:&[Preserve],extract:bool) -> Vec<&'static str> {
+	let has = |p:Preserve| l.contains(&p);
+	let mut a = vec![];
+	if has(Preserve::Xattrs) || has(Preserve::Selinux) { a.push("--xattrs"); }
+	if has(Preserve::Acls) { a.push("--acls"); }
+	if has(Preserve::Sparse) { a.push(if extract { "-S" } else { "--read-sparse" }); }
+	if has(Preserve::Owner) && extract { a.push("--same-owner"); }
+	return a;
+}
@@ -0,0 +1,316 @@
+use std::fs;
+use std::io::{self,Read,Seek,SeekFrom,Write,IsTerminal};
+use std::sync::{Arc,Mutex};
+use std::sync::atomic::{AtomicBool,AtomicU64,Ordering};
+use std::thread::{self,JoinHandle};
+use std::time::{Duration,Instant};
+use crate::library::*;
+use crate::args::*;
+
+/// 表示中の進行状況 (コマンドの実行時にプロセスを登録するために共有する)
+static CURRENT:Mutex<Option<Arc<State>>> = Mutex::new(None);
+
+/// 読み込み量を調べるプロセス
+struct Proc {
+	pid: u32,
+	/// 最後に調べた読み込み量
+	last: u64
+}
+
+struct State {
+	json: bool,
+	/// 作成では bsdtar -v で通知されたファイルの大きさも読み込み量に加える
+	create: bool,
+	total: u64,
+	start: Instant,
+	/// /proc で読み込み量を調べる外部コマンド
+	procs: Mutex<Vec<Proc>>,
+	/// 終了したプロセスの読み込み量の合計
+	finished: Mutex<u64>,
+	/// 自身が読み込んだ量 (ネイティブ実装の読み込みと、外部コマンドの標準入力に流し込んだ量)
+	counted: AtomicU64,
+	/// 最後に通知された処理中のファイル
+	file: Mutex<Option<String>>,
+	done: AtomicBool,
+	/// 表示を更新するスレッド
+	handle: Mutex<Option<JoinHandle<()>>>
+}
+
+pub struct Progress {
+	state: Arc<State>
+}
+
+/// 進行状況の表示を開始する (表示しない場合は None)
+/// total は作成では入力ファイルの合計、展開ではアーカイブの大きさで、入力を読み込んだ量と比較する
+/// 読み込み量は自身が読み込んだ量 (Counted) と、 /proc が使える環境では外部コマンドが読み込んだ量を合わせたもの
+/// 処理中のファイルはネイティブ実装と bsdtar -v の出力から通知される (file)
+/// auto は標準エラー出力が端末の場合にバーを表示するが、 -v でファイルごとの行を出力する場合は表示しない
+pub fn start(mode:&ProgressMode,verbose:bool,operation:&str,total:u64) -> Option<Progress> {
+	let json = match mode {
+		ProgressMode::None => { return None; },
+		ProgressMode::Auto => {
+			if verbose || !std::io::stderr().is_terminal() { return None; }
+			false
+		},
+		ProgressMode::Bar => false,
+		ProgressMode::Json => true
+	};
+	let state = Arc::new(State {
+		json,
+		create: operation=="create",
+		total,
+		start: Instant::now(),
+		procs: Mutex::new(vec![]),
+		finished: Mutex::new(0),
+		counted: AtomicU64::new(0),
+		file: Mutex::new(None),
+		done: AtomicBool::new(false),
+		handle: Mutex::new(None)
+	});
+	if json {
+		emit(format!("{{\"event\":\"start\",\"operation\":{},\"total_bytes\":{}}}",json_str(operation),total));
+	}
+	*CURRENT.lock().unwrap() = Some(state.clone());
+	let s = state.clone();
+	*state.handle.lock().unwrap() = Some(thread::spawn(move || {
+		while !s.done.load(Ordering::Relaxed) {
+			let bytes = s.bytes();
+			if s.json { emit(s.progress_json(bytes,false)); }
+			else {
+				let file = s.file.lock().unwrap().clone();
+				s.draw(bytes,file.as_ref(),false);
+			}
+			// 終了を待たずに抜けられるように短く区切って待つ
+			for _ in 0..5 {
+				if s.done.load(Ordering::Relaxed) { break; }
+				thread::sleep(Duration::from_millis(40));
+			}
+		}
+	}));
+	return Some(Progress { state });
+}
+
+impl Progress {
+	/// 表示を終了して結果を出力する
+	pub fn finish(self,success:bool) {
+		*CURRENT.lock().unwrap() = None;
+		self.state.end(success);
+	}
+}
+
+/// 進行状況を表示しているか
+pub fn active() -> bool {
+	return CURRENT.lock().unwrap().is_some();
+}
+
+/// コマンドのプロセスを読み込み量を調べる対象に加える
+pub fn track(pid:u32) {
+	if let Some(s) = CURRENT.lock().unwrap().as_ref() {
+		s.procs.lock().unwrap().push(Proc { pid, last:0 });
+	}
+}
+
+/// 自身が読み込んだ量を加える
+pub fn add(n:u64) {
+	if let Some(s) = CURRENT.lock().unwrap().as_ref() { s.counted.fetch_add(n,Ordering::Relaxed); }
+}
+
+/// 処理中のファイルを通知する (size は作成で読み込むファイルの大きさ)
+/// JSON では小さいファイルも漏れないように、表示の更新を待たずにすぐ出力する
+pub fn file(path:&str,size:Option<u64>) {
+	let s = match CURRENT.lock().unwrap().as_ref() {
+		Some(s) => s.clone(),
+		None => { return; }
+	};
+	if let Some(n) = size { s.counted.fetch_add(n,Ordering::Relaxed); }
+	if s.json { emit(format!("{{\"event\":\"file\",\"path\":{}}}",json_str(path))); }
+	*s.file.lock().unwrap() = Some(path.to_string());
+}
+
+/// bsdtar -v の標準エラー出力の行であれば処理中のファイルとして通知する ("a パス" は作成、 "x パス" は展開)
+/// 作成では cwd からのパスのファイルの大きさを読み込み量に加える
+/// 失敗したファイルは同じ行に ": エラーの内容" が続くので、エラーの行として扱う
+pub fn file_event(line:&str,cwd:&Path) -> bool {
+	let path = match line.strip_prefix("a ").or_else(|| line.strip_prefix("x ") ) {
+		Some(p) if !p.is_empty() && !p.contains(": ") => p,
+		_ => { return false; }
+	};
+	let create = CURRENT.lock().unwrap().as_ref().is_some_and(|s| s.create );
+	let size = match create {
+		true => Some(fs::symlink_metadata(cwd.join(path)).ok().filter(|m| m.is_file() ).map(|m| m.len() ).unwrap_or(0)),
+		false => None
+	};
+	file(path,size);
+	return true;
+}
+
+/// 表示している場合は bsdtar に -v を加え、その出力から処理中のファイルを読み込むようにする
+pub fn file_events(c:&mut Cmd) {
+	if !active() || c.prog!="bsdtar" { return; }
+	let verbose = c.args.iter().any(|a| a=="-v" );
+	if !verbose { c.args.insert(0,OsString::from("-v")); }
+	c.events = Some(verbose);
+}
+
+/// 読み込んだ量を進行状況に加える (ネイティブ実装や外部コマンドに流し込む入力を読み込むのに使う)
+pub struct Counted<R>(pub R);
+impl<R:Read> Read for Counted<R> {
+	fn read(&mut self,b:&mut [u8]) -> io::Result<usize> {
+		let n = self.0.read(b)?;
+		add(n as u64);
+		return Ok(n);
+	}
+}
+impl<R:Seek> Seek for Counted<R> {
+	fn seek(&mut self,p:SeekFrom) -> io::Result<u64> {
+		return self.0.seek(p);
+	}
+}
+
+/// 終了したプロセスを対象から外し、最後に調べた読み込み量を合計に加える
+pub fn untrack(pid:u32) {
+	if let Some(s) = CURRENT.lock().unwrap().as_ref() {
+		let mut procs = s.procs.lock().unwrap();
+		if let Some(i) = procs.iter().position(|p| p.pid==pid ) {
+			*s.finished.lock().unwrap() += procs.remove(i).last;
+		}
+	}
+}
+
+/// エラーで終了する前に表示を終える
+pub fn abort() {
+	let s = CURRENT.lock().unwrap().take();
+	if let Some(s) = s { s.end(false); }
+}
+
+impl State {
+	/// 入力を読み込んだ量 (/proc が使えない環境では外部コマンドの分は数えられない)
+	fn bytes(&self) -> u64 {
+		let mut procs = self.procs.lock().unwrap();
+		for p in procs.iter_mut() {
+			if let Some(n) = read_bytes(p.pid) { p.last = n; }
+		}
+		return self.counted.load(Ordering::Relaxed) + *self.finished.lock().unwrap() + procs.iter().map(|p| p.last ).sum::<u64>();
+	}
+
+	fn draw(&self,bytes:u64,file:Option<&String>,done:bool) {
+		let elapsed = self.start.elapsed().as_secs_f64();
+		let rate = if elapsed>0.0 { bytes as f64/elapsed } else { 0.0 };
+		let mut line = String::new();
+		if self.total>0 {
+			let ratio = if done { 1.0 } else { (bytes as f64/self.total as f64).min(0.99) };
+			let width = 24;
+			let filled = (ratio*width as f64) as usize;
+			line.push_str(&format!(
+				"{:>3}% [{}{}] {} / {}",
+				(ratio*100.0) as u32,"#".repeat(filled),"-".repeat(width-filled),
+				human_size(if done { self.total } else { bytes.min(self.total) }),human_size(self.total)
+			));
+		}
+		else { line.push_str(&human_size(bytes)); }
+		line.push_str(&format!("  {}/s",human_size(rate as u64)));
+		if done { line.push_str(&format!("  {}",clock(elapsed as u64))); }
+		else if let Some(eta) = self.eta(bytes,rate) { line.push_str(&msg!("eta",clock(eta))); }
+		if let (Some(f),false) = (file,done) {
+			line.push_str("  ");
+			line.push_str(&Path::new(f).file_name().unwrap_or_default().to_string_lossy());
+		}
+		// 端末の幅を超えて折り返さないように切り詰める
+		let line = line.chars().take(100).collect::<String>();
+		let mut e = std::io::stderr();
+		let _ = write!(e,"\r\x1b[K{}",line);
+		if done { let _ = writeln!(e); }
+		let _ = e.flush();
+	}
+
+	fn eta(&self,bytes:u64,rate:f64) -> Option<u64> {
+		if self.total==0 || rate<=0.0 || bytes==0 { return None; }
+		return Some((self.total.saturating_sub(bytes) as f64/rate) as u64);
+	}
+
+	fn progress_json(&self,bytes:u64,done:bool) -> String {
+		let elapsed = self.start.elapsed().as_secs_f64();
+		let rate = if elapsed>0.0 { bytes as f64/elapsed } else { 0.0 };
+		let percent = match self.total {
+			0 => String::from("null"),
+			t => format!("{:.1}",if done { 100.0 } else { (bytes as f64*100.0/t as f64).min(99.0) })
+		};
+		let eta = match done {
+			true => Some(0),
+			false => self.eta(bytes,rate)
+		};
+		return format!(
+			"{{\"event\":\"progress\",\"bytes\":{},\"total_bytes\":{},\"percent\":{},\"bytes_per_sec\":{},\"eta_secs\":{}}}",
+			bytes,self.total,percent,rate as u64,eta.map(|e| e.to_string() ).unwrap_or(String::from("null"))
+		);
+	}
+
+	fn end(&self,success:bool) {
+		self.done.store(true,Ordering::Relaxed);
+		let h = self.handle.lock().unwrap().take();
+		if let Some(h) = h { let _ = h.join(); }
+		// 最後に調べてから終了までに読み込んだ分は数えられないので、成功した場合は全て処理したものとする
+		let bytes = match success {
+			true => self.bytes().max(self.total),
+			false => self.bytes()
+		};
+		if self.json {
+			if success { emit(self.progress_json(bytes,true)); }
+			emit(format!(
+				"{{\"event\":\"finish\",\"success\":{},\"bytes\":{},\"elapsed_secs\":{:.3}}}",
+				success,bytes,self.start.elapsed().as_secs_f64()
+			));
+		}
+		else if success { self.draw(bytes,None,true); }
+		// 失敗した場合は続くエラーメッセージが行の途中に出ないように表示を消す
+		else { eprint!("\r\x1b[K"); }
+	}
+}
+
+/// /proc/<pid>/io の rchar (プロセスが読み込んだバイト数)
+fn read_bytes(pid:u32) -> Option<u64> {
+	let s = fs::read_to_string(format!("/proc/{}/io",pid)).ok()?;
+	return s.lines().find_map(|l| l.strip_prefix("rchar:") )?.trim().parse().ok();
+}
+
+/// ファイルの大きさ (フォルダは中のファイルの合計)
+pub fn path_size(p:&Path) -> u64 {
+	let m = match fs::symlink_metadata(p) {
+		Ok(m) => m,
+		Err(_) => { return 0; }
+	};
+	if m.is_dir() {
+		return match fs::read_dir(p) {
+			Ok(rd) => rd.filter_map(|e| e.ok() ).map(|e| path_size(&e.path()) ).sum(),
+			Err(_) => 0
+		};
+	}
+	return if m.is_file() { m.len() } else { 0 };
+}
+
+fn emit(line:String) {
+	let mut e = std::io::stderr();
+	let _ = writeln!(e,"{}",line);
+	let _ = e.flush();
+}
+
+fn human_size(n:u64) -> String {
+	let units = ["B","KiB","MiB","GiB","TiB"];
+	let mut v = n as f64;
+	let mut i = 0;
+	while v>=1024.0 && i<units.len()-1 {
+		v /= 1024.0;
+		i += 1;
+	}
+	return match i {
+		0 => format!("{} {}",n,units[0]),
+		_ => format!("{:.1} {}",v,units[i])
+	};
+}
+
+fn clock(secs:u64) -> String {
+	return match secs {
+		s if s>=3600 => format!("{}:{:02}:{:02}",s/3600,s/60%60,s%60),
+		s => format!("{}:{:02}",s/60,s%60)
+	};
+}
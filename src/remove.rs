@@ -0,0 +1,132 @@
+use std::fs;
+use crate::library::*;
+use crate::args::*;
+use crate::backend::*;
+use crate::error::*;
+use crate::create::tar_compress_cmd;
+use crate::extract::decompress_in_tmp;
+use crate::message::text;
+
+pub fn remove(mut d:RemoveData) -> Result<()> {
+
+	// アーカイブの種類を判定
+	let arc_type = match d.arc_type {
+		Some(t) => t,
+		None => guess_existing_type(&d.input)?
+	};
+
+	// アーカイブが存在することを確認
+	if !Path::new(&d.input).is_file() {
+		return Err(ArchiverError::NotFound(msg!("archive-not-found",d.input)));
+	}
+
+	// パラメータの条件を確認
+	d.level = rewrite_rate(&d.input,d.rate,&arc_type)?;
+
+	// 実装を選択して実行
+	let backend = select_backend(&arc_type,&d.backend,Operation::Remove)?;
+	return backend.remove(&d,&arc_type).map_err(|e| e.context(text("remove-failed")) );
+
+}
+
+/// 外部コマンドによる削除
+pub fn remove_external(d:&RemoveData,arc_type:&ArcType) -> Result<()> {
+
+	let tmp = work_dir()?;
+
+	// 元のアーカイブを壊さないよう、作業ディレクトリに複製したものから削除する
+	let mut archive = match arc_type {
+		ArcType::Zip    => tmp.join_str("archive.zip"),
+		ArcType::SevenZ => tmp.join_str("archive.7z"),
+		ArcType::Rar    => tmp.join_str("archive.rar"),
+		ArcType::Lha    => tmp.join_str("archive.lzh"),
+		ArcType::Tar    => tmp.join_str("source.tar"),
+		ArcType::Cpio   => tmp.join_str("source.cpio"),
+		at if is_compress(at) => tmp.join_str(format!("file.{}",compress_ext(at)?)),
+		ArcType::Dmg|ArcType::Iso => {
+			return Err(ArchiverError::Unsupported(msg!("remove-disk-image")));
+		},
+		_ => {
+			return Err(ArchiverError::Unsupported(msg!("unsupported-remove")));
+		}
+	};
+	if let Err(e) = fs::copy(&d.input,&archive) {
+		return Err(ArchiverError::Failed(msg!("copy-failed",e)));
+	}
+
+	// コマンドを用意
+	let cl:Vec<Cmd> = match arc_type {
+		ArcType::Zip => {
+			let mut c = Cmd::new("zip",vs(["-d"]));
+			if !d.verbose { c.args.push(OsString::from("-q")); }
+			c.args.push(OsString::from(&archive));
+			c.args.extend(d.target.iter().flat_map(|t| vs([t,&format!("{}/*",t.trim_end_matches('/'))]) ));
+			vec![c]
+		},
+		ArcType::SevenZ => {
+			let mut c = Cmd::new("7z",vs(["d","-ba"]));
+			c.args.push(OsString::from(&archive));
+			c.args.extend(vs(d.target.iter()));
+			c.inherit_output = d.verbose;
+			vec![c]
+		},
+		ArcType::Rar => {
+			let mut c = Cmd::new("rar",vs(["d"]));
+			if !d.verbose { c.args.push(OsString::from("-inul")); }
+			c.args.push(OsString::from(&archive));
+			c.args.extend(vs(d.target.iter()));
+			vec![c]
+		},
+		ArcType::Lha => {
+			let mut c = Cmd::new("lha",vs(["-d"]));
+			if !d.verbose { c.args.push(OsString::from("-q")); }
+			c.args.push(OsString::from(&archive));
+			c.args.extend(vs(d.target.iter()));
+			vec![c]
+		},
+		ArcType::Tar|ArcType::Cpio => {
+			// 対象を除外して書き直す
+			let format = match &arc_type {
+				ArcType::Cpio => Some(cpio_format(&archive)),
+				_ => None
+			};
+			let src = archive;
+			archive = tmp.join_str(match format { Some(_) => "archive.cpio", None => "archive.tar" });
+			vec![tar_rewrite_cmd(&src,&archive,format,exclude_args(&d.target))]
+		},
+		at => {
+			// 解凍して tar アーカイブにし、対象を除外して書き直してから再圧縮する
+			let src = decompress_in_tmp(at,&tmp)?;
+			let tar = tmp.join_str("archive.tar");
+			let mut l = vec![tar_rewrite_cmd(&src,&tar,None,exclude_args(&d.target))];
+			let mut c = tar_compress_cmd(at,&d.level,"archive.tar")?;
+			c.cwd = tmp.path().to_path_buf();
+			l.push(c);
+			archive = tmp.join_str(format!("archive.tar.{}",compress_ext(at)?));
+			l
+		}
+	};
+
+	// コマンドを実行
+	several_cmd(cl)?;
+	if !Path::new(&archive).is_file() {
+		return Err(ArchiverError::Failed(msg!("remove-nothing")));
+	}
+	if fs::rename(&archive,&d.input).is_err() {
+		if let Err(e) = fs::copy(&archive,&d.input) {
+			return Err(ArchiverError::Failed(msg!("save-failed",e)));
+		}
+	}
+
+	close_dir(tmp)?;
+	return Ok(());
+
+}
+
+/// 削除対象を bsdtar の置換オプションに変換 (空文字列に置換されたエントリは書き出されない。フォルダの場合は中身も対象になる)
+fn exclude_args(target:&[String]) -> VS {
+	return target.iter().flat_map(|t| {
+		let p = bre_escape(t.trim_end_matches('/'),',');
+		vs(["-s",&format!(",^{}$,,S",p),"-s",&format!(",^{}/.*,,S",p)])
+	}).collect();
+}
@@ -0,0 +1,182 @@
+use std::fs;
+use crate::library::*;
+use crate::args::*;
+use crate::error::*;
+use crate::create::tar_compress_cmd;
+use crate::extract::decompress_in_tmp;
+use crate::message::text;
+
+pub fn rename(mut d:RenameData) -> Result<()> {
+
+	// アーカイブの種類を判定
+	let arc_type = match d.arc_type {
+		Some(t) => t,
+		None => guess_existing_type(&d.input)?
+	};
+
+	// アーカイブが存在することを確認
+	if !Path::new(&d.input).is_file() {
+		return Err(ArchiverError::NotFound(msg!("archive-not-found",d.input)));
+	}
+
+	// 変更前と変更後のパスの組を用意
+	let mut pairs:Vec<(String,String)> = vec![];
+	if let (Some(o),Some(n)) = (&d.old,&d.new) {
+		pairs.push((o.clone(),n.clone()));
+	}
+	if let Some(m) = &d.map {
+		pairs.extend(read_map(m)?);
+	}
+	// フォルダの末尾のスラッシュは取り除いておく
+	let pairs = pairs.into_iter().map(|(o,n)| {
+		(o.trim_end_matches('/').to_string(),n.trim_end_matches('/').to_string())
+	}).collect::<Vec<_>>();
+	if pairs.iter().any(|(o,n)| o.is_empty() || n.is_empty() ) {
+		return Err(ArchiverError::Usage(msg!("rename-empty")));
+	}
+
+	// パラメータの条件を確認
+	d.level = rewrite_rate(&d.input,d.rate,&arc_type)?;
+
+	let tmp = work_dir()?;
+
+	// 元のアーカイブを壊さないよう、作業ディレクトリに複製したものの名前を変更する
+	let mut archive = match &arc_type {
+		ArcType::Zip    => tmp.join_str("archive.zip"),
+		ArcType::SevenZ => tmp.join_str("archive.7z"),
+		ArcType::Rar    => tmp.join_str("archive.rar"),
+		ArcType::Tar    => tmp.join_str("source.tar"),
+		ArcType::Cpio   => tmp.join_str("source.cpio"),
+		at if is_compress(at) => tmp.join_str(format!("file.{}",compress_ext(at)?)),
+		_ => {
+			return Err(ArchiverError::Unsupported(msg!("unsupported-rename")));
+		}
+	};
+	if let Err(e) = fs::copy(&d.input,&archive) {
+		return Err(ArchiverError::Failed(msg!("copy-failed",e)));
+	}
+
+	// コマンドを用意
+	let cl:Vec<Cmd> = match &arc_type {
+		ArcType::Zip => {
+			// zipnote で書き出したエントリ一覧に新しい名前を書き込んで反映する
+			let notes = tmp.join_str("notes.txt");
+			let mut c = Cmd::new("zipnote",vec![archive.clone()]);
+			c.stdout = Some(notes.to_string_lossy().to_string());
+			several_cmd(vec![c]).map_err(|e| e.corrupt(text("rename-failed")) )?;
+			let notes = match fs::read_to_string(&notes) {
+				Ok(s) => s,
+				Err(e) => {
+					return Err(ArchiverError::Failed(format!("{}: {}",text("rename-failed"),e)));
+				}
+			};
+			let mut c = Cmd::new_cwd_stdin("zipnote",vs(["-w"]),tmp.path().to_path_buf(),zipnote_rename(&notes,&pairs));
+			c.args.push(OsString::from(&archive));
+			vec![c]
+		},
+		ArcType::SevenZ => {
+			let mut c = Cmd::new("7z",vs(["rn","-ba"]));
+			c.args.push(OsString::from(&archive));
+			for (o,n) in pairs.iter() { c.args.extend(vs([o,n])); }
+			c.inherit_output = d.verbose;
+			vec![c]
+		},
+		ArcType::Rar => {
+			let mut c = Cmd::new("rar",vs(["rn"]));
+			if !d.verbose { c.args.push(OsString::from("-inul")); }
+			c.args.push(OsString::from(&archive));
+			for (o,n) in pairs.iter() { c.args.extend(vs([o,n])); }
+			vec![c]
+		},
+		ArcType::Tar|ArcType::Cpio => {
+			// パスを置換しながら書き直す (他のエントリの属性はそのまま引き継がれる)
+			let format = match &arc_type {
+				ArcType::Cpio => Some(cpio_format(&archive)),
+				_ => None
+			};
+			let src = archive;
+			archive = tmp.join_str(match format { Some(_) => "archive.cpio", None => "archive.tar" });
+			vec![tar_rewrite_cmd(&src,&archive,format,rename_args(&pairs))]
+		},
+		at => {
+			// 解凍して tar アーカイブにし、パスを置換しながら書き直してから再圧縮する
+			let src = decompress_in_tmp(at,&tmp)?;
+			let tar = tmp.join_str("archive.tar");
+			let mut l = vec![tar_rewrite_cmd(&src,&tar,None,rename_args(&pairs))];
+			let mut c = tar_compress_cmd(at,&d.level,"archive.tar")?;
+			c.cwd = tmp.path().to_path_buf();
+			l.push(c);
+			archive = tmp.join_str(format!("archive.tar.{}",compress_ext(at)?));
+			l
+		}
+	};
+
+	// コマンドを実行
+	several_cmd(cl).map_err(|e| e.context(text("rename-failed")) )?;
+	if !Path::new(&archive).is_file() {
+		return Err(ArchiverError::Failed(msg!("rename-nothing")));
+	}
+	if fs::rename(&archive,&d.input).is_err() {
+		if let Err(e) = fs::copy(&archive,&d.input) {
+			return Err(ArchiverError::Failed(msg!("save-failed",e)));
+		}
+	}
+
+	close_dir(tmp)?;
+	return Ok(());
+
+}
+
+/// タブ区切りの対応表を読み込む (空行と # で始まる行は無視する)
+fn read_map(file:&String) -> Result<Vec<(String,String)>> {
+	let s = match fs::read_to_string(file) {
+		Ok(s) => s,
+		Err(_) => {
+			return Err(ArchiverError::Usage(msg!("map-unreadable",file)));
+		}
+	};
+	return s.lines().enumerate().filter(|(_,l)| !l.trim().is_empty() && !l.starts_with('#') ).map(|(n,l)| {
+		match l.split_once('\t') {
+			Some((o,n)) => Ok((o.to_string(),n.to_string())),
+			None => Err(ArchiverError::Usage(msg!("map-not-tab",n+1)))
+		}
+	}).collect();
+}
+
+/// パスにいずれかの変更を適用する (最初に一致したものを使用し、フォルダの場合は中身も移動する)
+fn apply_rename(path:&str,pairs:&[(String,String)]) -> Option<String> {
+	for (o,n) in pairs.iter() {
+		if path==o || path.trim_end_matches('/')==o {
+			return Some(path.replacen(o.as_str(),n,1));
+		}
+		if let Some(rest) = path.strip_prefix(&format!("{}/",o)) {
+			return Some(format!("{}/{}",n,rest));
+		}
+	}
+	return None;
+}
+
+/// zipnote の出力に新しい名前の行 (@=名前) を書き加える
+fn zipnote_rename(notes:&str,pairs:&[(String,String)]) -> String {
+	let mut r = String::new();
+	for l in notes.lines() {
+		r.push_str(l);
+		r.push('\n');
+		if let Some(name) = l.strip_prefix("@ ") {
+			if name.starts_with('(') { continue; }
+			if let Some(n) = apply_rename(name,pairs) {
+				r.push_str(&format!("@={}\n",n));
+			}
+		}
+	}
+	return r;
+}
+
+/// 変更を bsdtar の置換オプションに変換 (シンボリックリンクの参照先は変更しない)
+fn rename_args(pairs:&[(String,String)]) -> VS {
+	return pairs.iter().flat_map(|(o,n)| {
+		let o = bre_escape(o,',');
+		let n = n.replace('\\',"\\\\").replace('&',"\\&").replace(',',"\\,");
+		vs(["-s",&format!(",^{}$,{},S",o,n),"-s",&format!(",^{}/,{}/,S",o,n)])
+	}).collect();
+}
@@ -0,0 +1,187 @@
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Component;
+use std::time::UNIX_EPOCH;
+use crate::library::*;
+use crate::args::*;
+use crate::error::*;
+use crate::create::{compress_stdout_cmd,create_type,CreateType};
+
+/// SOURCE_DATE_EPOCH が設定されていない場合の時刻 (zip で扱える最も古い 1980-01-01)
+const DEFAULT_EPOCH:i64 = 315532800;
+
+/// --reproducible で作成できる種類であることを確認する
+/// 暗号化は毎回異なるソルトを使い、 rar や wim などは作成時の情報を含むので同じものを作成できない
+pub fn check_reproducible(d:&CreateData,at:&ArcType) -> Result<()> {
+	if d.backend==Some(BackendType::Native) {
+		return Err(ArchiverError::Unsupported(msg!("reproducible-native")));
+	}
+	if d.pw.password.is_some() {
+		return Err(ArchiverError::Unsupported(msg!("reproducible-encrypt")));
+	}
+	if !(matches!(at,ArcType::Tar|ArcType::Cpio|ArcType::Zip|ArcType::SevenZ) || compressor_args(at).is_some()) {
+		let name = at.to_possible_value().map(|v| v.get_name() ).unwrap_or("");
+		return Err(ArchiverError::Unsupported(msg!("reproducible-type",name)));
+	}
+	return Ok(());
+}
+
+/// 更新日時の上限 (SOURCE_DATE_EPOCH の UNIX 時間)
+fn source_date_epoch() -> Result<i64> {
+	return match std::env::var("SOURCE_DATE_EPOCH") {
+		Ok(s) => match s.trim().parse() {
+			Ok(t) => Ok(t),
+			Err(_) => Err(ArchiverError::Usage(msg!("source-date-epoch-invalid",s)))
+		},
+		Err(_) => Ok(DEFAULT_EPOCH)
+	};
+}
+
+/// 圧縮するコマンドに追加する引数 (ファイル名や日時を含めず、並列に圧縮しないようにする)
+/// 中身以外の情報を含まない形式は空で、同じものを作成できない形式は None
+fn compressor_args(at:&ArcType) -> Option<Vec<&'static str>> {
+	return match at {
+		ArcType::Gzip => Some(vec!["-n"]),
+		ArcType::Xz|ArcType::Zstd => Some(vec!["-T1"]),
+		ArcType::Lzop => Some(vec!["--no-name","--no-mode","--no-time"]),
+		ArcType::Bzip2|ArcType::Lzip|ArcType::Lzma|ArcType::Lz4|ArcType::Brotli|ArcType::Compress => Some(vec![]),
+		_ => None
+	};
+}
+
+/// 再現可能なアーカイブを作成する
+/// 全ての項目を mtree 形式で指定して bsdtar で作成し、圧縮する場合は圧縮するコマンドに繋ぐ
+pub fn create_reproducible(d:&CreateData,at:&ArcType) -> Result<()> {
+	let tmp = work_dir()?;
+	let ip = d.input.iter().map(Path::new).collect::<Vec<_>>();
+
+	// 単一のファイルはそのまま圧縮する
+	if let (Some(a),CreateType::SingleFile) = (compressor_args(at),create_type(&ip)) {
+		let mut c = compress_stdout_cmd(at,&d.rate,1).ok_or_else(|| unsupported(at) )?;
+		c.args.extend(vs(a));
+		c.args.push(OsString::from(&d.input[0]));
+		c.stdout = Some(d.output.clone());
+		return several_cmd(vec![c]);
+	}
+
+	let spec = tmp.join_str("spec.mtree");
+	if let Err(e) = fs::write(&spec,mtree_spec(d,source_date_epoch()?)?) {
+		return Err(ArchiverError::Failed(msg!("file-list-failed",e)));
+	}
+	let format = match at {
+		ArcType::Zip => vs(["--format","zip","--options",&format!("zip:compression-level={}",d.rate)]),
+		ArcType::SevenZ => vs(["--format","7zip"]),
+		_ => vs(["--format","pax"])
+	};
+	// 一覧のパスと同じ名前のファイルがあると bsdtar がその属性を使うので、空の作業ディレクトリで実行する
+	let mut tar = Cmd::new_cwd("bsdtar",vs(["-c"]),tmp.path().to_path_buf());
+	tar.args.extend(format);
+	if d.verbose { tar.args.push(OsString::from("-v")); }
+	tar.inherit_output = d.verbose;
+	let mut l = vec![tar];
+	// cpio は読み込んだファイルの inode 番号を含むので、 tar アーカイブから変換して番号をつけ直させる
+	if *at==ArcType::Cpio { l.push(Cmd::new("bsdtar",vs(["-c","--format","newc","-f","-","@-"]))); }
+	if let Some(a) = compressor_args(at) {
+		let mut c = compress_stdout_cmd(at,&d.rate,1).ok_or_else(|| unsupported(at) )?;
+		c.args.extend(vs(a));
+		l.push(c);
+	}
+	if l.len()==1 {
+		l[0].args.push(OsString::from("-f"));
+		l[0].args.push(Path::new(&d.output).absolute_path().into_os_string());
+		l[0].args.push(with_at(&spec));
+		return several_cmd(l);
+	}
+	l[0].args.extend(vs(["-f","-"]));
+	l[0].args.push(with_at(&spec));
+	if let Some(c) = l.last_mut() { c.stdout = Some(d.output.clone()); }
+	return piped_cmd(l);
+}
+
+fn unsupported(at:&ArcType) -> ArchiverError {
+	let name = at.to_possible_value().map(|v| v.get_name() ).unwrap_or("");
+	return ArchiverError::Unsupported(msg!("reproducible-type",name));
+}
+
+/// bsdtar で一覧のファイルを読み込む引数 (@ファイル名)
+fn with_at(p:&OsStr) -> OsString {
+	let mut s = OsString::from("@");
+	s.push(p);
+	return s;
+}
+
+/// 入力ファイルの全ての項目をアーカイブ内のパスの順に並べた mtree 形式の一覧
+/// 所有者は 0 、更新日時は epoch までに切り詰め、パーミッションは実行できるかどうかだけを残す
+fn mtree_spec(d:&CreateData,epoch:i64) -> Result<String> {
+	let mut l = vec![];
+	for i in d.input.iter() {
+		let p = Path::new(i);
+		// --keep-path の場合は先頭の / や .. を取り除いたパス、そうでなければファイル名をアーカイブ内のパスにする
+		let name = match d.keep_path {
+			true => d.base.as_ref().and_then(|b| p.strip_prefix(b).ok() ).unwrap_or(p).components().filter(|c| matches!(c,Component::Normal(_)) ).map(|c| c.as_os_str().to_os_string() ).collect::<Vec<_>>().join(OsStr::new("/")),
+			false => p.absolute_path().file_name().unwrap_or_default().to_os_string()
+		};
+		collect(&p.absolute_path(),name,&mut l)?;
+	}
+	l.sort_by(|a,b| a.0.as_bytes().cmp(b.0.as_bytes()) );
+	l.dedup_by(|a,b| a.0==b.0 );
+
+	let mut s = String::from("#mtree\n");
+	for (name,path,m) in l {
+		let time = m.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok() ).map(|t| t.as_secs() as i64 ).unwrap_or(epoch).min(epoch);
+		let exec = m.permissions().mode() & 0o100 != 0;
+		let kind = match m.file_type() {
+			t if t.is_dir() => String::from("type=dir mode=0755"),
+			t if t.is_symlink() => {
+				let link = fs::read_link(&path).unwrap_or_default();
+				format!("type=link mode=0777 link={}",vis(link.as_os_str()))
+			},
+			t if t.is_file() => format!("type=file mode={} contents={}",if exec { "0755" } else { "0644" },vis(path.as_os_str())),
+			_ => {
+				return Err(ArchiverError::Unsupported(msg!("reproducible-special",path.to_string_lossy())));
+			}
+		};
+		s.push_str(&format!("{} {} uid=0 gid=0 time={}\n",vis(&name),kind,time.max(0)));
+	}
+	return Ok(s);
+}
+
+/// path 以下の全ての項目を (アーカイブ内のパス,パス,属性) として l に加える (シンボリックリンクは辿らない)
+fn collect(path:&Path,name:OsString,l:&mut Vec<(OsString,PathBuf,fs::Metadata)>) -> Result<()> {
+	let m = match fs::symlink_metadata(path) {
+		Ok(m) => m,
+		Err(e) => {
+			return Err(ArchiverError::Failed(msg!("read-file-failed",path.to_string_lossy(),e)));
+		}
+	};
+	let is_dir = m.is_dir();
+	if !name.is_empty() { l.push((name.clone(),path.to_path_buf(),m)); }
+	if !is_dir { return Ok(()); }
+	let rd = match fs::read_dir(path) {
+		Ok(rd) => rd,
+		Err(e) => {
+			return Err(ArchiverError::Failed(msg!("read-dir-failed",path.to_string_lossy(),e)));
+		}
+	};
+	for e in rd.filter_map(|e| e.ok() ) {
+		let mut child = name.clone();
+		if !child.is_empty() { child.push("/"); }
+		child.push(e.file_name());
+		collect(&e.path(),child,l)?;
+	}
+	return Ok(());
+}
+
+/// mtree 形式で使えない文字を \ と3桁の8進数で表す
+fn vis(s:&OsStr) -> String {
+	let mut r = String::new();
+	for b in s.as_bytes() {
+		match b {
+			b'\\'|b'#'|b'=' => r.push_str(&format!("\\{:03o}",b)),
+			b if b.is_ascii_graphic() => r.push(*b as char),
+			b => r.push_str(&format!("\\{:03o}",b))
+		}
+	}
+	return r;
+}
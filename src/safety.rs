@@ -0,0 +1,395 @@
+use std::fs;
+use std::io::{self,Read,Seek,SeekFrom};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::sync::{Arc,Mutex};
+use std::sync::atomic::{AtomicBool,Ordering};
+use std::thread::{self,JoinHandle};
+use std::time::Duration;
+use which::which;
+use crate::library::*;
+use crate::args::*;
+use crate::error::*;
+use crate::entry::*;
+use crate::list::{list_entries,split_fields,parse_mode_string};
+use crate::backend::Operation;
+use crate::native::{native_supports,native_entries};
+use crate::message::text;
+
+impl SafetyData {
+	/// 大きさやファイルの数の上限が指定されているか
+	pub fn has_limits(&self) -> bool {
+		return self.max_size.is_some() || self.max_files.is_some() || self.max_ratio.is_some();
+	}
+}
+
+/// 展開の上限 (ratio はアーカイブの大きさ archive_size に対する比)
+pub struct Limits {
+	size: Option<u64>,
+	files: Option<u64>,
+	ratio: Option<f64>,
+	archive_size: u64
+}
+
+impl Limits {
+	pub fn new(d:&SafetyData,archive_size:u64) -> Result<Self> {
+		let size = match &d.max_size {
+			Some(s) => match parse_size(s) {
+				Some(n) => Some(n),
+				None => { return Err(ArchiverError::Usage(msg!("max-size-invalid",s))); }
+			},
+			None => None
+		};
+		if let Some(r) = d.max_ratio.filter(|r| r.is_nan() || *r<=0.0 ) {
+			return Err(ArchiverError::Usage(msg!("max-ratio-invalid",r)));
+		}
+		return Ok(Limits { size, files:d.max_files, ratio:d.max_ratio, archive_size });
+	}
+
+	fn is_empty(&self) -> bool {
+		return self.size.is_none() && self.files.is_none() && self.ratio.is_none();
+	}
+
+	/// 上限を超えていればその内容
+	fn exceeded(&self,bytes:u64,files:u64) -> Option<String> {
+		if let Some(m) = self.size.filter(|m| bytes>*m ) {
+			return Some(msg!("limit-size",bytes,m));
+		}
+		if let Some(r) = self.ratio.filter(|r| self.archive_size>0 && bytes as f64>r*self.archive_size as f64 ) {
+			return Some(msg!("limit-ratio",bytes,r));
+		}
+		if let Some(m) = self.files.filter(|m| files>*m ) {
+			return Some(msg!("limit-files",files,m));
+		}
+		return None;
+	}
+}
+
+/// 展開する前にアーカイブ内のファイルを確認し、安全でないファイルがあるか上限を超える場合は中止する
+/// 確認には bsdtar -tv の出力を使う (mtree 形式は ../ などを取り除いてしまうので使えない)
+pub fn check_archive(input:&String,arc_type:&ArcType,untar:bool,password:&Option<String>,d:&SafetyData,limits:&Limits,tmp:&TempDir) -> Result<()> {
+	if d.allow_unsafe && limits.is_empty() { return Ok(()); }
+	let l = match scan(input,arc_type,untar,password,tmp)? {
+		Some(l) => l,
+		None if d.allow_unsafe => {
+			eprintln!("{}",text("scan-size-only"));
+			return Ok(());
+		},
+		None => {
+			return Err(ArchiverError::Unsafe(msg!("scan-failed")));
+		}
+	};
+	if !d.allow_unsafe {
+		let problems = unsafe_entries(&l);
+		if !problems.is_empty() {
+			eprintln!("{}",text("unsafe-found"));
+			for (e,r) in problems {
+				match &e.link {
+					Some(t) => eprintln!("  {} -> {} ({})",e.path,t,r),
+					None => eprintln!("  {} ({})",e.path,r)
+				}
+			}
+			return Err(ArchiverError::Unsafe(msg!("unsafe-abort")));
+		}
+	}
+	let bytes = l.iter().filter_map(|e| e.size ).sum();
+	let files = l.iter().filter(|e| e.kind!=EntryKind::Dir ).count() as u64;
+	if let Some(m) = limits.exceeded(bytes,files) {
+		return Err(ArchiverError::Unsafe(msg!("limit-abort",m)));
+	}
+	return Ok(());
+}
+
+/// アーカイブ内のファイルの一覧 (bsdtar で読めない種類は各コマンドの一覧を使う)
+fn scan(input:&String,arc_type:&ArcType,untar:bool,password:&Option<String>,tmp:&TempDir) -> Result<Option<Vec<Entry>>> {
+	// bsdtar にはパスワードを安全に渡せないので、パスワードがある場合は各コマンドの一覧を使う
+	let bsdtar = untar || matches!(arc_type,ArcType::Tar|ArcType::Cpio|ArcType::Zip|ArcType::SevenZ|ArcType::Rar|ArcType::Lha|ArcType::Iso);
+	if bsdtar && password.is_none() && which("bsdtar").is_ok() {
+		let cmd = |verbose:bool| {
+			let mut c = Cmd::new("bsdtar",vs(["-t","-f",input]));
+			if verbose { c.args.insert(0,OsString::from("-v")); }
+			c
+		};
+		if let (Ok(names),Ok(lines)) = (piped_output(vec![cmd(false)]),piped_output(vec![cmd(true)])) {
+			if let Some(l) = parse_listing(&names,&lines) { return Ok(Some(l)); }
+		}
+	}
+	// bsdtar が使えない場合は、ネイティブ実装で読み込める種類ならその一覧を使う
+	if native_supports(arc_type,Operation::List) {
+		if let Ok(l) = native_entries(input,arc_type) { return Ok(Some(l)); }
+	}
+	// 圧縮された tar アーカイブは mtree 形式でしか一覧にできないので確認できない
+	if untar { return Ok(None); }
+	return Ok(match arc_type {
+		ArcType::Zip|ArcType::SevenZ|ArcType::Rar|ArcType::Zpaq|ArcType::Wim|ArcType::Aar => list_entries(input,arc_type,password,false,tmp).ok(),
+		_ => None
+	});
+}
+
+/// bsdtar -t の名前の一覧と -tv の詳細な一覧を合わせる
+/// 名前に " -> " が含まれてもリンク先を取り違えないように、リンク先は名前の後ろから取り出す
+fn parse_listing(names:&str,lines:&str) -> Option<Vec<Entry>> {
+	let names = names.lines().collect::<Vec<_>>();
+	let lines = lines.lines().collect::<Vec<_>>();
+	if names.len()!=lines.len() { return None; }
+	let mut l = vec![];
+	for (name,line) in names.into_iter().zip(lines) {
+		let (f,rest) = split_fields(line,8)?;
+		let (kind,mode) = parse_mode_string(f[0])?;
+		let rest = rest.strip_prefix(name)?;
+		let mut e = Entry::new(name.to_string());
+		e.kind = kind;
+		e.mode = Some(mode);
+		// デバイスファイルは大きさの代わりにデバイス番号が表示される
+		e.size = f[4].parse().ok();
+		e.link = match kind {
+			EntryKind::Symlink => Some(rest.strip_prefix(" -> ")?.to_string()),
+			EntryKind::HardLink => Some(rest.strip_prefix(" link to ")?.to_string()),
+			_ => None
+		};
+		l.push(e);
+	}
+	return Some(l);
+}
+
+/// 安全に展開できないファイルとその理由
+/// シンボリックリンクは展開後にどこを指すか分からないフォルダとして扱い、アーカイブ内の順序に関係なく経由するものは全て除く
+/// (a -> . と b -> a/.. のように、それぞれは展開先の中を指していても組み合わせると外に出られるため)
+fn unsafe_entries(l:&[Entry]) -> Vec<(&Entry,&'static str)> {
+	let links = l.iter().filter(|e| e.kind==EntryKind::Symlink ).map(|e| normalize(&e.path) ).collect::<HashSet<_>>();
+	return l.iter().filter_map(|e| unsafe_reason(e,&links).map(|r| (e,r) ) ).collect();
+}
+
+/// 安全に展開できない理由 (問題がなければ None、 links はアーカイブ内のシンボリックリンク)
+fn unsafe_reason(e:&Entry,links:&HashSet<String>) -> Option<&'static str> {
+	if is_absolute(&e.path) { return Some(text("unsafe.absolute")); }
+	if has_parent(&e.path) { return Some(text("unsafe.parent")); }
+	if through_link("",&e.path,links) { return Some(text("unsafe.through-link")); }
+	return match (e.kind,&e.link) {
+		(EntryKind::Other,_) => Some(text("unsafe.special")),
+		(EntryKind::Symlink,None)|(EntryKind::HardLink,None) => Some(text("unsafe.unknown-link")),
+		// シンボリックリンクのリンク先はリンクのあるフォルダからの相対パス
+		(EntryKind::Symlink,Some(t)) if is_absolute(t) || escapes(parent(&e.path),t) => Some(text("unsafe.symlink-out")),
+		(EntryKind::Symlink,Some(t)) if through_link(parent(&e.path),t,links) => Some(text("unsafe.through-link")),
+		// ハードリンクのリンク先はアーカイブのルートからのパス
+		(EntryKind::HardLink,Some(t)) if is_absolute(t) || has_parent(t) => Some(text("unsafe.hardlink-out")),
+		(EntryKind::HardLink,Some(t)) if through_link("",t,links) => Some(text("unsafe.through-link")),
+		_ => None
+	};
+}
+
+/// パスの要素 (zip などで使われる \ も区切りとして扱う)
+fn components(p:&str) -> impl Iterator<Item=&str> {
+	return p.split(['/','\\']).filter(|c| !c.is_empty() && *c!="." );
+}
+
+fn is_absolute(p:&str) -> bool {
+	let b = p.as_bytes();
+	return p.starts_with(['/','\\']) || (b.len()>=2 && b[0].is_ascii_alphabetic() && b[1]==b':');
+}
+
+fn has_parent(p:&str) -> bool {
+	return components(p).any(|c| c==".." );
+}
+
+fn parent(p:&str) -> &str {
+	return p.trim_end_matches(['/','\\']).rsplit_once(['/','\\']).map(|(d,_)| d ).unwrap_or("");
+}
+
+/// / で区切り直したパス (シンボリックリンクの一覧と比べるため)
+fn normalize(p:&str) -> String {
+	return components(p).collect::<Vec<_>>().join("/");
+}
+
+/// フォルダ dir から相対パス target を辿る途中でシンボリックリンクを経由するか (最後の要素がリンクであるのは構わない)
+fn through_link(dir:&str,target:&str,links:&HashSet<String>) -> bool {
+	let mut path = components(dir).collect::<Vec<_>>();
+	let mut l = components(target).peekable();
+	while let Some(c) = l.next() {
+		if c==".." { path.pop(); }
+		else { path.push(c); }
+		if l.peek().is_some() && links.contains(&path.join("/")) { return true; }
+	}
+	return false;
+}
+
+/// フォルダ dir から相対パス target を辿ると展開先の外に出るか
+fn escapes(dir:&str,target:&str) -> bool {
+	let mut depth = components(dir).count();
+	for c in components(target) {
+		if c==".." {
+			if depth==0 { return true; }
+			depth -= 1;
+		}
+		else { depth += 1; }
+	}
+	return false;
+}
+
+thread_local! {
+	/// このスレッドの展開を監視している Watchdog が上限を超えたら立てるフラグ
+	static ABORT:RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+}
+
+/// このスレッドの展開が上限を超えて中止されたか
+fn aborted() -> bool {
+	return ABORT.with(|a| a.borrow().as_ref().is_some_and(|a| a.load(Ordering::Relaxed) ) );
+}
+
+/// 中止されたら読み込みを失敗させる (ネイティブ実装はプロセス内で展開するので、コマンドの代わりにこれで止める)
+pub struct Abortable<R>(pub R);
+impl<R:Read> Read for Abortable<R> {
+	fn read(&mut self,b:&mut [u8]) -> io::Result<usize> {
+		if aborted() { return Err(io::Error::other(text("extract-aborted"))); }
+		return self.0.read(b);
+	}
+}
+impl<R:Seek> Seek for Abortable<R> {
+	fn seek(&mut self,p:SeekFrom) -> io::Result<u64> {
+		return self.0.seek(p);
+	}
+}
+
+/// 展開中に展開先の大きさとファイルの数を調べ、上限を超えたら実行中のコマンドを終了させて中止する
+/// 一覧の大きさは偽装できるので、展開前の確認を通っても監視する
+pub struct Watchdog {
+	done: Arc<AtomicBool>,
+	abort: Arc<AtomicBool>,
+	/// 上限を超えた場合はその内容
+	exceeded: Arc<Mutex<Option<String>>>,
+	handle: JoinHandle<()>,
+	dst: PathBuf,
+	/// 圧縮ファイルの解凍先 (中止したら削除する)
+	single: bool
+}
+
+/// 展開先 dst の監視を開始する (上限の指定がなければ None)
+/// 展開するスレッドで呼び出し、同じスレッドで stop する
+pub fn watch(dst:PathBuf,limits:Limits) -> Option<Watchdog> {
+	if limits.is_empty() { return None; }
+	// 展開先に元からあるものは数えない
+	let (base_bytes,base_files) = usage(&dst);
+	let single = !dst.is_dir();
+	let done = Arc::new(AtomicBool::new(false));
+	let abort = Arc::new(AtomicBool::new(false));
+	let exceeded = Arc::new(Mutex::new(None));
+	ABORT.with(|a| *a.borrow_mut() = Some(abort.clone()) );
+	let owner = thread::current().id();
+	let (d,a,x,p) = (done.clone(),abort.clone(),exceeded.clone(),dst.clone());
+	let handle = thread::spawn(move || {
+		loop {
+			let stop = d.load(Ordering::Relaxed);
+			let (bytes,files) = usage(&p);
+			if let Some(m) = limits.exceeded(bytes.saturating_sub(base_bytes),files.saturating_sub(base_files)) {
+				if let Ok(mut x) = x.lock() { *x = Some(msg!("limit-abort",m)); }
+				// この展開で実行中のコマンドだけを終了させる
+				a.store(true,Ordering::Relaxed);
+				kill_running(owner);
+				break;
+			}
+			if stop { break; }
+			thread::sleep(Duration::from_millis(200));
+		}
+	});
+	return Some(Watchdog { done, abort, exceeded, handle, dst, single });
+}
+
+impl Watchdog {
+	/// 監視を終了する (最後にもう一度確認し、上限を超えていれば解凍したファイルを削除してエラーにする)
+	pub fn stop(self) -> Result<()> {
+		self.done.store(true,Ordering::Relaxed);
+		let _ = self.handle.join();
+		ABORT.with(|a| {
+			let mut a = a.borrow_mut();
+			if a.as_ref().is_some_and(|a| Arc::ptr_eq(a,&self.abort) ) { *a = None; }
+		});
+		return match self.exceeded.lock().ok().and_then(|mut x| x.take() ) {
+			Some(m) => {
+				if self.single { let _ = fs::remove_file(&self.dst); }
+				Err(ArchiverError::Unsafe(m))
+			},
+			None => Ok(())
+		};
+	}
+}
+
+/// ファイルの大きさの合計とフォルダ以外の数
+fn usage(p:&Path) -> (u64,u64) {
+	let m = match fs::symlink_metadata(p) {
+		Ok(m) => m,
+		Err(_) => { return (0,0); }
+	};
+	if m.is_dir() {
+		return match fs::read_dir(p) {
+			Ok(rd) => rd.filter_map(|e| e.ok() ).map(|e| usage(&e.path()) ).fold((0,0),|a,b| (a.0+b.0,a.1+b.1) ),
+			Err(_) => (0,0)
+		};
+	}
+	return (if m.is_file() { m.len() } else { 0 },1);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn link(path:&str,kind:EntryKind,target:&str) -> Entry {
+		let mut e = Entry::new(path.to_string());
+		e.kind = kind;
+		e.link = Some(target.to_string());
+		return e;
+	}
+	fn file(path:&str) -> Entry {
+		return Entry::new(path.to_string());
+	}
+	fn reasons(l:&[Entry]) -> Vec<(String,&'static str)> {
+		return unsafe_entries(l).into_iter().map(|(e,r)| (e.path.clone(),r) ).collect();
+	}
+
+	#[test]
+	fn safe_entries() {
+		let l = [file("d/"),file("d/a.txt"),link("d/l",EntryKind::Symlink,"a.txt"),link("d/up",EntryKind::Symlink,"../d/a.txt"),link("h",EntryKind::HardLink,"d/a.txt")];
+		assert!(reasons(&l).is_empty());
+	}
+
+	#[test]
+	fn unsafe_paths() {
+		let l = [file("/etc/passwd"),file("a/../../x"),file("C:\\x"),link("s",EntryKind::Symlink,"../x"),link("t",EntryKind::Symlink,"/etc"),link("h",EntryKind::HardLink,"../x")];
+		let r = reasons(&l);
+		assert_eq!(r.iter().map(|(p,_)| p.as_str() ).collect::<Vec<_>>(),["/etc/passwd","a/../../x","C:\\x","s","t","h"]);
+	}
+
+	#[test]
+	fn chained_symlinks() {
+		// それぞれは展開先の中を指しているが、 b は a を経由して展開先の親を指す
+		let l = [link("a",EntryKind::Symlink,"."),link("b",EntryKind::Symlink,"a/..")];
+		assert_eq!(reasons(&l),[(String::from("b"),text("unsafe.through-link"))]);
+		// 順序が逆でも展開後は同じになる
+		let l = [link("b",EntryKind::Symlink,"a/.."),link("a",EntryKind::Symlink,".")];
+		assert_eq!(reasons(&l),[(String::from("b"),text("unsafe.through-link"))]);
+		// リンクを指すだけのリンクは構わない
+		let l = [link("a",EntryKind::Symlink,"."),link("c",EntryKind::Symlink,"./a")];
+		assert!(reasons(&l).is_empty());
+	}
+
+	#[test]
+	fn through_symlink() {
+		let l = [link("a",EntryKind::Symlink,"d"),file("a/x"),link("h",EntryKind::HardLink,"a/x"),link("sub/b",EntryKind::Symlink,"../a/y")];
+		let r = reasons(&l);
+		assert_eq!(r.iter().map(|(p,_)| p.as_str() ).collect::<Vec<_>>(),["a/x","h","sub/b"]);
+	}
+
+	#[test]
+	fn watchdog_removes_oversized_file() {
+		let tmp = tempdir().unwrap();
+		let dst = tmp.path().join("out");
+		let d = SafetyData { max_size:Some(String::from("10")), ..SafetyData::default() };
+		let w = watch(dst.clone(),Limits::new(&d,0).unwrap()).unwrap();
+		fs::write(&dst,[0u8;100]).unwrap();
+		thread::sleep(Duration::from_millis(500));
+		// 上限を超えたらこのスレッドの読み込みは失敗する
+		assert!(Abortable(&[0u8;4][..]).read(&mut [0u8;4]).is_err());
+		assert!(matches!(w.stop(),Err(ArchiverError::Unsafe(_))));
+		assert!(!dst.exists());
+		assert!(!aborted());
+	}
+}
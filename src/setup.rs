@@ -0,0 +1,165 @@
+use which::which;
+use crate::library::*;
+use crate::args::*;
+use crate::backend::*;
+use crate::error::*;
+use crate::native::native_supports;
+use crate::message::text;
+
+/// 操作の種類 (Operation の順、表示名は message.rs の ID)
+const OPERATIONS:[(&str,Operation);7] = [
+	("setup.create",Operation::Create),
+	("setup.list",Operation::List),
+	("setup.extract",Operation::Extract),
+	("setup.append",Operation::Append),
+	("setup.remove",Operation::Remove),
+	("setup.rename",Operation::Rename),
+	("setup.test",Operation::Test)
+];
+
+pub fn setup(d:SetupData) -> Result<()> {
+
+	// 種類ごとに各操作で必要なコマンドを確認して表を出力
+	let mut missing:Vec<&'static str> = vec![];
+	let pad = |s:&str,w:usize| format!("{}{}",s," ".repeat(w.saturating_sub(display_width(s))));
+	println!("{}{}",pad(text("setup.type"),12),OPERATIONS.map(|(o,_)| pad(text(o),8) ).join(""));
+	for at in ArcType::value_variants() {
+		let name = at.to_possible_value().unwrap().get_name();
+		let cols = izip!(required_tools(at),OPERATIONS).map(|(t,(_,op))| {
+			match (t,native_supports(at,op)) {
+				(None,true) => "△",
+				(None,false) => "-",
+				(Some(l),native) => {
+					let absent = l.into_iter().filter(|p| which(p).is_err() ).collect::<Vec<_>>();
+					if absent.is_empty() { "○" }
+					else {
+						for p in absent {
+							if !missing.contains(&p) { missing.push(p); }
+						}
+						if native { "△" } else { "×" }
+					}
+				}
+			}
+		}).map(|m| pad(m,8) ).collect::<Vec<_>>().join("");
+		println!("{}{}",pad(name,12),cols);
+	}
+	println!("{}",text("setup.legend"));
+
+	if missing.is_empty() {
+		println!("{}",text("setup.all-installed"));
+		return Ok(());
+	}
+	println!("{}",msg!("setup.missing",missing.join(" ")));
+
+	// パッケージマネージャを検出してインストールコマンドを用意
+	let pm = match package_manager() {
+		Some(pm) => pm,
+		None => {
+			eprintln!("{}",text("setup.no-package-manager"));
+			return Ok(());
+		}
+	};
+	let mut packages:Vec<&'static str> = vec![];
+	let mut unavailable:Vec<&'static str> = vec![];
+	for p in missing.iter() {
+		match package_name(p,&pm) {
+			Some(n) => { if !packages.contains(&n) { packages.push(n); } },
+			None => { unavailable.push(p); }
+		}
+	}
+	if !unavailable.is_empty() {
+		eprintln!("{}",msg!("setup.unavailable",unavailable.join(" ")));
+	}
+	if packages.is_empty() { return Ok(()); }
+
+	let c = install_cmd(&pm,&packages);
+	let command = format!(
+		"{} {}",
+		c.prog.to_string_lossy(),
+		c.args.iter().map(|a| a.to_string_lossy() ).collect::<Vec<_>>().join(" ")
+	);
+	println!("{}",msg!("setup.install-command",command));
+
+	// --yes が指定されている場合のみ実行する
+	if d.yes {
+		several_cmd(vec![c]).map_err(|e| e.context(text("setup.install-failed")) )?;
+	}
+	else {
+		println!("{}",text("setup.need-yes"));
+	}
+	return Ok(());
+
+}
+
+enum PackageManager {
+	Apt,
+	Dnf,
+	Pacman,
+	Brew
+}
+
+/// 使用可能なパッケージマネージャを検出
+fn package_manager() -> Option<PackageManager> {
+	if which("brew").is_ok()    { return Some(PackageManager::Brew);   }
+	if which("apt-get").is_ok() { return Some(PackageManager::Apt);    }
+	if which("dnf").is_ok()     { return Some(PackageManager::Dnf);    }
+	if which("pacman").is_ok()  { return Some(PackageManager::Pacman); }
+	return None;
+}
+
+/// コマンドを含むパッケージ名 (None はパッケージマネージャから入手できないもの)
+fn package_name(prog:&str,pm:&PackageManager) -> Option<&'static str> {
+	use PackageManager::*;
+	return match (prog,pm) {
+		("zip",_)|("zipnote",_) => Some("zip"),
+		("zipinfo",_)|("unzip",_) => Some("unzip"),
+		("7z",Apt) => Some("p7zip-full"),
+		("7z",Dnf) => Some("p7zip-plugins"),
+		("7z",_)   => Some("p7zip"),
+		("rar",Apt) => Some("rar"),
+		("rar",_)   => None,
+		("bsdtar",Apt)    => Some("libarchive-tools"),
+		("bsdtar",Dnf)    => Some("bsdtar"),
+		("bsdtar",Pacman) => Some("libarchive"),
+		("bsdtar",Brew)   => Some("libarchive"),
+		("cpio",_) => Some("cpio"),
+		("wimcapture",Apt)|("wimdir",Apt)|("wimapply",Apt)|("wiminfo",Apt) => Some("wimtools"),
+		("wimcapture",Dnf)|("wimdir",Dnf)|("wimapply",Dnf)|("wiminfo",Dnf) => Some("wimlib-utils"),
+		("wimcapture",_)|("wimdir",_)|("wimapply",_)|("wiminfo",_) => Some("wimlib"),
+		("lha",_) => Some("lhasa"),
+		("zpaq",Apt)|("zpaq",Dnf) => Some("zpaq"),
+		("zpaq",_) => None,
+		("compress",Brew)|("uncompress",Brew) => None,
+		("compress",_)|("uncompress",_) => Some("ncompress"),
+		("gzip",_)  => Some("gzip"),
+		("bzip2",_) => Some("bzip2"),
+		("xz",Apt)|("lzma",Apt) => Some("xz-utils"),
+		("xz",_)|("lzma",_) => Some("xz"),
+		("lzip",_)   => Some("lzip"),
+		("lz4",_)    => Some("lz4"),
+		("lzop",_)   => Some("lzop"),
+		("lrzip",_)  => Some("lrzip"),
+		("rzip",Brew)|("rzip",Pacman) => None,
+		("rzip",_)   => Some("rzip"),
+		("zstd",_)   => Some("zstd"),
+		("brotli",_) => Some("brotli"),
+		// aa, hdiutil は macOS に付属している
+		_ => None
+	};
+}
+
+/// パッケージをインストールするコマンド
+fn install_cmd(pm:&PackageManager,packages:&Vec<&'static str>) -> Cmd {
+	let mut c = match pm {
+		PackageManager::Apt    => Cmd::new("sudo",vs(["apt-get","install","-y"])),
+		PackageManager::Dnf    => Cmd::new("sudo",vs(["dnf","install","-y"])),
+		PackageManager::Pacman => Cmd::new("sudo",vs(["pacman","-S","--needed","--noconfirm"])),
+		PackageManager::Brew   => Cmd::new("brew",vs(["install"]))
+	};
+	// 管理者権限で実行している場合や sudo がない場合はそのまま実行する
+	if c.prog=="sudo" && (which("sudo").is_err() || std::env::var("USER").map(|u| u=="root").unwrap_or(false)) {
+		c.prog = c.args.remove(0);
+	}
+	c.args.extend(vs(packages.iter()));
+	return c;
+}
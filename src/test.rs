@@ -0,0 +1,145 @@
+use crate::library::*;
+use crate::args::*;
+use crate::error::*;
+use crate::backend::*;
+use crate::password::*;
+use crate::volume::join_volumes;
+use crate::extract::{contains_tar,decompress_stdout_cmd};
+
+pub fn test(mut d:TestData) -> Result<()> {
+
+	let tmp = work_dir()?;
+	let (input,name) = match is_stdio(&d.input) {
+		// 標準入力は作業ディレクトリに保存してから読み込む
+		true => (spool_stdin(&tmp)?,d.input.clone()),
+		false => {
+			// アーカイブが存在することを確認
+			if !Path::new(&d.input).is_file() {
+				return Err(ArchiverError::NotFound(msg!("file-not-found",d.input)));
+			}
+			// 分割されたアーカイブは全てのボリュームを1つにまとめて読み込む
+			join_volumes(&d.input,&tmp)?
+		}
+	};
+	d.input = input;
+
+	// アーカイブの種類を判定
+	let arc_type = match d.arc_type {
+		Some(t) => t,
+		None => {
+			match detect_type(&d.input,true) {
+				Some(t) => t,
+				None => {
+					return Err(ArchiverError::Unsupported(msg!("unknown-type")));
+				}
+			}
+		}
+	};
+
+	// 暗号化されている場合はパスワードを用意する
+	load_password(&mut d.pw)?;
+	ask_password(&mut d.pw,&arc_type,&d.input)?;
+
+	// 実装を選択して実行 (検査に失敗したものは破損として扱う)
+	let backend = select_backend(&arc_type,&password_backend(&d.pw,&arc_type,&d.backend),Operation::Test)?;
+	backend.test(&d,&arc_type).map_err(|e| e.corrupt(&msg!("test-problem",name)) )?;
+	println!("{}",msg!("test-ok",name));
+	close_dir(tmp)?;
+	return Ok(());
+
+}
+
+/// 外部コマンドによる検査
+pub fn test_external(d:&TestData,arc_type:&ArcType) -> Result<()> {
+
+	let null = String::from("/dev/null");
+
+	// 圧縮ファイルは圧縮コマンドで検査し、中身が tar アーカイブであればその構造も確認する
+	if is_compress(arc_type) {
+		let mut c = match test_compressed_cmd(arc_type,&d.input) {
+			Some(c) => c,
+			None => {
+				return Err(ArchiverError::Unsupported(msg!("unsupported-test")));
+			}
+		};
+		c.inherit_output = true;
+		several_cmd(vec![c])?;
+		if !contains_tar(arc_type,&d.input) { return Ok(()); }
+		let dc = match decompress_stdout_cmd(arc_type,&d.input) {
+			Some(c) => c,
+			None => { return Ok(()); }
+		};
+		let mut t = Cmd::new("bsdtar",vs(["-t","-f","-"]));
+		t.inherit_output = true;
+		if !d.verbose { t.stdout = Some(null); }
+		return piped_cmd(vec![dc,t]);
+	}
+
+	// コマンドを用意
+	let tmp = work_dir()?;
+	let mut c = match arc_type {
+		// unzip にはパスワードを安全に渡せないので 7z で検査する
+		ArcType::Zip if d.pw.password.is_some() => Cmd::new("7z",vs(["t",&d.input])),
+		ArcType::Zip    => {
+			let mut c = Cmd::new("unzip",vs(["-t"]));
+			if !d.verbose { c.args.push(OsString::from("-q")); }
+			c.args.push(OsString::from(&d.input));
+			c
+		},
+		ArcType::SevenZ => Cmd::new("7z"     ,vs(["t",&d.input])),
+		ArcType::Rar    => Cmd::new("rar"    ,vs(["t",&d.input])),
+		ArcType::Wim    => Cmd::new("wiminfo",vs([&d.input,"--check"])),
+		ArcType::Zpaq   => Cmd::new("zpaq"   ,vs(["l",&d.input])),
+		ArcType::Dmg    => Cmd::new("hdiutil",vs(["verify",&d.input])),
+		// 全てのデータを読み込んで捨てる
+		ArcType::Tar|ArcType::Cpio|ArcType::Lha|ArcType::Iso => {
+			let mut c = Cmd::new("bsdtar",vs(["-x","-O","-f",&d.input]));
+			if d.verbose { c.args.insert(0,OsString::from("-v")); }
+			c.stdout = Some(null);
+			c
+		},
+		// Apple Archive には検査の機能がないので作業ディレクトリに展開してみる
+		ArcType::Aar    => {
+			let mut c = Cmd::new("aa",vs(["extract","-i",&d.input,"-d"]));
+			c.args.push(tmp.join_str("extract"));
+			if d.verbose { c.args.push(OsString::from("-v")); }
+			c
+		},
+		_ => {
+			return Err(ArchiverError::Unsupported(msg!("unsupported-test")));
+		}
+	};
+	c.inherit_output = true;
+	if let Some(pw) = &d.pw.password { add_password_args(&mut c,pw,&tmp,false)?; }
+
+	// コマンドを実行
+	let r = several_cmd(vec![c]);
+	close_dir(tmp)?;
+	return r;
+
+}
+
+/// 圧縮ファイルを検査するコマンド (None は非対応)
+fn test_compressed_cmd(arc_type:&ArcType,file:&String) -> Option<Cmd> {
+	let mut c = match arc_type {
+		ArcType::Gzip   => Cmd::new("gzip"  ,vs(["-t"])),
+		ArcType::Bzip2  => Cmd::new("bzip2" ,vs(["-t"])),
+		ArcType::Xz     => Cmd::new("xz"    ,vs(["-t"])),
+		ArcType::Lzip   => Cmd::new("lzip"  ,vs(["-t"])),
+		ArcType::Lzma   => Cmd::new("lzma"  ,vs(["-t"])),
+		ArcType::Lz4    => Cmd::new("lz4"   ,vs(["-t"])),
+		ArcType::Lzop   => Cmd::new("lzop"  ,vs(["-t"])),
+		ArcType::Lrzip  => Cmd::new("lrzip" ,vs(["-t"])),
+		ArcType::Zstd   => Cmd::new("zstd"  ,vs(["-t","-q"])),
+		ArcType::Brotli => Cmd::new("brotli",vs(["-t"])),
+		// 検査の機能がないので解凍した結果を捨てる
+		ArcType::Compress => {
+			let mut c = decompress_stdout_cmd(arc_type,file)?;
+			c.stdout = Some(String::from("/dev/null"));
+			return Some(c);
+		},
+		_ => { return None; }
+	};
+	c.args.push(OsString::from(file));
+	return Some(c);
+}
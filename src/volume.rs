@@ -0,0 +1,263 @@
+use std::fs::{self,File};
+use std::io::{self,Read,Write,Seek,SeekFrom,BufWriter};
+use crate::library::*;
+use crate::args::*;
+use crate::error::*;
+use crate::message::text;
+
+/// 分割されたアーカイブの種類
+enum VolumeSet {
+	/// 単純に分割したもの (base.001, base.part001 など)
+	Numbered { base:String, prefix:&'static str, width:usize },
+	/// zip -s で分割したもの (base.z01, ..., base.zip)
+	Zip { base:String },
+	/// rar -v で分割したもの (base.part1.rar, ...)
+	Rar { base:String, width:usize }
+}
+
+/// 分割の大きさの指定をバイト数に変換
+pub fn split_size(s:&String) -> Result<u64> {
+	return match parse_size(s) {
+		Some(n) if n>0 => Ok(n),
+		_ => Err(ArchiverError::Usage(msg!("split-size-invalid",s)))
+	};
+}
+
+/// 作成するコマンドで分割する種類 (rar -v と 7z -v)
+pub fn split_on_create(at:&ArcType) -> bool {
+	return matches!(at,ArcType::Rar|ArcType::SevenZ);
+}
+
+/// 作成したアーカイブ whole を分割して output を元にした名前で保存する
+/// zip は zip -s 、それ以外は .part001 のように単純に分割する
+pub fn split_archive(at:&ArcType,whole:&String,output:&String,size:u64) -> Result<()> {
+	match at {
+		ArcType::Zip => {
+			// zip -s は 64KB 未満に分割できない
+			let mut c = Cmd::new("zip",vs(["-q","-s",&format!("{}k",size/1024),whole,"--out",output]));
+			c.inherit_output = true;
+			several_cmd(vec![c]).map_err(|e| e.context(text("split-failed")) )?;
+		},
+		_ => {
+			let r = split_file(whole,size,|i| format!("{}.part{:03}",output,i) );
+			if let Err(e) = r { return Err(ArchiverError::Failed(format!("{}: {}",text("split-failed"),e))); }
+		}
+	}
+	return Ok(());
+}
+
+fn split_file<F:Fn(usize) -> String>(src:&String,size:u64,name:F) -> io::Result<()> {
+	let mut r = File::open(src)?;
+	let total = r.metadata()?.len();
+	let mut i = 1;
+	loop {
+		let mut w = BufWriter::new(File::create(name(i))?);
+		io::copy(&mut (&mut r).take(size),&mut w)?;
+		w.flush()?;
+		if (i as u64)*size>=total { break; }
+		i += 1;
+	}
+	return Ok(());
+}
+
+/// rar -v や 7z -v で作業ディレクトリに作成したボリューム (archive.part1.rar や archive.7z.001 など) を output を元にした名前で保存する
+pub fn move_volumes(tmp:&TempDir,at:&ArcType,output:&str) -> Result<()> {
+	let (stem,head) = match at {
+		ArcType::Rar => (output.strip_suffix(".rar").unwrap_or(output),"archive"),
+		_ => (output,"archive.7z")
+	};
+	let mut moved = false;
+	if let Ok(rd) = fs::read_dir(tmp.path()) {
+		for e in rd.filter_map(|e| e.ok() ) {
+			let name = e.file_name().to_string_lossy().to_string();
+			let rest = match (at,name.strip_prefix(head)) {
+				(ArcType::Rar,Some(r)) if r.ends_with(".rar") => r.to_string(),
+				(ArcType::SevenZ,Some(r)) if r.len()>1 && r[1..].bytes().all(|c| c.is_ascii_digit() ) => r.to_string(),
+				_ => { continue; }
+			};
+			let dst = format!("{}{}",stem,rest);
+			if fs::rename(e.path(),&dst).is_err() {
+				if let Err(e) = fs::copy(e.path(),&dst) {
+					return Err(ArchiverError::Failed(msg!("save-failed",e)));
+				}
+			}
+			moved = true;
+		}
+	}
+	if !moved { return Err(ArchiverError::Failed(msg!("not-created"))); }
+	return Ok(());
+}
+
+/// 分割されたアーカイブの最初のボリュームであれば、全てのボリュームが揃っていることを確認して1つのアーカイブとして読める形にする
+/// (読み込むファイル,分割の番号を除いた名前) を返し、分割されていなければ input をそのまま返す
+pub fn join_volumes(input:&String,tmp:&TempDir) -> Result<(String,String)> {
+	let set = match volume_set(input) {
+		Some(s) => s,
+		None => { return Ok((input.clone(),input.clone())); }
+	};
+	let base = match &set {
+		VolumeSet::Numbered{base,..}|VolumeSet::Zip{base}|VolumeSet::Rar{base,..} => base.clone()
+	};
+	let files = volume_files(&set)?;
+	let joined = tmp.path().join(Path::new(&base).file_name().unwrap_or_default()).to_string_lossy().to_string();
+	match set {
+		VolumeSet::Numbered{..} => {
+			let r = (|| -> io::Result<()> {
+				let mut w = BufWriter::new(File::create(&joined)?);
+				for f in files.iter() { io::copy(&mut File::open(f)?,&mut w)?; }
+				return w.flush();
+			})();
+			if let Err(e) = r { return Err(ArchiverError::Failed(format!("{}: {}",text("join-failed"),e))); }
+		},
+		VolumeSet::Zip{..} => {
+			// ボリュームが読み込めない場合に入力を求めないようにする
+			let mut c = Cmd::new("zip",vs(["-q","-s","0",files.last().unwrap(),"--out",&joined]));
+			c.stdin = Some(String::new());
+			c.inherit_output = true;
+			several_cmd(vec![c]).map_err(|e| e.corrupt(text("join-failed")) )?;
+		},
+		// rar は最初のボリュームを指定すれば残りも読み込む
+		VolumeSet::Rar{..} => { return Ok((files[0].clone(),base)); }
+	}
+	return Ok((joined,base));
+}
+
+/// ファイル名から分割されたアーカイブかを判定する
+fn volume_set(input:&String) -> Option<VolumeSet> {
+	let lower = input.to_ascii_lowercase();
+	let digits = |s:&str| !s.is_empty() && s.bytes().all(|c| c.is_ascii_digit() );
+	// base.part1.rar
+	if let Some(s) = lower.strip_suffix(".rar") {
+		if let Some(i) = s.rfind(".part") {
+			let n = &s[i+5..];
+			if digits(n) { return Some(VolumeSet::Rar { base:format!("{}.rar",&input[..i]), width:n.len() }); }
+		}
+		return None;
+	}
+	// base.z01 (最後のボリュームの base.zip を指定した場合も含む)
+	if let Some(i) = lower.rfind(".z") {
+		if digits(&lower[i+2..]) && lower.len()-i==4 {
+			return Some(VolumeSet::Zip { base:format!("{}.zip",&input[..i]) });
+		}
+	}
+	if lower.ends_with(".zip") && zip_disk_number(input).unwrap_or(0)>0 {
+		return Some(VolumeSet::Zip { base:input.clone() });
+	}
+	// base.part001, base.001 (backup.2024 や log.001 のようなファイルと区別するため、最初のボリュームがあり、まとめたものの種類が判定できるものに限る)
+	let numbered = |i:usize,prefix:&'static str| {
+		let n = &lower[i+1+prefix.len()..];
+		if !digits(n) || (prefix.is_empty() && n.len()<3) { return None; }
+		let base = input[..i].to_string();
+		let first = format!("{}.{}{:0w$}",base,prefix,1,w=n.len());
+		if !Path::new(&first).is_file() { return None; }
+		sniff_type(&first).or_else(|| guess_type(&base,false) )?;
+		return Some(VolumeSet::Numbered { base, prefix, width:n.len() });
+	};
+	if let Some(s) = lower.rfind(".part").and_then(|i| numbered(i,"part") ) { return Some(s); }
+	return lower.rfind('.').and_then(|i| numbered(i,"") );
+}
+
+/// 全てのボリュームを順に並べる (欠けているボリュームがある場合はその名前を表示してエラーにする)
+fn volume_files(set:&VolumeSet) -> Result<Vec<String>> {
+	// 番号の前後の文字列と桁数
+	let (stem,prefix,suffix,width) = match set {
+		VolumeSet::Numbered{base,prefix,width} => (base.as_str(),format!(".{}",prefix),"",*width),
+		VolumeSet::Zip{base} => (&base[..base.len()-4],String::from(".z"),"",2),
+		VolumeSet::Rar{base,width} => (&base[..base.len()-4],String::from(".part"),".rar",*width)
+	};
+	let name = |i:usize| format!("{}{}{:0w$}{}",stem,prefix,i,suffix,w=width);
+
+	// ディレクトリ内で最大の番号を探し、そこまでの番号が全て揃っていることを確認する
+	let path = Path::new(stem);
+	let dir = match path.parent() {
+		Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+		_ => PathBuf::from(".")
+	};
+	let head = format!("{}{}",path.file_name().unwrap_or_default().to_string_lossy(),prefix);
+	let max = fs::read_dir(&dir).map(|rd| {
+		rd.filter_map(|e| e.ok() ).filter_map(|e| {
+			let n = e.file_name().to_string_lossy().to_string();
+			n.strip_prefix(&head)?.strip_suffix(suffix)?.parse::<usize>().ok()
+		}).max().unwrap_or(1)
+	}).unwrap_or(1);
+	let mut l = (1..=max).map(name).collect::<Vec<_>>();
+	if let VolumeSet::Zip{base} = set {
+		// 最後のボリュームに記録されているディスク番号から、欠けている末尾のボリュームも確認する
+		if let Some(n) = zip_disk_number(base) {
+			l.extend((max+1..=n as usize).map(name));
+		}
+		l.push(base.clone());
+	}
+	if let Some(f) = l.iter().find(|f| !Path::new(f).is_file() ) {
+		return Err(ArchiverError::NotFound(msg!("volume-not-found",f)));
+	}
+	return Ok(l);
+}
+
+/// zip の終端レコードにあるディスク番号 (最後のボリュームの番号で、 .zNN の数と一致する)
+fn zip_disk_number(file:&String) -> Option<u16> {
+	let mut f = File::open(file).ok()?;
+	let len = f.metadata().ok()?.len();
+	// 終端レコードは末尾の 22 バイトにコメント (最大 65535 バイト) を加えた範囲にある
+	let start = len.saturating_sub(22+65535);
+	f.seek(SeekFrom::Start(start)).ok()?;
+	let mut b = vec![];
+	f.read_to_end(&mut b).ok()?;
+	let i = (0..b.len().saturating_sub(21)).rev().find(|&i| b[i..i+4]==[0x50,0x4b,0x05,0x06] )?;
+	return Some(u16::from_le_bytes([b[i+4],b[i+5]]));
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn write(dir:&TempDir,name:&str,data:&[u8]) -> String {
+		let p = dir.path().join(name).to_string_lossy().to_string();
+		fs::write(&p,data).unwrap();
+		return p;
+	}
+
+	fn numbered(s:Option<VolumeSet>) -> Option<(String,&'static str)> {
+		return match s {
+			Some(VolumeSet::Numbered{base,prefix,..}) => Some((base,prefix)),
+			_ => None
+		};
+	}
+
+	#[test]
+	fn plain_files_with_digits() {
+		let dir = tempdir().unwrap();
+		assert!(volume_set(&write(&dir,"backup.2024",b"text")).is_none());
+		assert!(volume_set(&write(&dir,"log.001",b"text")).is_none());
+		assert!(volume_set(&write(&dir,"photo.12",b"text")).is_none());
+	}
+
+	#[test]
+	fn numbered_volumes() {
+		let dir = tempdir().unwrap();
+		let first = write(&dir,"a.tar.gz.001",&[0x1f,0x8b,0x08,0x00]);
+		let second = write(&dir,"a.tar.gz.002",b"rest");
+		let base = dir.path().join("a.tar.gz").to_string_lossy().to_string();
+		assert_eq!(numbered(volume_set(&first)),Some((base.clone(),"")));
+		assert_eq!(numbered(volume_set(&second)),Some((base,"")));
+		// 内容から種類が分かれば拡張子がなくてもよい
+		let part = write(&dir,"data.part001",b"PK\x03\x04");
+		assert_eq!(numbered(volume_set(&part)),Some((dir.path().join("data").to_string_lossy().to_string(),"part")));
+	}
+
+	#[test]
+	fn missing_first_volume() {
+		let dir = tempdir().unwrap();
+		assert!(volume_set(&write(&dir,"a.7z.002",b"rest")).is_none());
+	}
+
+	#[test]
+	fn rar_and_zip_volumes() {
+		let dir = tempdir().unwrap();
+		let rar = write(&dir,"a.part2.rar",b"");
+		assert!(matches!(volume_set(&rar),Some(VolumeSet::Rar{width:1,..})));
+		let z = write(&dir,"a.z01",b"");
+		assert!(matches!(volume_set(&z),Some(VolumeSet::Zip{..})));
+		assert!(volume_set(&write(&dir,"a.zip",b"")).is_none());
+	}
+}